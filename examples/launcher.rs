@@ -0,0 +1,112 @@
+use vulkan_samples::{
+        app::{
+            AppConfig,
+            UpdateFrequency,
+        },
+        cli,
+        samples::{
+            self,
+            SampleRegistry,
+        },
+};
+
+use std::{
+        error::Error,
+        process::Command,
+};
+
+const USAGE: &str = "\
+Usage:
+    launcher [list]                 List the available samples
+    launcher run <sample> [options] Run a sample, PageDown / PageUp switch to the next / previous one
+    launcher all [options]          Run every sample headless in turn (100 frames unless --frames is given),
+                                    output file names get the sample's name appended
+";
+
+/// Frames each sample renders when running them all
+const DEFAULT_FRAMES: &str = "100";
+
+#[cfg_attr(target_os = "android", ndk_glue::main(backtrace))]
+fn main() {
+    let registry = SampleRegistry::with_builtin_samples();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match args.first().map(String::as_str) {
+        None | Some("list") => {
+            list(&registry);
+            Ok(())
+        }
+        Some("run") => run(registry, &args[1..]),
+        Some("all") => run_all(&registry, &args[1..]),
+        Some(_) => Err(format!("Unknown command '{}'", args[0]).into()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}\n\n{}\n{}", e, USAGE, cli::USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn list(registry: &SampleRegistry) {
+    println!("{}", USAGE);
+    println!("Samples:");
+    for sample in registry.samples() {
+        println!("    {:<24}{}", sample.name(), sample.description());
+    }
+}
+
+fn run(registry: SampleRegistry, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let name = args.first().ok_or("Missing sample name")?;
+    let sample = registry.find(name).ok_or_else(|| format!("Unknown sample '{}'", name))?;
+
+    let config = cli::from_args(AppConfig::new(sample.update_frequency()), args[1..].iter().cloned());
+    samples::run_with_config(registry, name, config)
+}
+
+/// Each sample runs in its own process as winit only allows one event loop per process
+fn run_all(registry: &SampleRegistry, args: &[String]) -> Result<(), Box<dyn Error>> {
+    // Validate the options once up front rather than in every child
+    let config = cli::parse(AppConfig::new(UpdateFrequency::Continuous), args.iter().cloned())?
+        .ok_or("--help is not supported with 'all'")?;
+
+    let executable = std::env::current_exe()?;
+    let mut failed = Vec::new();
+
+    for sample in registry.samples() {
+        if !samples::device_supports(&sample.required_features(), config.gpu.as_ref())? {
+            println!("{:<24}skipped (unsupported device features)", sample.name());
+            continue;
+        }
+
+        let mut command = Command::new(&executable);
+        command.arg("run").arg(sample.name()).arg("--headless").args(args);
+        if config.frames.is_none() && config.benchmark.is_none() {
+            command.args(&["--frames", DEFAULT_FRAMES]);
+        }
+
+        // The last occurrence of a flag wins, so these replace the shared paths in `args`
+        if let Some(benchmark) = &config.benchmark {
+            command.arg("--benchmark").arg(cli::sample_path(&benchmark.report_path, sample.name()));
+        }
+        if let Some(screenshot) = &config.screenshot {
+            command.arg("--screenshot").arg(cli::sample_path(screenshot, sample.name()));
+        }
+        if let Some(cpu_trace) = &config.cpu_trace {
+            command.arg("--cpu-trace").arg(cli::sample_path(cpu_trace, sample.name()));
+        }
+
+        let status = command.status()?;
+        if status.success() {
+            println!("{:<24}passed", sample.name());
+        } else {
+            println!("{:<24}failed ({})", sample.name(), status);
+            failed.push(sample.name());
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} sample(s) failed: {}", failed.len(), failed.join(", ")).into())
+    }
+}
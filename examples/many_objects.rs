@@ -0,0 +1,9 @@
+use vulkan_samples::samples::{
+        self,
+        many_objects::ManyObjects,
+};
+
+#[cfg_attr(target_os = "android", ndk_glue::main(backtrace))]
+fn main() {
+    samples::run(Box::new(ManyObjects));
+}
@@ -1,24 +1,213 @@
 pub use crate::logger;
 
-use std::error::Error;
+use crate::{
+    benchmark::{
+        Benchmark,
+        BenchmarkConfig,
+        BenchmarkReport,
+    },
+    cpu_profiler,
+    vulkan_app::{
+        DefaultInstanceFactory,
+        DefaultSwapchainFactory,
+        GpuSelection,
+        InstanceFactory,
+        SwapchainFactory,
+    },
+};
+
+use std::{
+    any::Any,
+    collections::HashSet,
+    error::Error,
+    path::PathBuf,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use log::LevelFilter;
+
+use vulkano::swapchain::PresentMode;
 
 use winit::{
+      dpi::PhysicalSize,
       event::{Event, WindowEvent},
-      event_loop::{ControlFlow, EventLoop},
+      event_loop::{ControlFlow, EventLoop, EventLoopProxy},
       window::{Window,WindowBuilder, WindowId},
 };
 
 pub trait AppEventHandlerFactory {
-    fn create_event_handler(&self, window: Window) -> Result<Box<dyn AppEventHandler>, Box<dyn Error>>;
+    fn create_event_handler(
+        &self,
+        window: Window,
+        config: &AppConfig,
+        context: AppContext,
+    ) -> Result<Box<dyn AppEventHandler>, Box<dyn Error>>;
+}
+
+/// Sent to the run loop through `AppContext`
+#[derive(Debug)]
+pub enum AppEvent {
+    /// Wakes the loop from `ControlFlow::Wait`, which redraws
+    Redraw,
+    /// Sent by `AppContext::wake_at` so a waiting loop picks up the new deadline, doesn't
+    /// redraw by itself
+    WakeUp,
+    /// Passed to `AppEventHandler::on_user_event`, handlers downcast to the types they expect
+    User(Box<dyn Any + Send>),
+    /// Sent by `AppContext::create_window`, windows can only be built on the loop's thread
+    CreateWindow(WindowRequest),
+    /// Sent by `AppContext::set_update_frequency`
+    SetUpdateFrequency(UpdateFrequency),
+}
+
+/// An additional window, passed back to `AppEventHandler::on_window_created` once built
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowRequest {
+    pub title: String,
+    /// Inner size in physical pixels, `None` lets the platform decide
+    pub size: Option<[u32; 2]>,
+}
+
+/// Lets handlers (or threads they spawn) schedule frames in `UpdateFrequency::OnEvent` mode,
+/// where the loop otherwise sleeps until the next window event
+///
+/// Requests are ignored in `Continuous` mode, which redraws back to back anyway.  Clones can
+/// be moved to other threads (e.g. asset loaders or file watchers) to notify the handler.
+#[derive(Clone)]
+pub struct AppContext {
+    proxy: EventLoopProxy<AppEvent>,
+    wake_up: Arc<Mutex<WakeUp>>,
+}
+
+/// Earliest deadline requested through `AppContext::wake_at`
+#[derive(Debug, Default)]
+struct WakeUp {
+    deadline: Option<Instant>,
+}
+
+impl WakeUp {
+
+    /// Keeps whichever of the pending and new deadline is earlier
+    fn schedule(&mut self, instant: Instant) {
+        self.deadline = Some(self.deadline.map_or(instant, |pending| pending.min(instant)));
+    }
+
+    /// Clears the deadline once `now` has reached it, returning whether it had
+    fn expire(&mut self, now: Instant) -> bool {
+        let expired = self.deadline.map_or(false, |deadline| deadline <= now);
+        if expired {
+            self.deadline = None;
+        }
+        expired
+    }
+}
+
+impl AppContext {
+
+    fn new(proxy: EventLoopProxy<AppEvent>) -> AppContext {
+        AppContext { proxy, wake_up: Arc::new(Mutex::new(WakeUp::default())) }
+    }
+
+    /// Redraws as soon as possible, can be called from any thread
+    pub fn request_redraw(&self) {
+        // Only fails once the loop has exited, when there is nothing left to redraw
+        let _ = self.proxy.send_event(AppEvent::Redraw);
+    }
+
+    /// Redraws at `instant` unless an earlier wake up is already pending
+    pub fn wake_at(&self, instant: Instant) {
+        self.wake_up.lock().unwrap().schedule(instant);
+
+        // The loop may already be waiting, so it needs to pick up the new deadline
+        let _ = self.proxy.send_event(AppEvent::WakeUp);
+    }
+
+    /// Switches the loop between `Continuous` and `OnEvent` updates, e.g. when a different
+    /// sample takes over the window
+    ///
+    /// Ignored when benchmarking or rendering a fixed number of frames, which stay continuous.
+    pub fn set_update_frequency(&self, update_frequency: UpdateFrequency) {
+        let _ = self.proxy.send_event(AppEvent::SetUpdateFrequency(update_frequency));
+    }
+
+    /// Delivers `event` to `AppEventHandler::on_user_event` on the loop's thread
+    pub fn send_user_event<T: Any + Send>(&self, event: T) -> Result<(), Box<dyn Error>> {
+        self.proxy
+            .send_event(AppEvent::User(Box::new(event)))
+            .map_err(|_| "Event loop has already exited".into())
+    }
+
+    /// Builds a secondary window on the next loop iteration
+    ///
+    /// Closing a secondary window calls `AppEventHandler::on_window_closed` instead of exiting.
+    pub fn create_window(&self, request: WindowRequest) -> Result<(), Box<dyn Error>> {
+        self.proxy
+            .send_event(AppEvent::CreateWindow(request))
+            .map_err(|_| "Event loop has already exited".into())
+    }
+
+    /// For code that needs to send `AppEvent`s itself
+    pub fn proxy(&self) -> EventLoopProxy<AppEvent> {
+        self.proxy.clone()
+    }
+
+    fn next_wake_up(&self) -> Option<Instant> {
+        self.wake_up.lock().unwrap().deadline
+    }
+
+    /// Whether the pending wake up is due, clearing it if so
+    fn expire_wake_up(&self, now: Instant) -> bool {
+        self.wake_up.lock().unwrap().expire(now)
+    }
 }
 
 pub trait AppEventHandler {
     /// (will only be run for continuos apps)
-    fn on_update(&mut self) {}
+    /// `delta_time` is the time elapsed since the previous update
+    fn on_update(&mut self, _delta_time: Duration) {}
+    /// Called for every event targeting the app window (input, focus, etc.) before it is
+    /// otherwise handled
+    fn on_window_event(&mut self, _event: &WindowEvent) {}
     fn on_window_resize(&mut self, width: u32, height: u32) -> Result<(), Box<dyn Error>>;
-    fn on_redraw(&mut self) -> Result<(), Box<dyn Error>>;
+    /// Returns whether a frame was presented, only those are measured in benchmark mode (frames
+    /// skipped while the swapchain is recreated aren't)
+    fn on_redraw(&mut self) -> Result<bool, Box<dyn Error>>;
+    /// Takes ownership of a window requested with `AppContext::create_window`, typically to
+    /// present to it with `RenderState::for_window`
+    fn on_window_created(&mut self, request: WindowRequest, _window: Window) -> Result<(), Box<dyn Error>> {
+        warn!("Handler does not support additional windows, dropping '{}'", request.title);
+        Ok(())
+    }
+    /// Events for secondary windows (including `Resized`), except `CloseRequested`
+    fn on_secondary_window_event(&mut self, _window_id: WindowId, _event: &WindowEvent) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// Called for each secondary window after `on_redraw`
+    fn on_secondary_window_redraw(&mut self, _window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// A secondary window asked to close, dropping it closes it
+    ///
+    /// Returning `Ok` stops all further events and redraws for `window_id`
+    fn on_window_closed(&mut self, _window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// Called with events sent through `AppContext::send_user_event`
+    fn on_user_event(&mut self, _event: Box<dyn Any + Send>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// Called in benchmark mode before the report is written, to add device info and GPU timings
+    fn on_benchmark_report(&self, _report: &mut BenchmarkReport) {}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UpdateFrequency {
     /// only update when event needs to be handle (appropriate for GUI/static apps)
     OnEvent,
@@ -27,10 +216,85 @@ pub enum UpdateFrequency {
     Continuous,
 }
 
+/// Runtime options shared by every sample, usually built by `cli::from_env`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppConfig {
+    pub update_frequency: UpdateFrequency,
+    /// `None` picks the first compatible device
+    pub gpu: Option<GpuSelection>,
+    pub validation: bool,
+    /// `None` uses Fifo (vsync), or the fastest available mode when benchmarking
+    pub present_mode: Option<PresentMode>,
+    /// Samples per pixel for the sample's main pass, 1 disables MSAA
+    pub msaa_samples: u32,
+    /// Initial inner size in physical pixels, `None` lets the platform decide
+    pub window_size: Option<[u32; 2]>,
+    /// Exit after rendering this many frames
+    pub frames: Option<u32>,
+    /// Renders into a hidden window
+    ///
+    /// @TODO - true surfaceless rendering needs a render target abstraction that doesn't
+    /// assume a swapchain
+    pub headless: bool,
+    /// Saved from the last frame (see `frames`)
+    pub screenshot: Option<PathBuf>,
+    pub log_level: LevelFilter,
+    /// Chrome trace written by the CPU profiler on exit
+    pub cpu_trace: Option<PathBuf>,
+    /// Renders a fixed number of frames, writes a report and exits
+    pub benchmark: Option<BenchmarkConfig>,
+}
+
+impl AppConfig {
+
+    pub fn new(update_frequency: UpdateFrequency) -> AppConfig {
+        AppConfig {
+            update_frequency,
+            gpu: None,
+            validation: false,
+            present_mode: None,
+            msaa_samples: 1,
+            window_size: None,
+            frames: None,
+            headless: false,
+            screenshot: None,
+            log_level: LevelFilter::Trace,
+            cpu_trace: None,
+            benchmark: None,
+        }
+    }
+
+    /// Mode to pass to `DefaultSwapchainFactory::with_present_mode`
+    pub fn present_mode(&self) -> PresentMode {
+        match (self.present_mode, &self.benchmark) {
+            (Some(present_mode), _) => present_mode,
+            (None, Some(_)) => PresentMode::Immediate,
+            (None, None) => PresentMode::Fifo,
+        }
+    }
+
+    pub fn instance_factory(&self) -> Box<dyn InstanceFactory> {
+        DefaultInstanceFactory::with_validation(self.validation)
+    }
+
+    pub fn swapchain_factory(&self) -> Box<dyn SwapchainFactory> {
+        DefaultSwapchainFactory::with_present_mode(self.present_mode())
+    }
+
+    /// The frame a screenshot should be taken on, counting from 1
+    pub fn screenshot_frame(&self) -> Option<u32> {
+        self.screenshot.as_ref().map(|_| self.frames.unwrap_or(1))
+    }
+}
+
 pub struct App {
     update_frequency: UpdateFrequency,
+    headless: bool,
+    frames: Option<u32>,
+    benchmark: Option<Benchmark>,
     window_id: WindowId,
-    event_loop: EventLoop<()>,
+    event_loop: EventLoop<AppEvent>,
+    context: AppContext,
     event_handler: Box<dyn AppEventHandler>,
 }
 
@@ -40,19 +304,44 @@ impl App {
         update_frequency: UpdateFrequency,
         event_handler_factory: Box<dyn AppEventHandlerFactory>,
     ) -> Result<App, Box<dyn Error>> {
-        // @TODO - add customizable paramaters?
-        logger::init();
+        App::with_config(AppConfig::new(update_frequency), event_handler_factory)
+    }
+
+    pub fn with_config(
+        mut config: AppConfig,
+        event_handler_factory: Box<dyn AppEventHandlerFactory>,
+    ) -> Result<App, Box<dyn Error>> {
+        logger::init_with_level(config.log_level);
+
+        if let Some(cpu_trace) = &config.cpu_trace {
+            cpu_profiler::enable(Some(cpu_trace.clone()));
+        }
+
+        // Benchmarks and frame limited runs need to render back to back regardless of events
+        if config.benchmark.is_some() || config.frames.is_some() {
+            config.update_frequency = UpdateFrequency::Continuous;
+        }
+
+        let event_loop = EventLoop::with_user_event();
+        let context = AppContext::new(event_loop.create_proxy());
 
-        let event_loop = EventLoop::new();
-        let window = WindowBuilder::new().build(&event_loop)?;
+        let mut window_builder = WindowBuilder::new().with_visible(!config.headless);
+        if let Some([width, height]) = config.window_size {
+            window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
+        }
+        let window = window_builder.build(&event_loop)?;
         let window_id = window.id();
 
-        let event_handler = event_handler_factory.create_event_handler(window)?;
+        let event_handler = event_handler_factory.create_event_handler(window, &config, context.clone())?;
 
         Ok(App {
-            update_frequency,
+            update_frequency: config.update_frequency,
+            headless: config.headless,
+            frames: config.frames,
+            benchmark: config.benchmark.map(Benchmark::new),
             window_id,
             event_loop,
+            context,
             event_handler,
         })
     }
@@ -60,25 +349,40 @@ impl App {
     /// Blocks until Application is complete
     pub fn run(self) {
 
-        let update_type = self.update_frequency;
+        let mut update_type = self.update_frequency;
         let mut event_handler = self.event_handler;
         let my_window_id = self.window_id;
+        let headless = self.headless;
+        let mut secondary_windows = HashSet::new();
+        let frame_limit = self.frames;
+        let mut frames_rendered = 0;
+        let mut benchmark = self.benchmark;
+        let mut last_update = Instant::now();
+        let mut exit_code = 0;
+        let context = self.context;
+        // `OnEvent` apps only redraw after events, redraw requests and expired wake ups
+        let mut redraw_pending = true;
 
-        self.event_loop.run(move |event, _, control_flow| {
+        self.event_loop.run(move |event, event_loop_target, control_flow| {
 
-            *control_flow = match update_type {
-                UpdateFrequency::Continuous => {
-                    event_handler.on_update();
-                    ControlFlow::Poll
-                },
-                UpdateFrequency::OnEvent => ControlFlow::Wait,
-            };
+            if update_type == UpdateFrequency::Continuous {
+                let now = Instant::now();
+                let _scope = cpu_profiler::scope("on_update");
+                event_handler.on_update(now - last_update);
+                last_update = now;
+            }
+
+            let loop_destroyed = matches!(event, Event::LoopDestroyed);
 
             let result = match event {
                 Event::WindowEvent {
                     event,
                     window_id,
                 } if window_id == my_window_id => {
+                    let _scope = cpu_profiler::scope("window event");
+                    redraw_pending = true;
+                    event_handler.on_window_event(&event);
+
                     match event {
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit;
@@ -88,7 +392,94 @@ impl App {
                         _ => Ok(())
                     }
                 }
-                Event::RedrawEventsCleared => event_handler.on_redraw(),
+                Event::WindowEvent {
+                    event,
+                    window_id,
+                } if secondary_windows.contains(&window_id) => {
+                    let _scope = cpu_profiler::scope("window event");
+                    redraw_pending = true;
+
+                    match event {
+                        // Once the handler has dropped the window it gets no further events or redraws
+                        WindowEvent::CloseRequested => event_handler.on_window_closed(window_id).map(|()| {
+                            secondary_windows.remove(&window_id);
+                        }),
+                        WindowEvent::Destroyed => {
+                            secondary_windows.remove(&window_id);
+                            Ok(())
+                        },
+                        event => event_handler.on_secondary_window_event(window_id, &event),
+                    }
+                }
+                Event::UserEvent(AppEvent::CreateWindow(request)) => {
+                    let mut window_builder = WindowBuilder::new()
+                        .with_title(request.title.clone())
+                        .with_visible(!headless);
+                    if let Some([width, height]) = request.size {
+                        window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
+                    }
+
+                    match window_builder.build(event_loop_target) {
+                        Ok(window) => {
+                            secondary_windows.insert(window.id());
+                            event_handler.on_window_created(request, window)
+                        },
+                        Err(e) => Err(e.into()),
+                    }
+                },
+                Event::UserEvent(AppEvent::Redraw) => {
+                    redraw_pending = true;
+                    Ok(())
+                },
+                Event::UserEvent(AppEvent::SetUpdateFrequency(update_frequency)) => {
+                    if benchmark.is_none() && frame_limit.is_none() && update_frequency != update_type {
+                        update_type = update_frequency;
+                        // Time spent waiting isn't an update interval
+                        last_update = Instant::now();
+                        redraw_pending = true;
+                    }
+                    Ok(())
+                },
+                // A due wake up is cleared before `redraw_pending` is checked, so one that
+                // coincides with another redraw reason doesn't cause a second frame
+                Event::RedrawEventsCleared if update_type == UpdateFrequency::OnEvent
+                    && !context.expire_wake_up(Instant::now())
+                    && !redraw_pending => Ok(()),
+                Event::RedrawEventsCleared => {
+                    redraw_pending = false;
+                    let result = {
+                        let _scope = cpu_profiler::scope("on_redraw");
+                        event_handler.on_redraw().and_then(|presented| {
+                            secondary_windows
+                                .iter()
+                                .try_for_each(|&window_id| event_handler.on_secondary_window_redraw(window_id))
+                                .map(|()| presented)
+                        })
+                    };
+                    cpu_profiler::end_frame();
+
+                    frames_rendered += 1;
+                    if frame_limit.map_or(false, |frame_limit| frames_rendered >= frame_limit) {
+                        *control_flow = ControlFlow::Exit;
+                    }
+
+                    let benchmark_finished = matches!(result, Ok(true))
+                        && benchmark.as_mut().map_or(false, |benchmark| benchmark.on_frame(Instant::now()));
+
+                    match (result, &benchmark) {
+                        (Ok(_), Some(benchmark)) if benchmark_finished => {
+                            *control_flow = ControlFlow::Exit;
+                            App::write_benchmark_report(benchmark, event_handler.as_ref())
+                        },
+                        (result, _) => result.map(|_| ()),
+                    }
+                },
+                Event::UserEvent(AppEvent::User(event)) => {
+                    let _scope = cpu_profiler::scope("on_user_event");
+                    redraw_pending = true;
+                    event_handler.on_user_event(event)
+                },
+                Event::LoopDestroyed => cpu_profiler::finish(),
                 _ => Ok(()),
             };
 
@@ -96,17 +487,82 @@ impl App {
                 Ok(()) => (),
                 Err(e) => {
                     error!("{}", e);
+                    exit_code = 1;
                     *control_flow = ControlFlow::Exit;
                 },
             }
+
+            // winit exits the process itself once the loop is destroyed
+            if loop_destroyed && exit_code != 0 {
+                std::process::exit(exit_code);
+            }
+
+            // After the handlers ran, so deadlines they set are waited for from this iteration
+            if *control_flow != ControlFlow::Exit {
+                *control_flow = match update_type {
+                    UpdateFrequency::Continuous => ControlFlow::Poll,
+                    UpdateFrequency::OnEvent => match context.next_wake_up() {
+                        Some(instant) => ControlFlow::WaitUntil(instant),
+                        None => ControlFlow::Wait,
+                    },
+                };
+            }
         });
     }
+
+    fn write_benchmark_report(benchmark: &Benchmark, event_handler: &dyn AppEventHandler) -> Result<(), Box<dyn Error>> {
+        let sample = std::env::current_exe()
+            .ok()
+            .and_then(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        let mut report = benchmark.report(&sample).ok_or("Benchmark finished without measuring any frames")?;
+        event_handler.on_benchmark_report(&mut report);
+
+        let path = &benchmark.config().report_path;
+        report.write(path)?;
+
+        info!(
+            "Benchmark: {} frames, average {:.3} ms, p95 {:.3} ms, p99 {:.3} ms, report written to {}",
+            report.measured_frames,
+            report.frame_times.average,
+            report.frame_times.p95,
+            report.frame_times.p99,
+            path.display(),
+        );
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn create_app() {
         //let app = App::new(
     }
+
+    #[test]
+    fn wake_up_keeps_the_earliest_deadline_until_it_expires() {
+        let now = Instant::now();
+        let mut wake_up = WakeUp::default();
+        assert!(!wake_up.expire(now));
+
+        wake_up.schedule(now + Duration::from_millis(33));
+        wake_up.schedule(now + Duration::from_millis(100));
+        assert_eq!(wake_up.deadline, Some(now + Duration::from_millis(33)));
+
+        wake_up.schedule(now + Duration::from_millis(10));
+        assert_eq!(wake_up.deadline, Some(now + Duration::from_millis(10)));
+
+        // Not due yet, so an unrelated event mustn't redraw or clear it
+        assert!(!wake_up.expire(now));
+        assert_eq!(wake_up.deadline, Some(now + Duration::from_millis(10)));
+
+        assert!(wake_up.expire(now + Duration::from_millis(10)));
+        assert_eq!(wake_up.deadline, None);
+        assert!(!wake_up.expire(now + Duration::from_millis(50)));
+    }
 }
@@ -0,0 +1,341 @@
+use std::{
+    error::Error,
+    fmt::Write as _,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Instant,
+};
+
+use vulkano::instance::PhysicalDevice;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkConfig {
+    /// Frames rendered before measuring starts (pipeline compilation, caches, clocks ramping up)
+    pub warmup_frames: u32,
+    pub measured_frames: u32,
+    /// Written as CSV if the extension is `.csv`, JSON otherwise
+    pub report_path: PathBuf,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> BenchmarkConfig {
+        BenchmarkConfig {
+            warmup_frames: 100,
+            measured_frames: 1000,
+            report_path: PathBuf::from("benchmark.json"),
+        }
+    }
+}
+
+/// Frame time summary in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStatistics {
+    pub min: f64,
+    pub average: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl FrameStatistics {
+
+    /// Percentiles use the nearest rank method, `None` when there are no frames
+    pub fn from_frame_times(frame_times: &[f64]) -> Option<FrameStatistics> {
+        if frame_times.is_empty() {
+            return None;
+        }
+
+        let mut sorted = frame_times.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let percentile = |percent: f64| {
+            let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+
+        Some(FrameStatistics {
+            min: sorted[0],
+            average: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub device_type: String,
+    pub api_version: String,
+    pub driver_version: u32,
+    pub vendor_id: u32,
+    pub device_id: u32,
+}
+
+impl DeviceInfo {
+
+    pub fn from_physical_device(physical_device: &PhysicalDevice) -> DeviceInfo {
+        let api_version = physical_device.api_version();
+
+        DeviceInfo {
+            name: physical_device.name().to_string(),
+            device_type: format!("{:?}", physical_device.ty()),
+            api_version: format!("{}.{}.{}", api_version.major, api_version.minor, api_version.patch),
+            driver_version: physical_device.driver_version(),
+            vendor_id: physical_device.pci_vendor_id(),
+            device_id: physical_device.pci_device_id(),
+        }
+    }
+}
+
+/// Filled in by `App` with frame times, then by `AppEventHandler::on_benchmark_report` with
+/// whatever the sample knows about its device and GPU timings
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub sample: String,
+    pub warmup_frames: u32,
+    pub measured_frames: u32,
+    pub frame_times: FrameStatistics,
+    pub device: Option<DeviceInfo>,
+    pub present_mode: Option<String>,
+    /// Average milliseconds per GPU profiler scope
+    pub gpu_scopes: Vec<(String, f64)>,
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            character if (character as u32) < 0x20 => { let _ = write!(escaped, "\\u{:04x}", character as u32); }
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl BenchmarkReport {
+
+    pub fn to_json(&self) -> String {
+        let frame_times = &self.frame_times;
+        let mut json = String::new();
+
+        // Writing to a String can't fail
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"sample\": {},", json_string(&self.sample));
+        let _ = writeln!(json, "  \"warmup_frames\": {},", self.warmup_frames);
+        let _ = writeln!(json, "  \"measured_frames\": {},", self.measured_frames);
+        let _ = writeln!(
+            json,
+            "  \"frame_time_ms\": {{ \"min\": {:.4}, \"average\": {:.4}, \"p95\": {:.4}, \"p99\": {:.4}, \"max\": {:.4} }},",
+            frame_times.min, frame_times.average, frame_times.p95, frame_times.p99, frame_times.max,
+        );
+
+        match &self.device {
+            Some(device) => {
+                let _ = writeln!(
+                    json,
+                    "  \"device\": {{ \"name\": {}, \"type\": {}, \"api_version\": {}, \"driver_version\": {}, \"vendor_id\": {}, \"device_id\": {} }},",
+                    json_string(&device.name),
+                    json_string(&device.device_type),
+                    json_string(&device.api_version),
+                    device.driver_version,
+                    device.vendor_id,
+                    device.device_id,
+                );
+            }
+            None => { let _ = writeln!(json, "  \"device\": null,"); }
+        }
+
+        match &self.present_mode {
+            Some(present_mode) => { let _ = writeln!(json, "  \"present_mode\": {},", json_string(present_mode)); }
+            None => { let _ = writeln!(json, "  \"present_mode\": null,"); }
+        }
+
+        let gpu_scopes = self.gpu_scopes
+            .iter()
+            .map(|(name, milliseconds)| format!("{}: {:.4}", json_string(name), milliseconds))
+            .collect::<Vec<_>>();
+        let _ = writeln!(json, "  \"gpu_time_ms\": {{ {} }}", gpu_scopes.join(", "));
+        let _ = writeln!(json, "}}");
+
+        json
+    }
+
+    /// One `metric,value` row per entry
+    pub fn to_csv(&self) -> String {
+        let mut rows = vec![
+            ("sample".to_string(), self.sample.clone()),
+            ("warmup_frames".to_string(), self.warmup_frames.to_string()),
+            ("measured_frames".to_string(), self.measured_frames.to_string()),
+            ("frame_time_min_ms".to_string(), format!("{:.4}", self.frame_times.min)),
+            ("frame_time_average_ms".to_string(), format!("{:.4}", self.frame_times.average)),
+            ("frame_time_p95_ms".to_string(), format!("{:.4}", self.frame_times.p95)),
+            ("frame_time_p99_ms".to_string(), format!("{:.4}", self.frame_times.p99)),
+            ("frame_time_max_ms".to_string(), format!("{:.4}", self.frame_times.max)),
+        ];
+
+        if let Some(device) = &self.device {
+            rows.push(("device_name".to_string(), device.name.clone()));
+            rows.push(("device_type".to_string(), device.device_type.clone()));
+            rows.push(("api_version".to_string(), device.api_version.clone()));
+            rows.push(("driver_version".to_string(), device.driver_version.to_string()));
+            rows.push(("vendor_id".to_string(), device.vendor_id.to_string()));
+            rows.push(("device_id".to_string(), device.device_id.to_string()));
+        }
+        if let Some(present_mode) = &self.present_mode {
+            rows.push(("present_mode".to_string(), present_mode.clone()));
+        }
+        for (name, milliseconds) in self.gpu_scopes.iter() {
+            rows.push((format!("gpu_{}_ms", name), format!("{:.4}", milliseconds)));
+        }
+
+        let mut csv = String::from("metric,value\n");
+        for (metric, value) in rows {
+            csv.push_str(&format!("{},{}\n", csv_field(&metric), csv_field(&value)));
+        }
+        csv
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => self.to_csv(),
+            _ => self.to_json(),
+        };
+
+        std::fs::write(path, contents)
+            .map_err(|error| format!("Unable to write benchmark report {}: {}", path.display(), error))?;
+
+        Ok(())
+    }
+}
+
+/// Counts frames for `App`, measuring the interval between consecutive presented frames
+pub struct Benchmark {
+    config: BenchmarkConfig,
+    frames: u32,
+    last_frame: Option<Instant>,
+    frame_times: Vec<f64>,
+}
+
+impl Benchmark {
+
+    pub fn new(config: BenchmarkConfig) -> Benchmark {
+        let frame_times = Vec::with_capacity(config.measured_frames as usize);
+        Benchmark { config, frames: 0, last_frame: None, frame_times }
+    }
+
+    pub fn config(&self) -> &BenchmarkConfig {
+        &self.config
+    }
+
+    /// Returns true once all measured frames have been recorded
+    pub fn on_frame(&mut self, now: Instant) -> bool {
+        if let Some(last_frame) = self.last_frame {
+            // The first measured interval starts at the end of the last warm up frame
+            if self.frames >= self.config.warmup_frames {
+                self.frame_times.push((now - last_frame).as_secs_f64() * 1000.0);
+            }
+        }
+        self.last_frame = Some(now);
+        self.frames += 1;
+
+        self.frame_times.len() >= self.config.measured_frames as usize
+    }
+
+    /// `None` until at least one frame has been measured
+    pub fn report(&self, sample: &str) -> Option<BenchmarkReport> {
+        Some(BenchmarkReport {
+            sample: sample.to_string(),
+            warmup_frames: self.config.warmup_frames,
+            measured_frames: self.frame_times.len() as u32,
+            frame_times: FrameStatistics::from_frame_times(&self.frame_times)?,
+            device: None,
+            present_mode: None,
+            gpu_scopes: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_statistics_include_percentiles() {
+        let frame_times = (1..=100).map(|time| time as f64).collect::<Vec<_>>();
+        let statistics = FrameStatistics::from_frame_times(&frame_times).unwrap();
+
+        assert_eq!(statistics.min, 1.0);
+        assert_eq!(statistics.max, 100.0);
+        assert_eq!(statistics.average, 50.5);
+        assert_eq!(statistics.p95, 95.0);
+        assert_eq!(statistics.p99, 99.0);
+
+        assert_eq!(FrameStatistics::from_frame_times(&[4.0]).unwrap().p99, 4.0);
+        assert!(FrameStatistics::from_frame_times(&[]).is_none());
+    }
+
+    #[test]
+    fn warmup_frames_are_not_measured() {
+        let mut benchmark = Benchmark::new(BenchmarkConfig {
+            warmup_frames: 2,
+            measured_frames: 2,
+            report_path: PathBuf::new(),
+        });
+
+        let start = Instant::now();
+        let frame = |index: u64| start + Duration::from_millis(index * index);
+
+        // The first two redraws are warm up, the 3 and 5 ms intervals after them are measured
+        assert!(!benchmark.on_frame(frame(0)));
+        assert!(!benchmark.on_frame(frame(1)));
+        assert!(!benchmark.on_frame(frame(2)));
+        assert!(benchmark.on_frame(frame(3)));
+
+        let report = benchmark.report("test").unwrap();
+        assert_eq!(report.measured_frames, 2);
+        assert_eq!(report.frame_times.min, 3.0);
+        assert_eq!(report.frame_times.max, 5.0);
+    }
+
+    #[test]
+    fn reports_escape_json_and_csv_fields() {
+        let report = BenchmarkReport {
+            sample: "simple \"triangle\"".to_string(),
+            warmup_frames: 1,
+            measured_frames: 2,
+            frame_times: FrameStatistics { min: 1.0, average: 2.0, p95: 3.0, p99: 4.0, max: 5.0 },
+            device: None,
+            present_mode: Some("Immediate".to_string()),
+            gpu_scopes: vec![("main, pass".to_string(), 0.5)],
+        };
+
+        let json = report.to_json();
+        assert!(json.contains("\"sample\": \"simple \\\"triangle\\\"\","));
+        assert!(json.contains("\"p99\": 4.0000"));
+        assert!(json.contains("\"device\": null,"));
+        assert!(json.contains("\"gpu_time_ms\": { \"main, pass\": 0.5000 }"));
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with("metric,value\nsample,\"simple \"\"triangle\"\"\"\n"));
+        assert!(csv.contains("frame_time_p95_ms,3.0000\n"));
+        assert!(csv.contains("\"gpu_main, pass_ms\",0.5000\n"));
+    }
+}
@@ -0,0 +1,369 @@
+use crate::math::{
+    self,
+    add,
+    cross,
+    normalize,
+    scale,
+    sub,
+    Matrix4,
+    Vector3,
+};
+
+use std::{
+    collections::HashSet,
+    f32::consts::FRAC_PI_2,
+};
+
+use winit::{
+    dpi::PhysicalPosition,
+    event::{
+        ElementState,
+        MouseButton,
+        MouseScrollDelta,
+        VirtualKeyCode,
+        WindowEvent,
+    },
+};
+
+const WORLD_UP: Vector3 = [0.0, 1.0, 0.0];
+
+/// Keeps the camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// `height` is the visible view space height, the width follows the aspect ratio
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub position: Vector3,
+    pub target: Vector3,
+    pub up: Vector3,
+    pub projection: Projection,
+    /// See `math::perspective`
+    pub reversed_z: bool,
+    aspect_ratio: f32,
+}
+
+impl Camera {
+
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Perspective { fov_y, near, far })
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Orthographic { height, near, far })
+    }
+
+    fn new(projection: Projection) -> Camera {
+        Camera {
+            position: [0.0, 0.0, 5.0],
+            target: [0.0, 0.0, 0.0],
+            up: WORLD_UP,
+            projection,
+            reversed_z: false,
+            aspect_ratio: 1.0,
+        }
+    }
+
+    /// Should be called from `AppEventHandler::on_window_resize`
+    ///
+    /// Zero sized (minimized) windows are ignored so the projection never divides by zero
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.aspect_ratio = width as f32 / height as f32;
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    pub fn forward(&self) -> Vector3 {
+        normalize(sub(self.target, self.position))
+    }
+
+    pub fn view_matrix(&self) -> Matrix4 {
+        math::look_at(self.position, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4 {
+        match self.projection {
+            Projection::Perspective { fov_y, near, far } => {
+                math::perspective(fov_y, self.aspect_ratio, near, far, self.reversed_z)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                math::orthographic(-half_width, half_width, -half_height, half_height, near, far, self.reversed_z)
+            }
+        }
+    }
+
+    pub fn view_projection_matrix(&self) -> Matrix4 {
+        math::multiply(&self.projection_matrix(), &self.view_matrix())
+    }
+}
+
+/// Moves a camera in response to window input
+///
+/// Forward `AppEventHandler::on_window_event` to `on_window_event` and call `update` from
+/// `AppEventHandler::on_update`.
+pub trait CameraController {
+    fn on_window_event(&mut self, event: &WindowEvent);
+    fn update(&mut self, camera: &mut Camera, delta_time: f32);
+}
+
+/// Converts absolute cursor positions into per event deltas
+#[derive(Default)]
+struct CursorTracker {
+    last_position: Option<PhysicalPosition<f64>>,
+}
+
+impl CursorTracker {
+
+    fn moved(&mut self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let delta = match self.last_position {
+            Some(last) => [(position.x - last.x) as f32, (position.y - last.y) as f32],
+            None => [0.0, 0.0],
+        };
+        self.last_position = Some(position);
+        delta
+    }
+}
+
+fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        // Roughly one line per 100 pixels on touchpads
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+    }
+}
+
+/// Orbits around `target`: left drag rotates, middle drag pans and the wheel zooms
+pub struct OrbitController {
+    pub target: Vector3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel dragged
+    pub rotate_speed: f32,
+    /// Fraction of the distance moved per pixel dragged
+    pub pan_speed: f32,
+    /// Fraction of the distance moved per scroll line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    cursor: CursorTracker,
+    rotating: bool,
+    panning: bool,
+    pending_rotation: [f32; 2],
+    pending_pan: [f32; 2],
+    pending_zoom: f32,
+}
+
+impl OrbitController {
+
+    pub fn new(target: Vector3, distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            rotate_speed: 0.005,
+            pan_speed: 0.002,
+            zoom_speed: 0.1,
+            min_distance: 0.01,
+            max_distance: f32::MAX,
+            cursor: CursorTracker::default(),
+            rotating: false,
+            panning: false,
+            pending_rotation: [0.0, 0.0],
+            pending_pan: [0.0, 0.0],
+            pending_zoom: 0.0,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.rotating = pressed,
+                    MouseButton::Middle => self.panning = pressed,
+                    _ => (),
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let [dx, dy] = self.cursor.moved(*position);
+                if self.rotating {
+                    self.pending_rotation[0] += dx;
+                    self.pending_rotation[1] += dy;
+                }
+                if self.panning {
+                    self.pending_pan[0] += dx;
+                    self.pending_pan[1] += dy;
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => self.pending_zoom += scroll_lines(delta),
+            WindowEvent::CursorLeft { .. } => self.cursor.last_position = None,
+            _ => (),
+        }
+    }
+
+    /// Mouse input is already frame rate independent so `delta_time` is unused
+    fn update(&mut self, camera: &mut Camera, _delta_time: f32) {
+
+        self.yaw -= self.pending_rotation[0] * self.rotate_speed;
+        self.pitch = (self.pitch + self.pending_rotation[1] * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+
+        self.distance *= (1.0 - self.pending_zoom * self.zoom_speed).max(0.1);
+        // Not `clamp`, which panics if the public limits are set with min above max
+        self.distance = self.distance.max(self.min_distance).min(self.max_distance);
+
+        let offset = [
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        ];
+
+        // Pan in the view plane so the target follows the cursor
+        let forward = scale(offset, -1.0);
+        let right = normalize(cross(forward, WORLD_UP));
+        let up = cross(right, forward);
+        let pan_scale = self.distance * self.pan_speed;
+        self.target = add(self.target, scale(right, -self.pending_pan[0] * pan_scale));
+        self.target = add(self.target, scale(up, self.pending_pan[1] * pan_scale));
+
+        self.pending_rotation = [0.0, 0.0];
+        self.pending_pan = [0.0, 0.0];
+        self.pending_zoom = 0.0;
+
+        camera.target = self.target;
+        camera.position = add(self.target, scale(offset, self.distance));
+        camera.up = WORLD_UP;
+    }
+}
+
+/// First person camera: WASD to move, Q/E down/up, shift to speed up and right drag to look
+pub struct FlyController {
+    pub position: Vector3,
+    /// Zero looks down -Z
+    pub yaw: f32,
+    pub pitch: f32,
+    /// World units per second
+    pub move_speed: f32,
+    pub boost_multiplier: f32,
+    /// Radians per pixel dragged
+    pub look_speed: f32,
+    cursor: CursorTracker,
+    looking: bool,
+    pressed_keys: HashSet<VirtualKeyCode>,
+    pending_look: [f32; 2],
+}
+
+impl FlyController {
+
+    /// Starts from the camera's current position and direction
+    pub fn from_camera(camera: &Camera) -> FlyController {
+        let forward = camera.forward();
+
+        FlyController {
+            position: camera.position,
+            yaw: forward[0].atan2(-forward[2]),
+            pitch: forward[1].clamp(-1.0, 1.0).asin(),
+            move_speed: 5.0,
+            boost_multiplier: 4.0,
+            look_speed: 0.003,
+            cursor: CursorTracker::default(),
+            looking: false,
+            pressed_keys: HashSet::new(),
+            pending_look: [0.0, 0.0],
+        }
+    }
+
+    pub fn forward(&self) -> Vector3 {
+        [
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        ]
+    }
+
+    fn axis(&self, positive: VirtualKeyCode, negative: VirtualKeyCode) -> f32 {
+        let mut value = 0.0;
+        if self.pressed_keys.contains(&positive) {
+            value += 1.0;
+        }
+        if self.pressed_keys.contains(&negative) {
+            value -= 1.0;
+        }
+        value
+    }
+}
+
+impl CameraController for FlyController {
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.pressed_keys.insert(key),
+                        ElementState::Released => self.pressed_keys.remove(&key),
+                    };
+                }
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Right, .. } => {
+                self.looking = *state == ElementState::Pressed;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let [dx, dy] = self.cursor.moved(*position);
+                if self.looking {
+                    self.pending_look[0] += dx;
+                    self.pending_look[1] += dy;
+                }
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor.last_position = None,
+            // Keys released while unfocused would otherwise stay held
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.looking = false;
+            }
+            _ => (),
+        }
+    }
+
+    fn update(&mut self, camera: &mut Camera, delta_time: f32) {
+
+        self.yaw += self.pending_look[0] * self.look_speed;
+        self.pitch = (self.pitch - self.pending_look[1] * self.look_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        self.pending_look = [0.0, 0.0];
+
+        let forward = self.forward();
+        let right = normalize(cross(forward, WORLD_UP));
+
+        let boost = if self.pressed_keys.contains(&VirtualKeyCode::LShift) { self.boost_multiplier } else { 1.0 };
+        let distance = self.move_speed * boost * delta_time;
+
+        let movement = add(
+            add(
+                scale(forward, self.axis(VirtualKeyCode::W, VirtualKeyCode::S)),
+                scale(right, self.axis(VirtualKeyCode::D, VirtualKeyCode::A)),
+            ),
+            scale(WORLD_UP, self.axis(VirtualKeyCode::E, VirtualKeyCode::Q)),
+        );
+
+        self.position = add(self.position, scale(normalize(movement), distance));
+
+        camera.position = self.position;
+        camera.target = add(self.position, forward);
+        camera.up = WORLD_UP;
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{
+    app::AppConfig,
+    benchmark::BenchmarkConfig,
+    vulkan_app::GpuSelection,
+};
+
+use std::{
+    error::Error,
+    path::{
+        Path,
+        PathBuf,
+    },
+    str::FromStr,
+};
+
+use log::LevelFilter;
+
+use vulkano::swapchain::PresentMode;
+
+pub const USAGE: &str = "\
+Options:
+    --gpu <index|name>          Physical device by enumeration index or name substring
+    --validation                Enable the Khronos validation layer
+    --present-mode <mode>       fifo, relaxed, mailbox or immediate
+    --msaa <samples>            1, 2, 4, 8 or 16
+    --width <pixels>            Initial window width (requires --height)
+    --height <pixels>           Initial window height (requires --width)
+    --frames <count>            Exit after this many frames (measured frames with --benchmark)
+    --warmup <count>            Benchmark warm up frames
+    --benchmark <report>        Write a .json or .csv frame time report and exit
+    --headless                  Render into a hidden window
+    --screenshot <path>         Save the last frame (see --frames) as an image
+    --log-level <level>         off, error, warn, info, debug or trace
+    --cpu-trace <path>          Write a Chrome trace of the run loop on exit
+    --help                      Print this message
+";
+
+/// Parses the process arguments on top of `defaults`
+///
+/// Prints usage and exits on `--help` or invalid arguments, as there is no logger yet.
+pub fn from_env(defaults: AppConfig) -> AppConfig {
+    from_args(defaults, std::env::args().skip(1))
+}
+
+/// `from_env` for arguments that have already been partly consumed (e.g. by the launcher)
+pub fn from_args<I, S>(defaults: AppConfig, args: I) -> AppConfig
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    match parse(defaults, args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, Box<dyn Error>> {
+    value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, flag).into())
+}
+
+fn parse_present_mode(value: &str) -> Result<PresentMode, Box<dyn Error>> {
+    match value.to_lowercase().as_str() {
+        "fifo" => Ok(PresentMode::Fifo),
+        "relaxed" => Ok(PresentMode::Relaxed),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "immediate" => Ok(PresentMode::Immediate),
+        _ => Err(format!("Unknown present mode '{}'", value).into()),
+    }
+}
+
+/// Returns `None` when `--help` was given
+///
+/// Values may be given as `--flag value` or `--flag=value`.
+pub fn parse<I, S>(defaults: AppConfig, args: I) -> Result<Option<AppConfig>, Box<dyn Error>>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut config = defaults;
+    let mut width = None;
+    let mut height = None;
+    let mut warmup_frames = None;
+    let mut benchmark_report = None;
+
+    let mut args = args.into_iter().map(Into::into);
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (arg[..index].to_string(), Some(arg[index + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+
+        let mut value = || -> Result<String, Box<dyn Error>> {
+            match inline_value.clone() {
+                Some(value) => Ok(value),
+                None => args.next().ok_or_else(|| format!("Missing value for {}", flag).into()),
+            }
+        };
+
+        match flag.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--gpu" => config.gpu = Some(GpuSelection::parse(&value()?)),
+            "--validation" => config.validation = true,
+            "--present-mode" => config.present_mode = Some(parse_present_mode(&value()?)?),
+            "--msaa" => {
+                let samples = parse_value::<u32>(&flag, &value()?)?;
+                if !samples.is_power_of_two() || samples > 16 {
+                    return Err(format!("--msaa must be 1, 2, 4, 8 or 16, not {}", samples).into());
+                }
+                config.msaa_samples = samples;
+            }
+            "--width" => width = Some(parse_value::<u32>(&flag, &value()?)?),
+            "--height" => height = Some(parse_value::<u32>(&flag, &value()?)?),
+            "--frames" => config.frames = Some(parse_value(&flag, &value()?)?),
+            "--warmup" => warmup_frames = Some(parse_value(&flag, &value()?)?),
+            "--benchmark" => benchmark_report = Some(PathBuf::from(value()?)),
+            "--headless" => config.headless = true,
+            "--screenshot" => config.screenshot = Some(PathBuf::from(value()?)),
+            "--log-level" => config.log_level = parse_value::<LevelFilter>(&flag, &value()?)?,
+            "--cpu-trace" => config.cpu_trace = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("Unknown argument '{}'", arg).into()),
+        }
+    }
+
+    config.window_size = match (width, height) {
+        (Some(0), _) | (_, Some(0)) => return Err("Window size must be non zero".into()),
+        (Some(width), Some(height)) => Some([width, height]),
+        (None, None) => config.window_size,
+        _ => return Err("--width and --height must be given together".into()),
+    };
+
+    if let Some(report_path) = benchmark_report {
+        let defaults = BenchmarkConfig::default();
+        config.benchmark = Some(BenchmarkConfig {
+            warmup_frames: warmup_frames.unwrap_or(defaults.warmup_frames),
+            // --frames counts measured frames, the benchmark decides when to exit
+            measured_frames: config.frames.take().unwrap_or(defaults.measured_frames),
+            report_path,
+        });
+    } else if warmup_frames.is_some() {
+        return Err("--warmup requires --benchmark".into());
+    }
+
+    Ok(Some(config))
+}
+
+/// `<stem>_<sample>.<extension>` beside `path`, so samples run in turn don't overwrite each
+/// other's reports, screenshots and traces
+pub fn sample_path(path: &Path, sample: &str) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("_");
+    file_name.push(sample);
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::UpdateFrequency;
+
+    fn parse_args(args: &[&str]) -> Result<Option<AppConfig>, Box<dyn Error>> {
+        parse(AppConfig::new(UpdateFrequency::Continuous), args.iter().cloned())
+    }
+
+    #[test]
+    fn no_arguments_keep_defaults() {
+        let config = parse_args(&[]).unwrap().unwrap();
+        assert_eq!(config, AppConfig::new(UpdateFrequency::Continuous));
+    }
+
+    #[test]
+    fn flags_override_defaults() {
+        let config = parse_args(&[
+            "--gpu", "nvidia",
+            "--validation",
+            "--present-mode=mailbox",
+            "--msaa", "4",
+            "--width", "1280", "--height=720",
+            "--frames", "10",
+            "--headless",
+            "--screenshot", "out.png",
+            "--log-level", "warn",
+        ]).unwrap().unwrap();
+
+        assert_eq!(config.gpu, Some(GpuSelection::Name("nvidia".to_string())));
+        assert!(config.validation);
+        assert_eq!(config.present_mode, Some(PresentMode::Mailbox));
+        assert_eq!(config.msaa_samples, 4);
+        assert_eq!(config.window_size, Some([1280, 720]));
+        assert_eq!(config.frames, Some(10));
+        assert!(config.headless);
+        assert_eq!(config.screenshot_frame(), Some(10));
+        assert_eq!(config.log_level, LevelFilter::Warn);
+        assert_eq!(config.benchmark, None);
+
+        assert_eq!(parse_args(&["--gpu", "1"]).unwrap().unwrap().gpu, Some(GpuSelection::Index(1)));
+    }
+
+    #[test]
+    fn benchmark_measures_frames_with_immediate_present() {
+        let config = parse_args(&["--benchmark", "report.csv", "--frames", "500", "--warmup=20"]).unwrap().unwrap();
+
+        assert_eq!(config.frames, None);
+        assert_eq!(config.benchmark, Some(BenchmarkConfig {
+            warmup_frames: 20,
+            measured_frames: 500,
+            report_path: PathBuf::from("report.csv"),
+        }));
+        assert_eq!(config.present_mode(), PresentMode::Immediate);
+    }
+
+    #[test]
+    fn sample_paths_append_the_sample_name() {
+        assert_eq!(sample_path(Path::new("out/report.json"), "particles"), PathBuf::from("out/report_particles.json"));
+        assert_eq!(sample_path(Path::new("trace"), "many_objects"), PathBuf::from("trace_many_objects"));
+    }
+
+    #[test]
+    fn help_and_invalid_arguments_stop_parsing() {
+        assert!(parse_args(&["--help"]).unwrap().is_none());
+        assert!(parse_args(&["--unknown"]).is_err());
+        assert!(parse_args(&["--frames"]).is_err());
+        assert!(parse_args(&["--frames", "ten"]).is_err());
+        assert!(parse_args(&["--msaa", "3"]).is_err());
+        assert!(parse_args(&["--width", "100"]).is_err());
+        assert!(parse_args(&["--present-mode", "vsync"]).is_err());
+        assert!(parse_args(&["--warmup", "5"]).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::shader_reflection::ReflectedShader;
+
+use std::{
+    error::Error,
+    sync::Arc,
+};
+
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferUsage,
+        CpuAccessibleBuffer,
+        DeviceLocalBuffer,
+        TypedBufferAccess,
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        CommandBuffer,
+    },
+    descriptor::{
+        descriptor_set::{
+            DescriptorSet,
+            DescriptorSetsCollection,
+            PersistentDescriptorSet,
+            UnsafeDescriptorSetLayout,
+        },
+        PipelineLayoutAbstract,
+    },
+    device::{
+        Device,
+        DeviceOwned,
+        Queue,
+    },
+    image::ImageViewAccess,
+    instance::QueueFamily,
+    pipeline::{
+        shader::EntryPointAbstract,
+        ComputePipeline,
+        ComputePipelineAbstract,
+    },
+    sync::GpuFuture,
+};
+
+/// Workgroups needed to cover `invocations` with groups of `local_size`, rounding up
+///
+/// Shaders must discard the extra invocations in the last group.
+pub fn workgroup_count(invocations: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+    let count = |axis: usize| {
+        let local_size = local_size[axis].max(1);
+        // Rather than adding `local_size - 1` first, which overflows near `u32::MAX`
+        invocations[axis] / local_size + (invocations[axis] % local_size != 0) as u32
+    };
+    [count(0), count(1), count(2)]
+}
+
+/// A compute pipeline and the workgroup size (`local_size`) its shader declares
+pub struct ComputeKernel {
+    pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    local_size: [u32; 3],
+}
+
+impl ComputeKernel {
+
+    /// `local_size` must match the shader's, vulkano's generated shaders don't expose it
+    pub fn new<Cs>(
+        device: &Arc<Device>,
+        shader: &Cs,
+        specialization_constants: &Cs::SpecializationConstants,
+        local_size: [u32; 3],
+    ) -> Result<ComputeKernel, Box<dyn Error>>
+    where
+        Cs: EntryPointAbstract,
+        Cs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
+        let pipeline = ComputePipeline::new(device.clone(), shader, specialization_constants)?;
+
+        Ok(ComputeKernel { pipeline: Arc::new(pipeline), local_size })
+    }
+
+    /// Takes the workgroup size from the shader's reflection
+    ///
+    /// Descriptor sets can be checked against the shader with
+    /// `shader.layout().descriptor_set_builder(kernel.descriptor_set_layout(set)?, set)`.
+    pub fn from_reflected(device: &Arc<Device>, shader: &ReflectedShader) -> Result<ComputeKernel, Box<dyn Error>> {
+        let local_size = shader.reflection.local_size.ok_or("Compute shader does not declare a local size")?;
+
+        ComputeKernel::new(device, &shader.compute_entry_point()?, &(), local_size)
+    }
+
+    pub fn pipeline(&self) -> &Arc<dyn ComputePipelineAbstract + Send + Sync> {
+        &self.pipeline
+    }
+
+    pub fn local_size(&self) -> [u32; 3] {
+        self.local_size
+    }
+
+    pub fn descriptor_set_layout(&self, set: usize) -> Result<Arc<UnsafeDescriptorSetLayout>, Box<dyn Error>> {
+        Ok(self.pipeline
+            .descriptor_set_layout(set)
+            .ok_or_else(|| format!("Compute pipeline has no descriptor set {}", set))?
+            .clone())
+    }
+
+    /// Set whose only binding is a storage (or uniform) buffer
+    pub fn buffer_set<B>(&self, set: usize, buffer: B) -> Result<Arc<dyn DescriptorSet + Send + Sync>, Box<dyn Error>>
+    where
+        B: BufferAccess + Send + Sync + 'static,
+    {
+        Ok(Arc::new(PersistentDescriptorSet::start(self.descriptor_set_layout(set)?)
+            .add_buffer(buffer)?
+            .build()?))
+    }
+
+    /// Set whose only binding is a storage image
+    pub fn image_set<I>(&self, set: usize, image: I) -> Result<Arc<dyn DescriptorSet + Send + Sync>, Box<dyn Error>>
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        Ok(Arc::new(PersistentDescriptorSet::start(self.descriptor_set_layout(set)?)
+            .add_image(image)?
+            .build()?))
+    }
+
+    /// Dispatches enough workgroups for `invocations` threads in each dimension
+    pub fn dispatch<S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        invocations: [u32; 3],
+        sets: S,
+        push_constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: DescriptorSetsCollection,
+    {
+        builder.dispatch(
+            workgroup_count(invocations, self.local_size),
+            self.pipeline.clone(),
+            sets,
+            push_constants,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// A queue from a compute family without graphics support (async compute), if one was created
+pub fn dedicated_compute_queue(queues: &[Arc<Queue>]) -> Option<Arc<Queue>> {
+    queues
+        .iter()
+        .find(|queue| queue.family().supports_compute() && !queue.family().supports_graphics())
+        .cloned()
+}
+
+/// Device local buffer initialised with `data` by a copy on the first of `queues`
+///
+/// The buffer is shared concurrently between the families of all `queues`, so graphics and
+/// compute can both use it without ownership transfers.  The returned future must be joined
+/// before the buffer is used.
+pub fn storage_buffer_from_iter<T, I>(
+    queues: &[Arc<Queue>],
+    usage: BufferUsage,
+    data: I,
+) -> Result<(Arc<DeviceLocalBuffer<[T]>>, Box<dyn GpuFuture>), Box<dyn Error>>
+where
+    T: Send + Sync + 'static,
+    I: ExactSizeIterator<Item = T>,
+{
+    let upload_queue = queues.first().ok_or("No queue to upload the storage buffer on")?;
+    let device = upload_queue.device().clone();
+
+    let staging = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::transfer_source(), false, data)?;
+
+    let mut families: Vec<QueueFamily> = Vec::new();
+    for queue in queues {
+        if !families.iter().any(|family| family.id() == queue.family().id()) {
+            families.push(queue.family());
+        }
+    }
+
+    let buffer = DeviceLocalBuffer::array(
+        device.clone(),
+        staging.len(),
+        BufferUsage { transfer_destination: true, ..usage },
+        families,
+    )?;
+
+    let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(device, upload_queue.family())?;
+    builder.copy_buffer(staging, buffer.clone())?;
+
+    let future = builder.build()?
+        .execute(upload_queue.clone())?
+        .then_signal_fence_and_flush()?
+        .boxed();
+
+    Ok((buffer, future))
+}
+
+/// Semaphore signalled once everything submitted to `queue` up to and including `after` has
+/// finished
+///
+/// A future ending in a fence (like a frame's `then_signal_fence_and_flush`) gives other queues
+/// nothing to wait on, so chain this before submitting dependent work to a different queue.
+pub fn signal_after_queue<F>(after: F, queue: Arc<Queue>) -> Result<Box<dyn GpuFuture>, Box<dyn Error>>
+where
+    F: GpuFuture + 'static,
+{
+    // A semaphore needs a submission to signal it, an empty one still waits for all earlier
+    // work on the queue
+    let empty = AutoCommandBufferBuilder::primary_one_time_submit(queue.device().clone(), queue.family())?.build()?;
+
+    Ok(after
+        .then_execute(queue, empty)?
+        .then_signal_semaphore_and_flush()?
+        .boxed())
+}
+
+/// Submits `command_buffer` on `queue` after `after`, signalling a semaphore instead of a fence
+///
+/// Join the result into the graphics submission that reads the compute output, vulkano then
+/// makes that submission wait on the semaphore.
+pub fn submit_after<F, Cb>(after: F, queue: Arc<Queue>, command_buffer: Cb) -> Result<Box<dyn GpuFuture>, Box<dyn Error>>
+where
+    F: GpuFuture + 'static,
+    Cb: CommandBuffer + 'static,
+{
+    Ok(after
+        .then_execute(queue, command_buffer)?
+        .then_signal_semaphore_and_flush()?
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workgroup_count_rounds_partial_groups_up() {
+        assert_eq!(workgroup_count([256, 1, 1], [256, 1, 1]), [1, 1, 1]);
+        assert_eq!(workgroup_count([257, 1, 1], [256, 1, 1]), [2, 1, 1]);
+        assert_eq!(workgroup_count([1920, 1080, 1], [16, 16, 1]), [120, 68, 1]);
+        assert_eq!(workgroup_count([0, 1, 1], [64, 1, 1]), [0, 1, 1]);
+        assert_eq!(workgroup_count([5, 5, 5], [0, 0, 0]), [5, 5, 5]);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{
+        BufWriter,
+        Write,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{
+            AtomicBool,
+            AtomicU64,
+            Ordering,
+        },
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// Stop collecting trace events past this so long runs can't exhaust memory
+const MAX_TRACE_EVENTS: usize = 1 << 20;
+
+/// Queues a scope ending at the end of the enclosing block (no-op while profiling is disabled)
+///
+/// ```ignore
+/// profile_scope!("record");
+/// ```
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::cpu_profiler::scope($name);
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEvent {
+    pub name: &'static str,
+    pub thread: u64,
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Collects scopes into per frame totals and a Chrome trace
+///
+/// Usually used through the global functions in this module, which `App::run` drives.
+#[derive(Default)]
+pub struct Profiler {
+    events: Vec<TraceEvent>,
+    dropped_events: usize,
+    current_frame: Vec<(&'static str, Duration)>,
+    last_frame: Vec<(&'static str, Duration)>,
+    frame_start: Option<Duration>,
+}
+
+impl Profiler {
+
+    pub fn record(&mut self, event: TraceEvent) {
+        match self.current_frame.iter_mut().find(|(name, _)| *name == event.name) {
+            Some((_, total)) => *total += event.duration,
+            None => self.current_frame.push((event.name, event.duration)),
+        }
+
+        if self.events.len() < MAX_TRACE_EVENTS {
+            self.events.push(event);
+        } else {
+            self.dropped_events += 1;
+        }
+    }
+
+    /// `now` is the time since profiling started, a "frame" event spanning the frame is added
+    pub fn end_frame(&mut self, now: Duration, thread: u64) {
+        if let Some(start) = self.frame_start {
+            self.record(TraceEvent { name: "frame", thread, start, duration: now - start });
+        }
+        self.frame_start = Some(now);
+        self.last_frame = std::mem::take(&mut self.current_frame);
+    }
+
+    /// Total time per scope name in the last completed frame, in first recorded order
+    pub fn last_frame(&self) -> &[(&'static str, Duration)] {
+        &self.last_frame
+    }
+
+    /// Chrome trace event format, load in chrome://tracing or https://ui.perfetto.dev
+    pub fn write_chrome_trace<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "{{\"traceEvents\":[")?;
+        for (index, event) in self.events.iter().enumerate() {
+            writeln!(
+                writer,
+                "{{\"name\":\"{}\",\"cat\":\"cpu\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{}}}{}",
+                escape(event.name),
+                event.thread,
+                event.start.as_micros(),
+                event.duration.as_micros(),
+                if index + 1 < self.events.len() { "," } else { "" },
+            )?;
+        }
+        writeln!(writer, "],\"displayTimeUnit\":\"ms\"}}")
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+lazy_static! {
+    static ref PROFILER: Mutex<Option<GlobalProfiler>> = Mutex::new(None);
+}
+
+thread_local! {
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+struct GlobalProfiler {
+    profiler: Profiler,
+    epoch: Instant,
+    trace_path: Option<PathBuf>,
+}
+
+fn thread_index() -> u64 {
+    THREAD.with(|thread| *thread)
+}
+
+/// Starts collecting scopes, the trace is written to `trace_path` by `finish`
+pub fn enable(trace_path: Option<PathBuf>) {
+    let mut global = PROFILER.lock().unwrap_or_else(|error| error.into_inner());
+    *global = Some(GlobalProfiler { profiler: Profiler::default(), epoch: Instant::now(), trace_path });
+    ENABLED.store(true, Ordering::Release);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Times until dropped, see `profile_scope!`
+pub struct ScopeGuard {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let end = Instant::now();
+        let mut global = PROFILER.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(global) = global.as_mut() {
+            let event = TraceEvent {
+                name: self.name,
+                thread: thread_index(),
+                start: self.start.saturating_duration_since(global.epoch),
+                duration: end - self.start,
+            };
+            global.profiler.record(event);
+        }
+    }
+}
+
+pub fn scope(name: &'static str) -> Option<ScopeGuard> {
+    if is_enabled() {
+        Some(ScopeGuard { name, start: Instant::now() })
+    } else {
+        None
+    }
+}
+
+/// Called by `App::run` after each redraw
+pub fn end_frame() {
+    if !is_enabled() {
+        return;
+    }
+    let mut global = PROFILER.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(global) = global.as_mut() {
+        let now = global.epoch.elapsed();
+        global.profiler.end_frame(now, thread_index());
+    }
+}
+
+/// Per scope totals of the last completed frame
+pub fn last_frame() -> Vec<(&'static str, Duration)> {
+    let global = PROFILER.lock().unwrap_or_else(|error| error.into_inner());
+    global.as_ref().map(|global| global.profiler.last_frame().to_vec()).unwrap_or_default()
+}
+
+/// Stops profiling and writes the trace, if a path was given.  Called by `App::run` on exit.
+pub fn finish() -> Result<(), Box<dyn Error>> {
+    ENABLED.store(false, Ordering::Release);
+    let global = PROFILER.lock().unwrap_or_else(|error| error.into_inner()).take();
+
+    if let Some(GlobalProfiler { profiler, trace_path: Some(path), .. }) = global {
+        if profiler.dropped_events > 0 {
+            warn!("CPU trace is missing {} events past the {} event limit", profiler.dropped_events, MAX_TRACE_EVENTS);
+        }
+
+        let mut writer = BufWriter::new(File::create(&path)
+            .map_err(|error| format!("Unable to create trace {}: {}", path.display(), error))?);
+        profiler.write_chrome_trace(&mut writer)?;
+        writer.flush()?;
+
+        info!("Wrote CPU trace to {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &'static str, start_us: u64, duration_us: u64) -> TraceEvent {
+        TraceEvent {
+            name,
+            thread: 1,
+            start: Duration::from_micros(start_us),
+            duration: Duration::from_micros(duration_us),
+        }
+    }
+
+    #[test]
+    fn last_frame_sums_scopes_by_name() {
+        let mut profiler = Profiler::default();
+        profiler.end_frame(Duration::from_micros(0), 1);
+        profiler.record(event("record", 10, 100));
+        profiler.record(event("submit", 120, 30));
+        profiler.record(event("record", 200, 50));
+        profiler.end_frame(Duration::from_micros(1000), 1);
+
+        assert_eq!(profiler.last_frame(), &[
+            ("record", Duration::from_micros(150)),
+            ("submit", Duration::from_micros(30)),
+            ("frame", Duration::from_micros(1000)),
+        ]);
+
+        profiler.end_frame(Duration::from_micros(2000), 1);
+        assert_eq!(profiler.last_frame(), &[("frame", Duration::from_micros(1000))]);
+    }
+
+    #[test]
+    fn chrome_trace_escapes_names() {
+        let mut profiler = Profiler::default();
+        profiler.record(event("a \"quoted\" name", 5, 10));
+        profiler.record(event("b", 20, 1));
+
+        let mut output = Vec::new();
+        profiler.write_chrome_trace(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(output, concat!(
+            "{\"traceEvents\":[\n",
+            "{\"name\":\"a \\\"quoted\\\" name\",\"cat\":\"cpu\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":5,\"dur\":10},\n",
+            "{\"name\":\"b\",\"cat\":\"cpu\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":20,\"dur\":1}\n",
+            "],\"displayTimeUnit\":\"ms\"}\n",
+        ));
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    camera::Camera,
+    math::{
+        self,
+        add,
+        scale,
+        Matrix4,
+        Vector3,
+    },
+    mesh::MeshData,
+    scene::Aabb,
+    vulkan_app::AcquiredImage,
+};
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    f32::consts::PI,
+    sync::Arc,
+};
+
+use winit::window::Window;
+
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferSlice,
+        BufferUsage,
+        CpuBufferPool,
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        DynamicState,
+    },
+    device::Device,
+    format::{
+        ClearValue,
+        Format,
+    },
+    framebuffer::{
+        Framebuffer,
+        FramebufferAbstract,
+        RenderPassAbstract,
+        Subpass,
+    },
+    image::{
+        ImageViewAccess,
+        SwapchainImage,
+    },
+    pipeline::{
+        depth_stencil::{
+            Compare,
+            DepthStencil,
+        },
+        viewport::Viewport,
+        GraphicsPipeline,
+        GraphicsPipelineAbstract,
+    },
+};
+
+pub type Color = [f32; 4];
+
+pub const RED: Color = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: Color = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: Color = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: Color = [1.0, 1.0, 0.0, 1.0];
+pub const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+
+/// Segments used for each circle of a sphere
+const CIRCLE_SEGMENTS: usize = 32;
+
+mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(push_constant) uniform PushConstants {
+                mat4 view_projection;
+            } pc;
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec4 color;
+
+            layout(location = 0) out vec4 v_color;
+
+            void main() {
+                v_color = color;
+                gl_Position = pc.view_projection * vec4(position, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec4 v_color;
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color;
+            }
+        "
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(DebugVertex, position, color);
+
+type CachedFramebuffer = (Arc<SwapchainImage<Window>>, Arc<dyn FramebufferAbstract + Send + Sync>);
+
+/// Immediate mode world space line drawing for visualising bounds, cameras, normals etc.
+///
+/// Shapes are queued during the frame and recorded in a pass of their own after the main
+/// pass, loading its color (and, when depth tested lines are wanted, depth) attachments.  The
+/// main pass must therefore store its depth image for depth testing to work.  Lines queued while
+/// `depth_test` is false are always drawn on top.
+pub struct DebugDraw {
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    depth_tested_pipeline: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+    overlay_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    vertex_pool: CpuBufferPool<DebugVertex>,
+    framebuffers: HashMap<usize, CachedFramebuffer>,
+    depth_tested_vertices: Vec<DebugVertex>,
+    overlay_vertices: Vec<DebugVertex>,
+    /// Applies to shapes queued after it is changed, ignored without a depth format
+    pub depth_test: bool,
+}
+
+impl DebugDraw {
+
+    /// `depth_format` enables depth tested lines, `reversed_z` must match the camera used
+    pub fn new(
+        device: Arc<Device>,
+        color_format: Format,
+        depth_format: Option<Format>,
+        reversed_z: bool,
+    ) -> Result<DebugDraw, Box<dyn Error>> {
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = match depth_format {
+            Some(depth_format) => Arc::new(vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: color_format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Load,
+                        store: DontCare,
+                        format: depth_format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )?),
+            None => Arc::new(vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: color_format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )?),
+        };
+
+        let depth_tested_pipeline = match depth_format {
+            Some(_) => {
+                // Test against the scene but never write, so overlapping lines don't hide each other
+                let depth_stencil = DepthStencil {
+                    depth_write: false,
+                    depth_compare: if reversed_z { Compare::GreaterOrEqual } else { Compare::LessOrEqual },
+                    .. DepthStencil::disabled()
+                };
+                Some(DebugDraw::create_pipeline(&device, render_pass.clone(), depth_stencil)?)
+            }
+            None => None,
+        };
+
+        let overlay_pipeline = DebugDraw::create_pipeline(&device, render_pass.clone(), DepthStencil::disabled())?;
+
+        Ok(DebugDraw {
+            render_pass,
+            depth_tested_pipeline,
+            overlay_pipeline,
+            vertex_pool: CpuBufferPool::new(device, BufferUsage::vertex_buffer()),
+            framebuffers: HashMap::new(),
+            depth_tested_vertices: Vec::new(),
+            overlay_vertices: Vec::new(),
+            depth_test: depth_format.is_some(),
+        })
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        depth_stencil: DepthStencil,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, Box<dyn Error>> {
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        Ok(Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<DebugVertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .line_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil(depth_stencil)
+            .blend_alpha_blending()
+            .render_pass(Subpass::from(render_pass, 0).ok_or("Unable to build subpass")?)
+            .build(device.clone())?))
+    }
+
+    pub fn line(&mut self, from: Vector3, to: Vector3, color: Color) {
+        let vertices = if self.depth_test && self.depth_tested_pipeline.is_some() {
+            &mut self.depth_tested_vertices
+        } else {
+            &mut self.overlay_vertices
+        };
+
+        vertices.push(DebugVertex { position: from, color });
+        vertices.push(DebugVertex { position: to, color });
+    }
+
+    /// Closed loop through `points`
+    fn polygon(&mut self, points: &[Vector3], color: Color) {
+        for (index, &point) in points.iter().enumerate() {
+            self.line(point, points[(index + 1) % points.len()], color);
+        }
+    }
+
+    /// The 12 edges of a box given its corners in `Aabb` order (bit 0 = x, bit 1 = y, bit 2 = z)
+    fn box_edges(&mut self, corners: &[Vector3; 8], color: Color) {
+        for &(a, b) in [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ].iter() {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+
+    pub fn aabb(&mut self, bounds: &Aabb, color: Color) {
+        self.oriented_box(bounds, &math::IDENTITY, color);
+    }
+
+    /// `bounds` in object space, transformed by `transform`
+    pub fn oriented_box(&mut self, bounds: &Aabb, transform: &Matrix4, color: Color) {
+        let mut corners = [[0.0; 3]; 8];
+        for (index, corner) in corners.iter_mut().enumerate() {
+            let point = [
+                if index & 1 == 0 { bounds.min[0] } else { bounds.max[0] },
+                if index & 2 == 0 { bounds.min[1] } else { bounds.max[1] },
+                if index & 4 == 0 { bounds.min[2] } else { bounds.max[2] },
+            ];
+            *corner = math::transform_point(transform, point);
+        }
+        self.box_edges(&corners, color);
+    }
+
+    pub fn circle(&mut self, center: Vector3, axis_u: Vector3, axis_v: Vector3, radius: f32, color: Color) {
+        let points = (0..CIRCLE_SEGMENTS)
+            .map(|segment| {
+                let angle = 2.0 * PI * segment as f32 / CIRCLE_SEGMENTS as f32;
+                add(center, add(scale(axis_u, angle.cos() * radius), scale(axis_v, angle.sin() * radius)))
+            })
+            .collect::<Vec<_>>();
+
+        self.polygon(&points, color);
+    }
+
+    /// Three great circles, one per axis plane
+    pub fn sphere(&mut self, center: Vector3, radius: f32, color: Color) {
+        let (x, y, z) = ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]);
+        self.circle(center, x, y, radius, color);
+        self.circle(center, y, z, radius, color);
+        self.circle(center, z, x, radius, color);
+    }
+
+    /// Red, green and blue lines along the transform's X, Y and Z axes
+    pub fn axes(&mut self, transform: &Matrix4, size: f32) {
+        let origin = math::transform_point(transform, [0.0, 0.0, 0.0]);
+        self.line(origin, math::transform_point(transform, [size, 0.0, 0.0]), RED);
+        self.line(origin, math::transform_point(transform, [0.0, size, 0.0]), GREEN);
+        self.line(origin, math::transform_point(transform, [0.0, 0.0, size]), BLUE);
+    }
+
+    /// The volume seen by `view_projection`, which must have a finite far plane
+    pub fn frustum(&mut self, view_projection: &Matrix4, color: Color) {
+        let inverse = match math::inverse(view_projection) {
+            Some(inverse) => inverse,
+            None => return,
+        };
+
+        let mut corners = [[0.0; 3]; 8];
+        for (index, corner) in corners.iter_mut().enumerate() {
+            let clip = [
+                if index & 1 == 0 { -1.0 } else { 1.0 },
+                if index & 2 == 0 { -1.0 } else { 1.0 },
+                if index & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            ];
+            let world = math::transform_vector4(&inverse, clip);
+            *corner = scale([world[0], world[1], world[2]], 1.0 / world[3]);
+        }
+        self.box_edges(&corners, color);
+    }
+
+    pub fn camera(&mut self, camera: &Camera, color: Color) {
+        self.frustum(&camera.view_projection_matrix(), color);
+    }
+
+    /// Per vertex normals (blue) and tangents (red), assuming `transform` has uniform scale
+    pub fn normals(&mut self, mesh: &MeshData, transform: &Matrix4, length: f32) {
+        let direction = |v: Vector3| math::normalize(math::sub(math::transform_point(transform, v), math::transform_point(transform, [0.0; 3])));
+
+        for vertex in mesh.vertices.iter() {
+            let position = math::transform_point(transform, vertex.position);
+            self.line(position, add(position, scale(direction(vertex.normal), length)), BLUE);
+
+            let tangent = [vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]];
+            if math::length(tangent) > 0.0 {
+                self.line(position, add(position, scale(direction(tangent), length)), RED);
+            }
+        }
+    }
+
+    fn framebuffer(
+        &mut self,
+        acquired_image: &AcquiredImage,
+        depth: Option<Arc<dyn ImageViewAccess + Send + Sync>>,
+    ) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, Box<dyn Error>> {
+
+        // The depth image is owned by the caller and may change at any time, so only framebuffers
+        // without depth are cached
+        if depth.is_none() {
+            if let Some((image, framebuffer)) = self.framebuffers.get(&acquired_image.image_num) {
+                if Arc::ptr_eq(image, &acquired_image.image) {
+                    return Ok(framebuffer.clone());
+                }
+            }
+        }
+
+        let framebuffer: Arc<dyn FramebufferAbstract + Send + Sync> = match depth {
+            Some(depth) => Arc::new(
+                Framebuffer::start(self.render_pass.clone())
+                    .add(acquired_image.image.clone())?
+                    .add(depth)?
+                    .build()?,
+            ),
+            None => {
+                let framebuffer: Arc<dyn FramebufferAbstract + Send + Sync> = Arc::new(
+                    Framebuffer::start(self.render_pass.clone())
+                        .add(acquired_image.image.clone())?
+                        .build()?,
+                );
+                self.framebuffers.insert(acquired_image.image_num, (acquired_image.image.clone(), framebuffer.clone()));
+                framebuffer
+            }
+        };
+
+        Ok(framebuffer)
+    }
+
+    /// Records and clears everything queued this frame
+    ///
+    /// `depth` is required when the debug draw was created with a depth format.  Must be
+    /// recorded outside of any render pass.
+    pub fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        acquired_image: &AcquiredImage,
+        depth: Option<Arc<dyn ImageViewAccess + Send + Sync>>,
+        view_projection: &Matrix4,
+    ) -> Result<(), Box<dyn Error>> {
+
+        if self.depth_tested_pipeline.is_some() != depth.is_some() {
+            return Err("Debug draw depth image must be provided if and only if it has a depth format".into());
+        }
+
+        let depth_tested_count = self.depth_tested_vertices.len();
+        let overlay_count = self.overlay_vertices.len();
+
+        if depth_tested_count + overlay_count == 0 {
+            return Ok(());
+        }
+
+        let clear_values = if depth.is_some() { vec![ClearValue::None, ClearValue::None] } else { vec![ClearValue::None] };
+        let framebuffer = self.framebuffer(acquired_image, depth)?;
+        let dimensions = acquired_image.image.dimensions();
+
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            .. DynamicState::none()
+        };
+
+        // One buffer for the frame, depth tested lines first
+        let vertices = Arc::new(self.vertex_pool.chunk(
+            self.depth_tested_vertices.drain(..).chain(self.overlay_vertices.drain(..)).collect::<Vec<_>>().into_iter(),
+        )?);
+
+        let push_constants = vs::ty::PushConstants { view_projection: *view_projection };
+
+        builder.begin_render_pass(framebuffer, false, clear_values)?;
+
+        let batches = [
+            (self.depth_tested_pipeline.clone(), 0..depth_tested_count),
+            (Some(self.overlay_pipeline.clone()), depth_tested_count..depth_tested_count + overlay_count),
+        ];
+
+        for (pipeline, range) in batches.iter().cloned() {
+            if let (Some(pipeline), false) = (pipeline, range.start == range.end) {
+                let slice: Arc<dyn BufferAccess + Send + Sync> = Arc::new(
+                    BufferSlice::from_typed_buffer_access(vertices.clone())
+                        .slice(range)
+                        .ok_or("Debug draw range lies outside of its vertex buffer")?,
+                );
+
+                builder.draw(pipeline, &dynamic_state, vec![slice], (), push_constants)?;
+            }
+        }
+
+        builder.end_render_pass()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{
+    math::{
+        multiply,
+        Matrix4,
+        IDENTITY,
+    },
+    mesh::{
+        Mesh,
+        MeshData,
+        MeshVertex,
+    },
+    texture::{
+        Texture,
+        TextureColorSpace,
+    },
+    upload::UploadManager,
+};
+
+use std::{
+    error::Error,
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureReference {
+    /// Index into `GltfModel::textures`
+    pub texture: usize,
+    /// Which UV set the texture is sampled with, always 0 as only `TEXCOORD_0` is loaded
+    pub tex_coord: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask { cutoff: f32 },
+    Blend,
+}
+
+/// glTF metallic-roughness material parameters
+#[derive(Debug, Clone)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureReference>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureReference>,
+    pub normal_texture: Option<TextureReference>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureReference>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<TextureReference>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> PbrMaterial {
+        PbrMaterial {
+            name: None,
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+pub struct GltfPrimitive {
+    pub mesh: Mesh,
+    /// Index into `GltfModel::materials`, `None` for the glTF default material
+    pub material: Option<usize>,
+    /// Object space bounds (min, max)
+    pub bounds: ([f32; 3], [f32; 3]),
+}
+
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// Index into `GltfModel::meshes`
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+    pub local_transform: Matrix4,
+    /// Local transform concatenated with all of its parents in the default scene
+    pub world_transform: Matrix4,
+}
+
+pub struct GltfModel {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<Texture>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default (or first) scene
+    pub scene_roots: Vec<usize>,
+}
+
+impl GltfModel {
+
+    /// Loads a .gltf (with external or embedded buffers) or .glb file
+    ///
+    /// Geometry and textures are recorded into `upload_manager`, so its future must be joined
+    /// before the model is drawn
+    pub fn load<P: AsRef<Path>>(upload_manager: &mut UploadManager, path: P) -> Result<GltfModel, Box<dyn Error>> {
+
+        let (document, buffers, images) = gltf::import(path.as_ref())
+            .map_err(|e| format!("Unable to load glTF {}: {}", path.as_ref().display(), e))?;
+
+        let materials = document.materials().map(load_material).collect::<Result<Vec<_>, _>>()?;
+        let textures = load_textures(upload_manager, &document, &images, &materials)?;
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| -> Result<GltfMesh, Box<dyn Error>> {
+                let mut primitives = Vec::new();
+
+                for primitive in mesh.primitives() {
+                    if primitive.mode() != gltf::mesh::Mode::Triangles {
+                        warn!("Skipping non-triangle primitive in mesh {}", mesh.index());
+                        continue;
+                    }
+
+                    let data = load_primitive(&primitive, &buffers)?;
+                    let bounding_box = primitive.bounding_box();
+
+                    primitives.push(GltfPrimitive {
+                        mesh: data.upload(upload_manager)?,
+                        material: primitive.material().index(),
+                        bounds: (bounding_box.min, bounding_box.max),
+                    });
+                }
+
+                Ok(GltfMesh {
+                    name: mesh.name().map(String::from),
+                    primitives,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut nodes = document
+            .nodes()
+            .map(|node| GltfNode {
+                name: node.name().map(String::from),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+                local_transform: node.transform().matrix(),
+                world_transform: IDENTITY,
+            })
+            .collect::<Vec<_>>();
+
+        let scene_roots = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .map(|scene| scene.nodes().map(|node| node.index()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        for root in scene_roots.iter() {
+            update_world_transforms(&mut nodes, *root, &IDENTITY);
+        }
+
+        Ok(GltfModel {
+            meshes,
+            materials,
+            textures,
+            nodes,
+            scene_roots,
+        })
+    }
+
+    /// Nodes reachable from `scene_roots` that reference a mesh, depth first
+    ///
+    /// Nodes outside the scene are skipped, their world transforms are never computed.
+    pub fn mesh_nodes(&self) -> impl Iterator<Item = &GltfNode> {
+        let mut mesh_nodes = Vec::new();
+        let mut stack = self.scene_roots.iter().rev().cloned().collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.mesh.is_some() {
+                mesh_nodes.push(node);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+
+        mesh_nodes.into_iter()
+    }
+}
+
+fn update_world_transforms(nodes: &mut [GltfNode], index: usize, parent: &Matrix4) {
+
+    let world_transform = multiply(parent, &nodes[index].local_transform);
+    nodes[index].world_transform = world_transform;
+
+    for child in nodes[index].children.clone() {
+        update_world_transforms(nodes, child, &world_transform);
+    }
+}
+
+fn texture_reference(texture: gltf::texture::Texture, tex_coord: u32) -> Result<TextureReference, Box<dyn Error>> {
+
+    // @TODO - load the other UV sets once MeshVertex has room for them
+    if tex_coord != 0 {
+        return Err(format!("glTF texture {} uses TEXCOORD_{}, only TEXCOORD_0 is supported", texture.index(), tex_coord).into());
+    }
+
+    Ok(TextureReference {
+        texture: texture.index(),
+        tex_coord,
+    })
+}
+
+fn load_material(material: gltf::Material) -> Result<PbrMaterial, Box<dyn Error>> {
+
+    let pbr = material.pbr_metallic_roughness();
+
+    Ok(PbrMaterial {
+        name: material.name().map(String::from),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| texture_reference(info.texture(), info.tex_coord())).transpose()?,
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr.metallic_roughness_texture().map(|info| texture_reference(info.texture(), info.tex_coord())).transpose()?,
+        normal_texture: material.normal_texture().map(|info| texture_reference(info.texture(), info.tex_coord())).transpose()?,
+        normal_scale: material.normal_texture().map_or(1.0, |info| info.scale()),
+        occlusion_texture: material.occlusion_texture().map(|info| texture_reference(info.texture(), info.tex_coord())).transpose()?,
+        occlusion_strength: material.occlusion_texture().map_or(1.0, |info| info.strength()),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material.emissive_texture().map(|info| texture_reference(info.texture(), info.tex_coord())).transpose()?,
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask { cutoff: material.alpha_cutoff() },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    })
+}
+
+fn to_rgba8(image: &gltf::image::Data) -> Result<Vec<u8>, Box<dyn Error>> {
+    use gltf::image::Format;
+
+    let pixels = &image.pixels;
+
+    Ok(match image.format {
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::R8G8B8 => pixels.chunks_exact(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        Format::B8G8R8A8 => pixels.chunks_exact(4).flat_map(|p| vec![p[2], p[1], p[0], p[3]]).collect(),
+        Format::B8G8R8 => pixels.chunks_exact(3).flat_map(|p| vec![p[2], p[1], p[0], 255]).collect(),
+        Format::R8G8 => pixels.chunks_exact(2).flat_map(|p| vec![p[0], p[1], 0, 255]).collect(),
+        Format::R8 => pixels.iter().flat_map(|p| vec![*p, *p, *p, 255]).collect(),
+        // 16 bit formats are stored little endian, keep the most significant byte
+        Format::R16G16B16A16 => pixels.chunks_exact(8).flat_map(|p| vec![p[1], p[3], p[5], p[7]]).collect(),
+        Format::R16G16B16 => pixels.chunks_exact(6).flat_map(|p| vec![p[1], p[3], p[5], 255]).collect(),
+        Format::R16G16 => pixels.chunks_exact(4).flat_map(|p| vec![p[1], p[3], 0, 255]).collect(),
+        Format::R16 => pixels.chunks_exact(2).flat_map(|p| vec![p[1], p[1], p[1], 255]).collect(),
+    })
+}
+
+/// Uploads one texture per glTF texture, picking sRGB for textures used as color data
+fn load_textures(
+    upload_manager: &mut UploadManager,
+    document: &gltf::Document,
+    images: &[gltf::image::Data],
+    materials: &[PbrMaterial],
+) -> Result<Vec<Texture>, Box<dyn Error>> {
+
+    let is_color_texture = |index: usize| materials.iter().any(|material| {
+        material.base_color_texture.map(|reference| reference.texture) == Some(index)
+        || material.emissive_texture.map(|reference| reference.texture) == Some(index)
+    });
+
+    document
+        .textures()
+        .map(|texture| -> Result<Texture, Box<dyn Error>> {
+            let image = images.get(texture.source().index()).ok_or("glTF texture references a missing image")?;
+
+            let color_space = if is_color_texture(texture.index()) {
+                TextureColorSpace::Srgb
+            } else {
+                TextureColorSpace::Linear
+            };
+
+            // @TODO - honour glTF sampler wrap/filter modes
+            Texture::from_rgba8(upload_manager, image.width, image.height, &to_rgba8(image)?, color_space)
+        })
+        .collect()
+}
+
+fn load_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Result<MeshData, Box<dyn Error>> {
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+    let mut vertices = reader
+        .read_positions()
+        .ok_or("glTF primitive has no positions")?
+        .map(|position| MeshVertex {
+            position,
+            ..MeshVertex::default()
+        })
+        .collect::<Vec<_>>();
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+
+    let has_normals = match reader.read_normals() {
+        Some(normals) => {
+            vertices.iter_mut().zip(normals).for_each(|(vertex, normal)| vertex.normal = normal);
+            true
+        },
+        None => false,
+    };
+
+    let has_uvs = match reader.read_tex_coords(0) {
+        Some(uvs) => {
+            vertices.iter_mut().zip(uvs.into_f32()).for_each(|(vertex, uv)| vertex.uv = uv);
+            true
+        },
+        None => false,
+    };
+
+    let has_tangents = match reader.read_tangents() {
+        Some(tangents) => {
+            vertices.iter_mut().zip(tangents).for_each(|(vertex, tangent)| vertex.tangent = tangent);
+            true
+        },
+        None => false,
+    };
+
+    let mut data = MeshData { vertices, indices };
+
+    if !has_normals {
+        data.compute_smooth_normals();
+    }
+
+    if !has_tangents && has_uvs {
+        data.compute_tangents();
+    }
+
+    Ok(data)
+}
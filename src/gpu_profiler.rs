@@ -0,0 +1,509 @@
+use std::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    error::Error,
+    mem,
+    sync::Arc,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use vk_sys as vk;
+
+use imgui::{
+    im_str,
+    Condition,
+    Ui,
+    Window,
+};
+
+use vulkano::{
+    buffer::BufferAccess,
+    command_buffer::{
+        pool::standard::{
+            StandardCommandPoolAlloc,
+            StandardCommandPoolBuilder,
+        },
+        sys::{
+            Flags,
+            Kind,
+            KindOcclusionQuery,
+            UnsafeCommandBuffer,
+            UnsafeCommandBufferBuilder,
+        },
+        AutoCommandBufferBuilder,
+        CommandBuffer,
+        CommandBufferExecError,
+    },
+    device::{
+        Device,
+        DeviceOwned,
+        Queue,
+    },
+    image::{
+        ImageAccess,
+        ImageLayout,
+    },
+    query::{
+        QueryPipelineStatisticFlags,
+        QueryType,
+        UnsafeQueryPool,
+    },
+    sync::{
+        AccessCheckError,
+        AccessFlagBits,
+        GpuFuture,
+        PipelineStages,
+    },
+    VulkanObject,
+};
+
+/// Samples kept per scope for the rolling average
+const HISTORY: usize = 120;
+
+/// Timestamps per frame, two per scope
+const MAX_QUERIES: u32 = 256;
+
+/// A scope recorded in a frame, `begin_query` and `end_query` index that frame's timestamps
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedScope {
+    pub name: String,
+    pub depth: usize,
+    pub begin_query: u32,
+    pub end_query: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeTiming {
+    pub name: String,
+    pub depth: usize,
+    pub milliseconds: f64,
+}
+
+/// Converts raw timestamps into per scope durations
+///
+/// Only the low `valid_bits` of each timestamp are meaningful, so differences are taken modulo
+/// that width which also handles the counter wrapping between the two queries.  Unclosed scopes
+/// are dropped.
+pub fn resolve_frame(
+    scopes: &[RecordedScope],
+    timestamps: &[u64],
+    timestamp_period: f32,
+    valid_bits: u32,
+) -> Vec<ScopeTiming> {
+
+    let mask = if valid_bits >= 64 { u64::MAX } else { (1u64 << valid_bits) - 1 };
+
+    scopes
+        .iter()
+        .filter_map(|scope| {
+            let begin = *timestamps.get(scope.begin_query as usize)?;
+            let end = *timestamps.get(scope.end_query? as usize)?;
+            let ticks = (end & mask).wrapping_sub(begin & mask) & mask;
+
+            Some(ScopeTiming {
+                name: scope.name.clone(),
+                depth: scope.depth,
+                milliseconds: ticks as f64 * timestamp_period as f64 / 1_000_000.0,
+            })
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct FrameQueries {
+    /// `None` when timestamps are unsupported
+    pool: Option<Arc<UnsafeQueryPool>>,
+    scopes: Vec<RecordedScope>,
+    open_scopes: Vec<usize>,
+    next_query: u32,
+}
+
+#[derive(Default)]
+struct ScopeHistory {
+    depth: usize,
+    /// Order the scope was first seen in, so reports follow recording order
+    order: usize,
+    samples: VecDeque<f64>,
+}
+
+impl ScopeHistory {
+
+    fn average(&self) -> f64 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.samples.iter().sum::<f64>() / self.samples.len() as f64
+        }
+    }
+}
+
+/// Secondary command buffer holding query commands, which `AutoCommandBufferBuilder` has no
+/// methods for
+///
+/// The query pool isn't a resource vulkano tracks, so there is nothing to lock or check.
+struct QueryCommands {
+    device: Arc<Device>,
+    inner: UnsafeCommandBuffer<StandardCommandPoolAlloc>,
+    _pool: Arc<UnsafeQueryPool>,
+}
+
+unsafe impl DeviceOwned for QueryCommands {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+unsafe impl CommandBuffer for QueryCommands {
+    type PoolAlloc = StandardCommandPoolAlloc;
+
+    fn inner(&self) -> &UnsafeCommandBuffer<StandardCommandPoolAlloc> {
+        &self.inner
+    }
+
+    fn lock_submit(&self, _future: &dyn GpuFuture, _queue: &Queue) -> Result<(), CommandBufferExecError> {
+        Ok(())
+    }
+
+    unsafe fn unlock(&self) {
+    }
+
+    fn check_buffer_access(
+        &self,
+        _buffer: &dyn BufferAccess,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+
+    fn check_image_access(
+        &self,
+        _image: &dyn ImageAccess,
+        _layout: ImageLayout,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+}
+
+/// Measures GPU time spent in named scopes of the frame's command buffers
+///
+/// Timestamps are written into a query pool per frame in flight and read back when that frame
+/// slot comes around again, so results lag `frames_in_flight` frames behind, exactly like
+/// `UniformBufferRing`.  Per scope rolling averages are logged every `report_interval` and can
+/// be shown in the UI overlay with `draw_ui`.
+///
+/// Each query command is recorded in its own secondary command buffer and executed in the
+/// frame's primary one, so scopes must begin and end outside render passes.
+///
+/// Only timestamps are measured.  Pipeline statistics queries have to begin and end in the
+/// command buffer that records the measured work, and `AutoCommandBufferBuilder` can't record
+/// raw commands in between its own, so they are out of scope for this profiler.
+pub struct GpuProfiler {
+    device: Arc<Device>,
+    queue_family: u32,
+    enabled: bool,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    valid_bits: u32,
+    frames: Vec<FrameQueries>,
+    current_frame: usize,
+    history: BTreeMap<String, ScopeHistory>,
+    pub report_interval: Option<Duration>,
+    last_report: Instant,
+}
+
+impl GpuProfiler {
+
+    /// Profiles command buffers submitted to `queue`'s family
+    pub fn new(queue: &Arc<Queue>, frames_in_flight: usize) -> Result<GpuProfiler, Box<dyn Error>> {
+
+        if frames_in_flight == 0 {
+            return Err("GPU profiler requires at least one frame".into());
+        }
+
+        let device = queue.device().clone();
+
+        let valid_bits = queue.family().timestamp_valid_bits().unwrap_or(0);
+        let enabled = valid_bits != 0;
+        if !enabled {
+            warn!("Timestamp queries are not supported on the profiled queue, GPU profiling disabled");
+        }
+
+        let frames = (0..frames_in_flight)
+            .map(|_| -> Result<FrameQueries, Box<dyn Error>> {
+                let pool = if enabled {
+                    Some(Arc::new(UnsafeQueryPool::new(device.clone(), QueryType::Timestamp, MAX_QUERIES)?))
+                } else {
+                    None
+                };
+                Ok(FrameQueries { pool, ..FrameQueries::default() })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(GpuProfiler {
+            timestamp_period: device.physical_device().limits().timestamp_period(),
+            queue_family: queue.family().id(),
+            device,
+            enabled,
+            valid_bits,
+            frames,
+            current_frame: 0,
+            history: BTreeMap::new(),
+            report_interval: Some(Duration::from_secs(5)),
+            last_report: Instant::now(),
+        })
+    }
+
+    /// Must be called once per frame, outside any render pass, before any scopes are recorded
+    ///
+    /// Collects the results of the frame that last used this slot, which the caller must have
+    /// waited on (as it would before reusing any per frame resource).
+    pub fn begin_frame(&mut self, builder: &mut AutoCommandBufferBuilder) -> Result<(), Box<dyn Error>> {
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
+
+        if let Some(timestamps) = self.read_results(self.current_frame) {
+            let timings = resolve_frame(
+                &self.frames[self.current_frame].scopes,
+                &timestamps,
+                self.timestamp_period,
+                self.valid_bits,
+            );
+            self.add_timings(timings);
+        }
+
+        let frame = &mut self.frames[self.current_frame];
+        frame.scopes.clear();
+        frame.open_scopes.clear();
+        frame.next_query = 0;
+
+        self.record_queries(builder, |commands, pool| unsafe {
+            commands.reset_query_pool(pool.queries_range(0, MAX_QUERIES).ok_or("Invalid query range")?);
+            Ok(())
+        })?;
+
+        self.report_if_due();
+
+        Ok(())
+    }
+
+    /// Starts a named scope, scopes may nest but must be closed in reverse order
+    pub fn begin_scope(&mut self, builder: &mut AutoCommandBufferBuilder, name: &str) -> Result<(), Box<dyn Error>> {
+        let frame = &mut self.frames[self.current_frame];
+        let query = frame.next_query;
+        frame.next_query += 1;
+
+        let depth = frame.open_scopes.len();
+        frame.open_scopes.push(frame.scopes.len());
+        frame.scopes.push(RecordedScope { name: name.to_string(), depth, begin_query: query, end_query: None });
+
+        self.write_timestamp(builder, query)
+    }
+
+    pub fn end_scope(&mut self, builder: &mut AutoCommandBufferBuilder) -> Result<(), Box<dyn Error>> {
+        let frame = &mut self.frames[self.current_frame];
+
+        let scope = match frame.open_scopes.pop() {
+            Some(scope) => scope,
+            None => {
+                warn!("GpuProfiler::end_scope called without a matching begin_scope");
+                return Ok(());
+            }
+        };
+
+        let query = frame.next_query;
+        frame.next_query += 1;
+        frame.scopes[scope].end_query = Some(query);
+
+        self.write_timestamp(builder, query)
+    }
+
+    /// Wraps `record` in a scope
+    pub fn scope<F, R>(&mut self, builder: &mut AutoCommandBufferBuilder, name: &str, record: F) -> Result<R, Box<dyn Error>>
+    where
+        F: FnOnce(&mut AutoCommandBufferBuilder) -> R,
+    {
+        self.begin_scope(builder, name)?;
+        let result = record(builder);
+        self.end_scope(builder)?;
+        Ok(result)
+    }
+
+    /// Timestamps once all previous commands have finished
+    fn write_timestamp(&mut self, builder: &mut AutoCommandBufferBuilder, query: u32) -> Result<(), Box<dyn Error>> {
+        if query >= MAX_QUERIES {
+            if query == MAX_QUERIES {
+                warn!("GPU profiler ran out of queries, later scopes this frame are not timed");
+            }
+            return Ok(());
+        }
+
+        self.record_queries(builder, |commands, pool| unsafe {
+            commands.write_timestamp(
+                pool.query(query).ok_or("Invalid query index")?,
+                PipelineStages { bottom_of_pipe: true, ..PipelineStages::none() },
+            );
+            Ok(())
+        })
+    }
+
+    /// Records `record` into a secondary command buffer and executes it in `builder`
+    fn record_queries<F>(&self, builder: &mut AutoCommandBufferBuilder, record: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>, &UnsafeQueryPool) -> Result<(), Box<dyn Error>>,
+    {
+        let pool = match &self.frames[self.current_frame].pool {
+            Some(pool) => pool.clone(),
+            None => return Ok(()),
+        };
+
+        let family = self.device
+            .physical_device()
+            .queue_family_by_id(self.queue_family)
+            .ok_or("Profiled queue family not found")?;
+        let command_pool = Device::standard_command_pool(&self.device, family);
+
+        let inner = unsafe {
+            let mut commands = UnsafeCommandBufferBuilder::new(
+                &command_pool,
+                Kind::secondary(KindOcclusionQuery::Forbidden, QueryPipelineStatisticFlags::none()),
+                Flags::OneTimeSubmit,
+            )?;
+            record(&mut commands, &pool)?;
+            commands.build()?
+        };
+
+        // @TODO - vulkano doesn't validate execute_commands yet, the query commands are only
+        // valid outside of render passes
+        unsafe {
+            builder.execute_commands(QueryCommands { device: self.device.clone(), inner, _pool: pool })?;
+        }
+
+        Ok(())
+    }
+
+    /// Timestamps of the frame that last used `frame`, `None` if it recorded none or the GPU
+    /// hasn't finished it
+    fn read_results(&self, frame: usize) -> Option<Vec<u64>> {
+        let frame = &self.frames[frame];
+        let pool = frame.pool.as_ref()?;
+        let query_count = frame.next_query.min(MAX_QUERIES);
+        if query_count == 0 {
+            return None;
+        }
+
+        let mut timestamps = vec![0u64; query_count as usize];
+        let result = unsafe {
+            self.device.pointers().GetQueryPoolResults(
+                self.device.internal_object(),
+                pool.internal_object(),
+                0,
+                query_count,
+                timestamps.len() * mem::size_of::<u64>(),
+                timestamps.as_mut_ptr() as *mut _,
+                mem::size_of::<u64>() as u64,
+                vk::QUERY_RESULT_64_BIT,
+            )
+        };
+
+        // NOT_READY when the frame is still in flight, treated like any other failure
+        if result == vk::SUCCESS { Some(timestamps) } else { None }
+    }
+
+    fn add_timings(&mut self, timings: Vec<ScopeTiming>) {
+        for timing in timings {
+            let order = self.history.len();
+            let history = self.history.entry(timing.name).or_insert_with(|| ScopeHistory { order, ..ScopeHistory::default() });
+
+            if history.samples.len() == HISTORY {
+                history.samples.pop_front();
+            }
+            history.samples.push_back(timing.milliseconds);
+            history.depth = timing.depth;
+        }
+    }
+
+    /// Rolling average in milliseconds per scope, in recording order
+    pub fn averages(&self) -> Vec<(&str, usize, f64)> {
+        let mut averages = self.history
+            .iter()
+            .map(|(name, history)| (history.order, name.as_str(), history.depth, history.average()))
+            .collect::<Vec<_>>();
+        averages.sort_by_key(|(order, ..)| *order);
+        averages.into_iter().map(|(_, name, depth, average)| (name, depth, average)).collect()
+    }
+
+    fn report_if_due(&mut self) {
+        let interval = match self.report_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        if self.history.is_empty() || self.last_report.elapsed() < interval {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        for (name, depth, average) in self.averages() {
+            info!("GPU {:indent$}{}: {:.3} ms", "", name, average, indent = depth * 2);
+        }
+    }
+
+    /// Call from within `UiOverlay::record`'s `build_ui`
+    pub fn draw_ui(&self, ui: &Ui) {
+        Window::new(im_str!("GPU Profiler"))
+            .position([10.0, 200.0], Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(ui, || {
+                if !self.enabled {
+                    ui.text("Timestamp queries not supported");
+                    return;
+                }
+                if self.history.is_empty() {
+                    ui.text("No results yet");
+                }
+                for (name, depth, average) in self.averages() {
+                    ui.text(format!("{:indent$}{}: {:.3} ms", "", name, average, indent = depth * 2));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(name: &str, depth: usize, begin_query: u32, end_query: Option<u32>) -> RecordedScope {
+        RecordedScope { name: name.to_string(), depth, begin_query, end_query }
+    }
+
+    #[test]
+    fn resolved_scopes_drop_unclosed_ones() {
+        let scopes = [scope("frame", 0, 0, Some(3)), scope("shadows", 1, 1, Some(2)), scope("unclosed", 1, 4, None)];
+        let timestamps = [1_000, 1_500, 2_500, 5_000, 6_000];
+
+        // 2ns per tick
+        let timings = resolve_frame(&scopes, &timestamps, 2.0, 64);
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].name, "frame");
+        assert!((timings[0].milliseconds - 0.008).abs() < 1e-9);
+        assert_eq!(timings[1].depth, 1);
+        assert!((timings[1].milliseconds - 0.002).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resolved_timestamps_wrap_at_valid_bits() {
+        let scopes = [scope("wrapped", 0, 0, Some(1))];
+
+        // 16 bit counter wrapping from 0xFFF0 to 0x0010, high garbage bits are ignored
+        let timestamps = [0xABCD_FFF0, 0x1234_0010];
+        let timings = resolve_frame(&scopes, &timestamps, 1_000_000.0, 16);
+        assert_eq!(timings[0].milliseconds, 32.0);
+    }
+}
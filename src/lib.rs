@@ -1,14 +1,39 @@
 #[macro_export]
 #[macro_use] extern crate log;
+#[macro_use] extern crate lazy_static;
 
 pub extern crate vulkano;
 pub extern crate vulkano_shaders;
 
 pub mod logger;
+pub mod cpu_profiler;
 pub mod app;
+pub mod cli;
 pub mod vulkan_app;
+pub mod math;
+pub mod camera;
+pub mod shader_reflection;
+pub mod compute;
+pub mod uniform_ring;
+pub mod upload;
+pub mod texture;
+pub mod mesh;
+pub mod gltf_loader;
+pub mod obj_loader;
+pub mod scene;
+pub mod ui_overlay;
+pub mod debug_draw;
+pub mod text;
+pub mod gpu_profiler;
+pub mod benchmark;
+pub mod parallel_recorder;
+pub mod render_graph;
+pub mod screenshot;
+pub mod samples;
 pub mod vulkan_device_factories{
     pub mod single_graphics_queue;
+    pub mod graphics_and_transfer_queues;
+    pub mod graphics_and_compute_queues;
 }
 
 
@@ -1,5 +1,11 @@
+use log::LevelFilter;
+
 pub fn init() {
-    target_logger::init();
+    init_with_level(LevelFilter::Trace);
+}
+
+pub fn init_with_level(level: LevelFilter) {
+    target_logger::init(level);
 }
 
 #[cfg(not(target_os="android"))]
@@ -11,7 +17,7 @@ mod target_logger {
     use log4rs::encode::pattern::PatternEncoder;
     use log4rs::config::{Appender, Config, Root};
 
-    pub fn init() {
+    pub fn init(level: LevelFilter) {
 
         let logfile = FileAppender::builder()
             .encoder(Box::new(PatternEncoder::new("{l} - {m}\n")))
@@ -21,7 +27,7 @@ mod target_logger {
         let config = Config::builder()
             .appender(Appender::builder().build("logfile", Box::new(logfile)))
             .build(Root::builder()
-                       .appender("logfile") .build(LevelFilter::Trace))
+                       .appender("logfile") .build(level))
                        .expect("unable to initialize log");
 
         log4rs::init_config(config)
@@ -34,13 +40,14 @@ mod target_logger {
 mod target_logger {
 
     extern crate android_logger;
-    use log::Level;
+    use log::{Level, LevelFilter};
     use android_logger::Config;
 
-    pub fn init() {
+    pub fn init(level: LevelFilter) {
 
+        // android_logger has no "off" level so the least verbose one is used instead
         android_logger::init_once(
             Config::default()
-                .with_min_level(Level::Trace));
+                .with_min_level(level.to_level().unwrap_or(Level::Error)));
     }
 }
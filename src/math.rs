@@ -0,0 +1,292 @@
+//! Minimal vector/matrix helpers on plain arrays so they can be copied straight into buffers
+//!
+//! Matrices are column major (`m[column][row]`), matching GLSL and glTF.  World space is right
+//! handed with +Y up and cameras looking down -Z; the projection functions map to Vulkan clip
+//! space, which has Y pointing down and depth in 0..1.
+
+pub type Vector3 = [f32; 3];
+pub type Vector4 = [f32; 4];
+pub type Matrix4 = [[f32; 4]; 4];
+
+pub const IDENTITY: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn add(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(v: Vector3, s: f32) -> Vector3 {
+    [v[0] * s, v[1] * s, v[2] * s]
+}
+
+pub fn dot(a: Vector3, b: Vector3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vector3, b: Vector3) -> Vector3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(v: Vector3) -> f32 {
+    dot(v, v).sqrt()
+}
+
+/// Returns the zero vector for degenerate input rather than NaNs
+pub fn normalize(v: Vector3) -> Vector3 {
+    let length = length(v);
+    if length > f32::EPSILON {
+        scale(v, 1.0 / length)
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+/// Column major multiply (`a * b`)
+pub fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut result = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+pub fn transform_vector4(m: &Matrix4, v: Vector4) -> Vector4 {
+    let mut result = [0.0; 4];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = (0..4).map(|column| m[column][row] * v[column]).sum();
+    }
+    result
+}
+
+/// Transforms a point (w = 1) without the perspective divide
+pub fn transform_point(m: &Matrix4, p: Vector3) -> Vector3 {
+    let result = transform_vector4(m, [p[0], p[1], p[2], 1.0]);
+    [result[0], result[1], result[2]]
+}
+
+pub fn translation(offset: Vector3) -> Matrix4 {
+    let mut m = IDENTITY;
+    m[3] = [offset[0], offset[1], offset[2], 1.0];
+    m
+}
+
+pub fn scaling(factors: Vector3) -> Matrix4 {
+    let mut m = IDENTITY;
+    m[0][0] = factors[0];
+    m[1][1] = factors[1];
+    m[2][2] = factors[2];
+    m
+}
+
+/// Rotation from a unit quaternion stored as [x, y, z, w] (glTF order)
+pub fn rotation(q: Vector4) -> Matrix4 {
+    let [x, y, z, w] = q;
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w), 0.0],
+        [2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w), 0.0],
+        [2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Translation * rotation * scale, the same composition glTF uses
+pub fn from_translation_rotation_scale(t: Vector3, r: Vector4, s: Vector3) -> Matrix4 {
+    multiply(&translation(t), &multiply(&rotation(r), &scaling(s)))
+}
+
+pub fn transpose(m: &Matrix4) -> Matrix4 {
+    let mut result = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = m[row][column];
+        }
+    }
+    result
+}
+
+/// General 4x4 inverse, `None` for singular matrices
+pub fn inverse(m: &Matrix4) -> Option<Matrix4> {
+    // Flattened column major, so a[column * 4 + row]
+    let mut a = [0.0f32; 16];
+    for column in 0..4 {
+        for row in 0..4 {
+            a[column * 4 + row] = m[column][row];
+        }
+    }
+
+    let mut inv = [0.0f32; 16];
+
+    inv[0] = a[5] * a[10] * a[15] - a[5] * a[11] * a[14] - a[9] * a[6] * a[15] + a[9] * a[7] * a[14] + a[13] * a[6] * a[11] - a[13] * a[7] * a[10];
+    inv[4] = -a[4] * a[10] * a[15] + a[4] * a[11] * a[14] + a[8] * a[6] * a[15] - a[8] * a[7] * a[14] - a[12] * a[6] * a[11] + a[12] * a[7] * a[10];
+    inv[8] = a[4] * a[9] * a[15] - a[4] * a[11] * a[13] - a[8] * a[5] * a[15] + a[8] * a[7] * a[13] + a[12] * a[5] * a[11] - a[12] * a[7] * a[9];
+    inv[12] = -a[4] * a[9] * a[14] + a[4] * a[10] * a[13] + a[8] * a[5] * a[14] - a[8] * a[6] * a[13] - a[12] * a[5] * a[10] + a[12] * a[6] * a[9];
+    inv[1] = -a[1] * a[10] * a[15] + a[1] * a[11] * a[14] + a[9] * a[2] * a[15] - a[9] * a[3] * a[14] - a[13] * a[2] * a[11] + a[13] * a[3] * a[10];
+    inv[5] = a[0] * a[10] * a[15] - a[0] * a[11] * a[14] - a[8] * a[2] * a[15] + a[8] * a[3] * a[14] + a[12] * a[2] * a[11] - a[12] * a[3] * a[10];
+    inv[9] = -a[0] * a[9] * a[15] + a[0] * a[11] * a[13] + a[8] * a[1] * a[15] - a[8] * a[3] * a[13] - a[12] * a[1] * a[11] + a[12] * a[3] * a[9];
+    inv[13] = a[0] * a[9] * a[14] - a[0] * a[10] * a[13] - a[8] * a[1] * a[14] + a[8] * a[2] * a[13] + a[12] * a[1] * a[10] - a[12] * a[2] * a[9];
+    inv[2] = a[1] * a[6] * a[15] - a[1] * a[7] * a[14] - a[5] * a[2] * a[15] + a[5] * a[3] * a[14] + a[13] * a[2] * a[7] - a[13] * a[3] * a[6];
+    inv[6] = -a[0] * a[6] * a[15] + a[0] * a[7] * a[14] + a[4] * a[2] * a[15] - a[4] * a[3] * a[14] - a[12] * a[2] * a[7] + a[12] * a[3] * a[6];
+    inv[10] = a[0] * a[5] * a[15] - a[0] * a[7] * a[13] - a[4] * a[1] * a[15] + a[4] * a[3] * a[13] + a[12] * a[1] * a[7] - a[12] * a[3] * a[5];
+    inv[14] = -a[0] * a[5] * a[14] + a[0] * a[6] * a[13] + a[4] * a[1] * a[14] - a[4] * a[2] * a[13] - a[12] * a[1] * a[6] + a[12] * a[2] * a[5];
+    inv[3] = -a[1] * a[6] * a[11] + a[1] * a[7] * a[10] + a[5] * a[2] * a[11] - a[5] * a[3] * a[10] - a[9] * a[2] * a[7] + a[9] * a[3] * a[6];
+    inv[7] = a[0] * a[6] * a[11] - a[0] * a[7] * a[10] - a[4] * a[2] * a[11] + a[4] * a[3] * a[10] + a[8] * a[2] * a[7] - a[8] * a[3] * a[6];
+    inv[11] = -a[0] * a[5] * a[11] + a[0] * a[7] * a[9] + a[4] * a[1] * a[11] - a[4] * a[3] * a[9] - a[8] * a[1] * a[7] + a[8] * a[3] * a[5];
+    inv[15] = a[0] * a[5] * a[10] - a[0] * a[6] * a[9] - a[4] * a[1] * a[10] + a[4] * a[2] * a[9] + a[8] * a[1] * a[6] - a[8] * a[2] * a[5];
+
+    let determinant = a[0] * inv[0] + a[1] * inv[4] + a[2] * inv[8] + a[3] * inv[12];
+    // Only exactly singular, an absolute threshold would reject valid matrices with small
+    // scales (e.g. orthographic projections of large scenes)
+    if determinant == 0.0 {
+        return None;
+    }
+
+    let mut result = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = inv[column * 4 + row] / determinant;
+        }
+    }
+    Some(result)
+}
+
+/// Right handed view matrix looking from `eye` towards `target`
+pub fn look_at(eye: Vector3, target: Vector3, up: Vector3) -> Matrix4 {
+    let f = normalize(sub(target, eye));
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+
+    [
+        [s[0], u[0], -f[0], 0.0],
+        [s[1], u[1], -f[1], 0.0],
+        [s[2], u[2], -f[2], 0.0],
+        [-dot(s, eye), -dot(u, eye), dot(f, eye), 1.0],
+    ]
+}
+
+/// Perspective projection to Vulkan clip space
+///
+/// With `reversed_z` the near plane maps to depth 1 and the far plane to 0, which spreads float
+/// precision far more evenly (use a `Greater` depth test and clear depth to 0).  Reversed-Z
+/// also accepts an infinite `far`.
+pub fn perspective(fov_y: f32, aspect_ratio: f32, near: f32, far: f32, reversed_z: bool) -> Matrix4 {
+    let f = 1.0 / (fov_y * 0.5).tan();
+
+    let (a, b) = match (reversed_z, far.is_infinite()) {
+        (true, true) => (0.0, near),
+        (true, false) => (near / (far - near), near * far / (far - near)),
+        (false, _) => (far / (near - far), near * far / (near - far)),
+    };
+
+    [
+        [f / aspect_ratio, 0.0, 0.0, 0.0],
+        [0.0, -f, 0.0, 0.0],
+        [0.0, 0.0, a, -1.0],
+        [0.0, 0.0, b, 0.0],
+    ]
+}
+
+/// Orthographic projection of the view space box to Vulkan clip space
+pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32, reversed_z: bool) -> Matrix4 {
+    let (a, b) = if reversed_z {
+        (1.0 / (far - near), far / (far - near))
+    } else {
+        (-1.0 / (far - near), -near / (far - near))
+    };
+
+    [
+        [2.0 / (right - left), 0.0, 0.0, 0.0],
+        [0.0, -2.0 / (top - bottom), 0.0, 0.0],
+        [0.0, 0.0, a, 0.0],
+        [-(right + left) / (right - left), (top + bottom) / (top - bottom), b, 1.0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(m: &Matrix4, p: Vector3) -> Vector3 {
+        let clip = transform_vector4(m, [p[0], p[1], p[2], 1.0]);
+        [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+    }
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!(length(sub(a, b)) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn multiply_applies_right_hand_side_first() {
+        let result = multiply(&translation([1.0, 2.0, 3.0]), &scaling([2.0, 2.0, 2.0]));
+
+        assert_eq!(result[0][0], 2.0);
+        assert_eq!(result[3], [1.0, 2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let m = multiply(&translation([1.0, -2.0, 3.0]), &multiply(&rotation([0.0, 0.6, 0.0, 0.8]), &scaling([2.0, 3.0, 4.0])));
+        let identity = multiply(&m, &inverse(&m).unwrap());
+
+        for column in 0..4 {
+            for row in 0..4 {
+                assert!((identity[column][row] - IDENTITY[column][row]).abs() < 1e-5);
+            }
+        }
+
+        assert!(inverse(&scaling([1.0, 0.0, 1.0])).is_none());
+    }
+
+    #[test]
+    fn inverse_of_large_orthographic_projection_exists() {
+        let projection = orthographic(-5000.0, 5000.0, -5000.0, 5000.0, 0.1, 10000.0, false);
+        let inverse = inverse(&projection).expect("Tiny determinant isn't singular");
+
+        let clip = [0.5, -0.5, 0.25];
+        assert_close(project(&projection, project(&inverse, clip)), clip);
+    }
+
+    #[test]
+    fn perspective_maps_to_vulkan_clip_space() {
+        let projection = perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 10.0, false);
+
+        assert_close(project(&projection, [0.0, 0.0, -1.0]), [0.0, 0.0, 0.0]);
+        assert_close(project(&projection, [0.0, 0.0, -10.0]), [0.0, 0.0, 1.0]);
+        // +Y in view space is up, which is -Y in Vulkan clip space
+        assert_close(project(&projection, [0.0, 1.0, -1.0]), [0.0, -1.0, 0.0]);
+
+        let reversed = perspective(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 10.0, true);
+        assert_close(project(&reversed, [0.0, 0.0, -1.0]), [0.0, 0.0, 1.0]);
+        assert_close(project(&reversed, [0.0, 0.0, -10.0]), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn orthographic_maps_box_to_clip_space() {
+        let projection = orthographic(-2.0, 2.0, -1.0, 1.0, 0.0, 4.0, false);
+
+        assert_close(project(&projection, [-2.0, 1.0, 0.0]), [-1.0, -1.0, 0.0]);
+        assert_close(project(&projection, [2.0, -1.0, -4.0]), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn look_at_moves_target_onto_negative_z() {
+        let view = look_at([0.0, 0.0, 5.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert_close(transform_point(&view, [0.0, 0.0, 0.0]), [0.0, 0.0, -5.0]);
+
+        let view = look_at([3.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert_close(transform_point(&view, [0.0, 1.0, 0.0]), [0.0, 1.0, -3.0]);
+    }
+}
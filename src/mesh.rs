@@ -0,0 +1,524 @@
+pub mod procedural;
+
+use crate::{
+    math::{
+        cross,
+        dot,
+        normalize,
+        sub,
+    },
+    upload::UploadManager,
+};
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    ops::Range,
+    sync::Arc,
+};
+
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferSlice,
+        ImmutableBuffer,
+        TypedBufferAccess,
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        DrawIndexedIndirectCommand,
+        DrawIndirectCommand,
+        DynamicState,
+    },
+    descriptor::descriptor_set::DescriptorSetsCollection,
+    pipeline::GraphicsPipelineAbstract,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// xyz is the tangent direction, w the bitangent sign
+    pub tangent: [f32; 4],
+}
+
+vulkano::impl_vertex!(MeshVertex, position, normal, uv, tangent);
+
+/// CPU side indexed triangle list
+#[derive(Default, Debug, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize])
+    }
+
+    /// Area weighted average of the face normals sharing each vertex
+    pub fn compute_smooth_normals(&mut self) {
+
+        let mut normals = vec![[0.0f32; 3]; self.vertices.len()];
+
+        for [a, b, c] in self.triangles() {
+            let p0 = self.vertices[a].position;
+            let face_normal = cross(sub(self.vertices[b].position, p0), sub(self.vertices[c].position, p0));
+
+            for &index in [a, b, c].iter() {
+                for axis in 0..3 {
+                    normals[index][axis] += face_normal[axis];
+                }
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normalize(normal);
+        }
+    }
+
+    /// Gives every triangle its own vertices with the face normal, then re-merges identical vertices
+    pub fn compute_flat_normals(&mut self) {
+
+        let mut vertices = Vec::with_capacity(self.indices.len());
+
+        for [a, b, c] in self.triangles() {
+            let (p0, p1, p2) = (self.vertices[a].position, self.vertices[b].position, self.vertices[c].position);
+            let normal = normalize(cross(sub(p1, p0), sub(p2, p0)));
+
+            for &index in [a, b, c].iter() {
+                vertices.push(MeshVertex {
+                    normal,
+                    ..self.vertices[index]
+                });
+            }
+        }
+
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+        self.deduplicate();
+    }
+
+    /// Merges bitwise identical vertices and rewrites the indices to match
+    pub fn deduplicate(&mut self) {
+
+        fn key(vertex: &MeshVertex) -> [u32; 12] {
+            let mut key = [0; 12];
+            let components = vertex.position.iter()
+                .chain(vertex.normal.iter())
+                .chain(vertex.uv.iter())
+                .chain(vertex.tangent.iter());
+
+            for (bits, component) in key.iter_mut().zip(components) {
+                *bits = component.to_bits();
+            }
+            key
+        }
+
+        let mut unique = HashMap::new();
+        let mut vertices = Vec::new();
+
+        let remap = self.vertices
+            .iter()
+            .map(|vertex| *unique.entry(key(vertex)).or_insert_with(|| {
+                vertices.push(*vertex);
+                vertices.len() as u32 - 1
+            }))
+            .collect::<Vec<_>>();
+
+        for index in self.indices.iter_mut() {
+            *index = remap[*index as usize];
+        }
+
+        self.vertices = vertices;
+    }
+
+    /// Per triangle tangents from UV derivatives, accumulated per vertex and orthogonalized against the normal
+    ///
+    /// Requires normals and UVs to already be present
+    pub fn compute_tangents(&mut self) {
+
+        let mut tangents = vec![[0.0f32; 3]; self.vertices.len()];
+        let mut bitangents = vec![[0.0f32; 3]; self.vertices.len()];
+
+        for [a, b, c] in self.triangles() {
+            let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+
+            let edge1 = sub(v1.position, v0.position);
+            let edge2 = sub(v2.position, v0.position);
+            let duv1 = [v1.uv[0] - v0.uv[0], v1.uv[1] - v0.uv[1]];
+            let duv2 = [v2.uv[0] - v0.uv[0], v2.uv[1] - v0.uv[1]];
+
+            let determinant = duv1[0] * duv2[1] - duv2[0] * duv1[1];
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+
+            let tangent = [
+                (edge1[0] * duv2[1] - edge2[0] * duv1[1]) * r,
+                (edge1[1] * duv2[1] - edge2[1] * duv1[1]) * r,
+                (edge1[2] * duv2[1] - edge2[2] * duv1[1]) * r,
+            ];
+            let bitangent = [
+                (edge2[0] * duv1[0] - edge1[0] * duv2[0]) * r,
+                (edge2[1] * duv1[0] - edge1[1] * duv2[0]) * r,
+                (edge2[2] * duv1[0] - edge1[2] * duv2[0]) * r,
+            ];
+
+            for &index in [a, b, c].iter() {
+                for axis in 0..3 {
+                    tangents[index][axis] += tangent[axis];
+                    bitangents[index][axis] += bitangent[axis];
+                }
+            }
+        }
+
+        for (index, vertex) in self.vertices.iter_mut().enumerate() {
+            let normal = vertex.normal;
+
+            // Gram-Schmidt orthogonalize
+            let tangent = tangents[index];
+            let projection = dot(normal, tangent);
+            let tangent = normalize([
+                tangent[0] - normal[0] * projection,
+                tangent[1] - normal[1] * projection,
+                tangent[2] - normal[2] * projection,
+            ]);
+
+            let handedness = if dot(cross(normal, tangent), bitangents[index]) < 0.0 { -1.0 } else { 1.0 };
+
+            vertex.tangent = [tangent[0], tangent[1], tangent[2], handedness];
+        }
+    }
+
+    /// Uploads as a single sub-mesh, using 16 bit indices when the vertex count allows it
+    pub fn upload(&self, upload_manager: &mut UploadManager) -> Result<Mesh, Box<dyn Error>> {
+
+        let vertex_buffer = upload_manager.upload_vertex_buffer(self.vertices.iter().cloned())?;
+
+        let index_buffer = if self.vertices.len() <= u16::MAX as usize + 1 {
+            IndexBuffer::U16(upload_manager.upload_index_buffer(self.indices.iter().map(|&index| index as u16))?)
+        } else {
+            IndexBuffer::U32(upload_manager.upload_index_buffer(self.indices.iter().cloned())?)
+        };
+
+        Ok(Mesh::new(vertex_buffer, Some(index_buffer)))
+    }
+}
+
+#[derive(Clone)]
+pub enum IndexBuffer {
+    U16(Arc<ImmutableBuffer<[u16]>>),
+    U32(Arc<ImmutableBuffer<[u32]>>),
+}
+
+impl IndexBuffer {
+
+    pub fn index_count(&self) -> u32 {
+        match self {
+            IndexBuffer::U16(buffer) => buffer.len() as u32,
+            IndexBuffer::U32(buffer) => buffer.len() as u32,
+        }
+    }
+}
+
+/// A range of indices (or vertices for non-indexed meshes) drawn with a single material
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubMesh {
+    pub range: Range<u32>,
+    pub material: Option<usize>,
+}
+
+/// Device local vertex and optional index buffers split into sub-meshes
+///
+/// Instanced draws bind `instance_buffers` after the vertex buffer, so the pipeline must be
+/// built with a matching vertex definition such as `OneVertexOneInstanceDefinition`.  Vulkano
+/// derives the instance count from the length of the instance buffers.
+#[derive(Clone)]
+pub struct Mesh<V = MeshVertex> {
+    pub vertex_buffer: Arc<ImmutableBuffer<[V]>>,
+    pub index_buffer: Option<IndexBuffer>,
+    pub sub_meshes: Vec<SubMesh>,
+}
+
+fn slice<T>(
+    buffer: &Arc<ImmutableBuffer<[T]>>,
+    range: Range<u32>,
+) -> Result<BufferSlice<[T], Arc<ImmutableBuffer<[T]>>>, Box<dyn Error>>
+where
+    T: Send + Sync + 'static,
+{
+    BufferSlice::from_typed_buffer_access(buffer.clone())
+        .slice(range.start as usize..range.end as usize)
+        .ok_or_else(|| "Draw range lies outside of the mesh buffers".into())
+}
+
+fn vertex_buffers(
+    vertex_buffer: Arc<dyn BufferAccess + Send + Sync>,
+    instance_buffers: &[Arc<dyn BufferAccess + Send + Sync>],
+) -> Vec<Arc<dyn BufferAccess + Send + Sync>> {
+    std::iter::once(vertex_buffer)
+        .chain(instance_buffers.iter().cloned())
+        .collect()
+}
+
+impl<V> Mesh<V>
+where
+    V: Send + Sync + 'static,
+{
+    /// Creates a mesh with a single sub-mesh covering every index (or vertex)
+    pub fn new(vertex_buffer: Arc<ImmutableBuffer<[V]>>, index_buffer: Option<IndexBuffer>) -> Mesh<V> {
+
+        let element_count = match &index_buffer {
+            Some(index_buffer) => index_buffer.index_count(),
+            None => vertex_buffer.len() as u32,
+        };
+
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            sub_meshes: vec![SubMesh {
+                range: 0..element_count,
+                material: None,
+            }],
+        }
+    }
+
+    pub fn with_sub_meshes(mut self, sub_meshes: Vec<SubMesh>) -> Mesh<V> {
+        self.sub_meshes = sub_meshes;
+        self
+    }
+
+    /// Number of indices, or vertices for non-indexed meshes
+    pub fn element_count(&self) -> u32 {
+        match &self.index_buffer {
+            Some(index_buffer) => index_buffer.index_count(),
+            None => self.vertex_buffer.len() as u32,
+        }
+    }
+
+    /// Draws the whole mesh with a single draw call
+    pub fn draw<S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: DescriptorSetsCollection,
+    {
+        self.record(builder, pipeline, dynamic_state, 0..self.element_count(), &[], sets, constants)
+    }
+
+    pub fn draw_sub_mesh<S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        sub_mesh: usize,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: DescriptorSetsCollection,
+    {
+        let range = self.sub_meshes
+            .get(sub_mesh)
+            .ok_or_else(|| format!("Mesh has no sub-mesh {}", sub_mesh))?
+            .range
+            .clone();
+
+        self.record(builder, pipeline, dynamic_state, range, &[], sets, constants)
+    }
+
+    /// Draws the whole mesh once per element of the per-instance attribute buffers
+    pub fn draw_instanced<S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        instance_buffers: &[Arc<dyn BufferAccess + Send + Sync>],
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: DescriptorSetsCollection,
+    {
+        self.record(builder, pipeline, dynamic_state, 0..self.element_count(), instance_buffers, sets, constants)
+    }
+
+    /// One command per sub-mesh for `draw_indirect`
+    ///
+    /// Upload with `UploadManager::upload_buffer` and `BufferUsage::indirect_buffer()`
+    pub fn indirect_commands(&self, instance_count: u32) -> Vec<DrawIndirectCommand> {
+        self.sub_meshes
+            .iter()
+            .map(|sub_mesh| DrawIndirectCommand {
+                vertex_count: sub_mesh.range.end - sub_mesh.range.start,
+                instance_count,
+                first_vertex: sub_mesh.range.start,
+                first_instance: 0,
+            })
+            .collect()
+    }
+
+    /// One command per sub-mesh for `draw_indexed_indirect`
+    pub fn indexed_indirect_commands(&self, instance_count: u32) -> Vec<DrawIndexedIndirectCommand> {
+        self.sub_meshes
+            .iter()
+            .map(|sub_mesh| DrawIndexedIndirectCommand {
+                index_count: sub_mesh.range.end - sub_mesh.range.start,
+                instance_count,
+                first_index: sub_mesh.range.start,
+                vertex_offset: 0,
+                first_instance: 0,
+            })
+            .collect()
+    }
+
+    /// Non-indexed indirect draw, the commands index into the whole vertex buffer
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indirect<Inb, S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        indirect_buffer: Inb,
+        instance_buffers: &[Arc<dyn BufferAccess + Send + Sync>],
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Inb: BufferAccess + TypedBufferAccess<Content = [DrawIndirectCommand]> + Send + Sync + 'static,
+        S: DescriptorSetsCollection,
+    {
+        if self.index_buffer.is_some() {
+            return Err("Indexed meshes must be drawn with draw_indexed_indirect".into());
+        }
+
+        builder.draw_indirect(
+            pipeline,
+            dynamic_state,
+            vertex_buffers(self.vertex_buffer.clone(), instance_buffers),
+            indirect_buffer,
+            sets,
+            constants,
+        )?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect<Inb, S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        indirect_buffer: Inb,
+        instance_buffers: &[Arc<dyn BufferAccess + Send + Sync>],
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Inb: BufferAccess + TypedBufferAccess<Content = [DrawIndexedIndirectCommand]> + Send + Sync + 'static,
+        S: DescriptorSetsCollection,
+    {
+        let vertex_buffers = vertex_buffers(self.vertex_buffer.clone(), instance_buffers);
+
+        match self.index_buffer.as_ref().ok_or("Non-indexed meshes must be drawn with draw_indirect")? {
+            IndexBuffer::U16(index_buffer) => builder.draw_indexed_indirect(
+                pipeline, dynamic_state, vertex_buffers, index_buffer.clone(), indirect_buffer, sets, constants,
+            )?,
+            IndexBuffer::U32(index_buffer) => builder.draw_indexed_indirect(
+                pipeline, dynamic_state, vertex_buffers, index_buffer.clone(), indirect_buffer, sets, constants,
+            )?,
+        };
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record<S, Pc>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        dynamic_state: &DynamicState,
+        range: Range<u32>,
+        instance_buffers: &[Arc<dyn BufferAccess + Send + Sync>],
+        sets: S,
+        constants: Pc,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: DescriptorSetsCollection,
+    {
+        match &self.index_buffer {
+            Some(index_buffer) => {
+                let vertex_buffers = vertex_buffers(self.vertex_buffer.clone(), instance_buffers);
+
+                match index_buffer {
+                    IndexBuffer::U16(index_buffer) => builder.draw_indexed(
+                        pipeline, dynamic_state, vertex_buffers, slice(index_buffer, range)?, sets, constants,
+                    )?,
+                    IndexBuffer::U32(index_buffer) => builder.draw_indexed(
+                        pipeline, dynamic_state, vertex_buffers, slice(index_buffer, range)?, sets, constants,
+                    )?,
+                };
+            }
+            None => {
+                // Without indices the range selects vertices, so bind just that part of the buffer
+                let vertices = Arc::new(slice(&self.vertex_buffer, range)?);
+                let vertex_buffers = vertex_buffers(vertices, instance_buffers);
+
+                builder.draw(pipeline, dynamic_state, vertex_buffers, sets, constants)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> MeshData {
+        let vertex = |x: f32, y: f32| MeshVertex {
+            position: [x, y, 0.0],
+            uv: [x, y],
+            ..MeshVertex::default()
+        };
+
+        MeshData {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0)],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    #[test]
+    fn flat_normals_merge_coplanar_vertices() {
+        let mut mesh = quad();
+        mesh.compute_flat_normals();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn tangents_follow_u_direction() {
+        let mut mesh = quad();
+        mesh.compute_smooth_normals();
+        mesh.compute_tangents();
+
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+}
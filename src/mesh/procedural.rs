@@ -0,0 +1,385 @@
+//! Indexed primitive shapes with normals, tangents and UVs
+//!
+//! All shapes are centred on the origin with +Y up and use counter-clockwise winding when
+//! viewed from outside.  Use `MeshData::upload` to move the result into device local buffers.
+
+use super::{
+    MeshData,
+    MeshVertex,
+};
+
+use crate::math::{
+    cross,
+    normalize,
+    scale,
+};
+
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+};
+
+fn vertex(position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> MeshVertex {
+    MeshVertex {
+        position,
+        normal,
+        uv,
+        .. MeshVertex::default()
+    }
+}
+
+fn with_tangents(mut data: MeshData) -> MeshData {
+    data.compute_tangents();
+    data
+}
+
+/// Axis aligned cube with separate vertices per face so the edges stay sharp
+pub fn cube(size: f32) -> MeshData {
+
+    let half = size * 0.5;
+
+    // (normal, image up) per face, the image right axis follows from them
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ];
+
+    let mut data = MeshData::default();
+
+    for &(normal, up) in faces.iter() {
+        let right = cross(up, normal);
+        let base = data.vertices.len() as u32;
+
+        for &(x, y) in [(-1.0f32, -1.0f32), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            let position = [
+                (normal[0] + right[0] * x + up[0] * y) * half,
+                (normal[1] + right[1] * x + up[1] * y) * half,
+                (normal[2] + right[2] * x + up[2] * y) * half,
+            ];
+            data.vertices.push(vertex(position, normal, [(x + 1.0) * 0.5, (1.0 - y) * 0.5]));
+        }
+
+        data.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    with_tangents(data)
+}
+
+/// Latitude/longitude sphere, `segments` around the equator and `rings` from pole to pole
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+
+    let mut data = MeshData::default();
+
+    for ring in 0..=rings {
+        let theta = PI * ring as f32 / rings as f32;
+
+        for segment in 0..=segments {
+            let phi = 2.0 * PI * segment as f32 / segments as f32;
+            let normal = [theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()];
+
+            data.vertices.push(vertex(
+                scale(normal, radius),
+                normal,
+                [segment as f32 / segments as f32, ring as f32 / rings as f32],
+            ));
+        }
+    }
+
+    let stride = segments + 1;
+
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = ring * stride + segment;
+            let b = a + stride;
+
+            // The first and last rings collapse to a point so only one triangle per quad is needed
+            if ring != 0 {
+                data.indices.extend_from_slice(&[a, a + 1, b + 1]);
+            }
+            if ring != rings - 1 {
+                data.indices.extend_from_slice(&[a, b + 1, b]);
+            }
+        }
+    }
+
+    with_tangents(data)
+}
+
+/// Subdivided icosahedron, giving a far more even triangle distribution than `uv_sphere`
+///
+/// Each subdivision level quadruples the triangle count
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+
+    let mut positions = vec![
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(normalize)
+    .collect::<Vec<_>>();
+
+    let mut triangles = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+
+        let mut midpoint = |a: u32, b: u32| -> u32 {
+            let key = (a.min(b), a.max(b));
+            *midpoints.entry(key).or_insert_with(|| {
+                let (pa, pb) = (positions[a as usize], positions[b as usize]);
+                positions.push(normalize([pa[0] + pb[0], pa[1] + pb[1], pa[2] + pb[2]]));
+                positions.len() as u32 - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Same parameterisation as `uv_sphere`
+    let mut vertices = positions
+        .iter()
+        .map(|&normal| {
+            let u = normal[2].atan2(normal[0]) / (2.0 * PI);
+            let u = if u < 0.0 { u + 1.0 } else { u };
+            let v = normal[1].clamp(-1.0, 1.0).acos() / PI;
+            vertex(scale(normal, radius), normal, [u, v])
+        })
+        .collect::<Vec<_>>();
+
+    // Triangles straddling the u = 0/1 seam would otherwise interpolate across the whole texture,
+    // so give them copies of the low u vertices shifted past 1
+    let mut wrapped = HashMap::new();
+
+    for triangle in triangles.iter_mut() {
+        let us = [
+            vertices[triangle[0] as usize].uv[0],
+            vertices[triangle[1] as usize].uv[0],
+            vertices[triangle[2] as usize].uv[0],
+        ];
+        let max_u = us.iter().cloned().fold(0.0f32, f32::max);
+
+        for (index, &u) in triangle.iter_mut().zip(us.iter()) {
+            if max_u - u > 0.5 {
+                *index = *wrapped.entry(*index).or_insert_with(|| {
+                    let mut copy = vertices[*index as usize];
+                    copy.uv[0] += 1.0;
+                    vertices.push(copy);
+                    vertices.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    with_tangents(MeshData {
+        vertices,
+        indices: triangles.iter().flat_map(|triangle| triangle.iter().cloned()).collect(),
+    })
+}
+
+/// Flat grid in the XZ plane facing +Y, UVs span the whole plane once
+pub fn plane(width: f32, depth: f32, x_segments: u32, z_segments: u32) -> MeshData {
+
+    let x_segments = x_segments.max(1);
+    let z_segments = z_segments.max(1);
+
+    let mut data = MeshData::default();
+
+    for z in 0..=z_segments {
+        for x in 0..=x_segments {
+            let u = x as f32 / x_segments as f32;
+            let v = z as f32 / z_segments as f32;
+
+            data.vertices.push(vertex(
+                [(u - 0.5) * width, 0.0, (v - 0.5) * depth],
+                [0.0, 1.0, 0.0],
+                [u, v],
+            ));
+        }
+    }
+
+    let stride = x_segments + 1;
+
+    for z in 0..z_segments {
+        for x in 0..x_segments {
+            let a = z * stride + x;
+            let c = a + stride;
+            data.indices.extend_from_slice(&[a, c, c + 1, a, c + 1, a + 1]);
+        }
+    }
+
+    with_tangents(data)
+}
+
+/// Capped cylinder along the Y axis
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+
+    let segments = segments.max(3);
+    let half_height = height * 0.5;
+
+    let mut data = MeshData::default();
+
+    // Side, top row then bottom row
+    for &(y, v) in [(half_height, 0.0), (-half_height, 1.0)].iter() {
+        for segment in 0..=segments {
+            let phi = 2.0 * PI * segment as f32 / segments as f32;
+            let normal = [phi.cos(), 0.0, phi.sin()];
+
+            data.vertices.push(vertex(
+                [normal[0] * radius, y, normal[2] * radius],
+                normal,
+                [segment as f32 / segments as f32, v],
+            ));
+        }
+    }
+
+    let stride = segments + 1;
+
+    for segment in 0..segments {
+        let a = segment;
+        let b = a + stride;
+        data.indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+    }
+
+    // Caps, each a fan around its own centre vertex
+    for &(y, normal_y) in [(half_height, 1.0f32), (-half_height, -1.0f32)].iter() {
+        let centre = data.vertices.len() as u32;
+        data.vertices.push(vertex([0.0, y, 0.0], [0.0, normal_y, 0.0], [0.5, 0.5]));
+
+        for segment in 0..=segments {
+            let phi = 2.0 * PI * segment as f32 / segments as f32;
+            let (cos, sin) = (phi.cos(), phi.sin());
+
+            data.vertices.push(vertex(
+                [cos * radius, y, sin * radius],
+                [0.0, normal_y, 0.0],
+                [0.5 + 0.5 * cos, 0.5 + 0.5 * sin],
+            ));
+        }
+
+        for segment in 0..segments {
+            let k = centre + 1 + segment;
+            if normal_y > 0.0 {
+                data.indices.extend_from_slice(&[centre, k + 1, k]);
+            } else {
+                data.indices.extend_from_slice(&[centre, k, k + 1]);
+            }
+        }
+    }
+
+    with_tangents(data)
+}
+
+/// Torus around the Y axis
+///
+/// `major_radius` is the distance from the centre to the middle of the tube and
+/// `minor_radius` the radius of the tube itself
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> MeshData {
+
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+
+    let mut data = MeshData::default();
+
+    for major in 0..=major_segments {
+        let phi = 2.0 * PI * major as f32 / major_segments as f32;
+        let centre = [phi.cos() * major_radius, 0.0, phi.sin() * major_radius];
+
+        for minor in 0..=minor_segments {
+            let theta = 2.0 * PI * minor as f32 / minor_segments as f32;
+            let normal = [theta.cos() * phi.cos(), theta.sin(), theta.cos() * phi.sin()];
+
+            data.vertices.push(vertex(
+                [
+                    centre[0] + normal[0] * minor_radius,
+                    centre[1] + normal[1] * minor_radius,
+                    centre[2] + normal[2] * minor_radius,
+                ],
+                normal,
+                [major as f32 / major_segments as f32, minor as f32 / minor_segments as f32],
+            ));
+        }
+    }
+
+    let stride = minor_segments + 1;
+
+    for major in 0..major_segments {
+        for minor in 0..minor_segments {
+            let a = major * stride + minor;
+            let c = a + stride;
+            data.indices.extend_from_slice(&[a, a + 1, c, a + 1, c + 1, c]);
+        }
+    }
+
+    with_tangents(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{dot, sub};
+
+    /// Every face normal agrees with the vertex normals, i.e. triangles wind counter-clockwise
+    /// seen from outside
+    fn assert_outward_winding(mesh: &MeshData) {
+        for [a, b, c] in mesh.triangles() {
+            let (v0, v1, v2) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+            let face_normal = cross(sub(v1.position, v0.position), sub(v2.position, v0.position));
+            let vertex_normal = [
+                v0.normal[0] + v1.normal[0] + v2.normal[0],
+                v0.normal[1] + v1.normal[1] + v2.normal[1],
+                v0.normal[2] + v1.normal[2] + v2.normal[2],
+            ];
+            assert!(dot(face_normal, vertex_normal) > 0.0, "Triangle {:?} winds inwards", [a, b, c]);
+        }
+    }
+
+    #[test]
+    fn shapes_wind_outwards() {
+        assert_outward_winding(&cube(1.0));
+        assert_outward_winding(&uv_sphere(1.0, 16, 8));
+        assert_outward_winding(&icosphere(1.0, 2));
+        assert_outward_winding(&plane(2.0, 2.0, 3, 3));
+        assert_outward_winding(&cylinder(1.0, 2.0, 12));
+        assert_outward_winding(&torus(1.0, 0.25, 16, 8));
+    }
+
+    #[test]
+    fn icosphere_subdivision_shares_midpoints() {
+        let mesh = icosphere(2.0, 1);
+        assert_eq!(mesh.indices.len(), 80 * 3);
+
+        for vertex in mesh.vertices.iter() {
+            assert!((dot(vertex.position, vertex.position).sqrt() - 2.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn tangents_are_perpendicular_to_normals() {
+        for vertex in cube(1.0).vertices.iter() {
+            let tangent = [vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]];
+            assert!(dot(tangent, vertex.normal).abs() < 1e-5);
+            assert!((dot(tangent, tangent) - 1.0).abs() < 1e-5);
+        }
+    }
+}
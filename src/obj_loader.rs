@@ -0,0 +1,180 @@
+use crate::{
+    mesh::{
+        Mesh,
+        MeshData,
+        MeshVertex,
+        SubMesh,
+    },
+    upload::UploadManager,
+};
+
+use std::{
+    error::Error,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// How normals are generated for meshes that don't provide them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalGeneration {
+    Flat,
+    Smooth,
+}
+
+/// The subset of MTL parameters the samples make use of
+///
+/// Texture paths are resolved relative to the OBJ file
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub diffuse_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+}
+
+pub struct ObjMesh {
+    pub name: String,
+    pub data: MeshData,
+    /// Index into `ObjModel::materials`
+    pub material: Option<usize>,
+}
+
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+impl ObjModel {
+
+    /// Loads an OBJ file and the MTL libraries it references
+    ///
+    /// Polygons are triangulated and vertices deduplicated into indexed meshes.  Missing or
+    /// unreadable MTL files are logged and the meshes are returned without materials.
+    pub fn load<P: AsRef<Path>>(path: P, normal_generation: NormalGeneration) -> Result<ObjModel, Box<dyn Error>> {
+
+        let path = path.as_ref();
+        let base_directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            .. Default::default()
+        };
+
+        let (models, materials) = tobj::load_obj(path, &options)
+            .map_err(|e| format!("Unable to load {}: {}", path.display(), e))?;
+
+        let materials = match materials {
+            Ok(materials) => materials,
+            Err(e) => {
+                warn!("Unable to load materials for {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+
+        let resolve_texture = |texture: &str| -> Option<PathBuf> {
+            if texture.is_empty() {
+                None
+            } else {
+                Some(base_directory.join(texture))
+            }
+        };
+
+        let materials = materials
+            .into_iter()
+            .map(|material| ObjMaterial {
+                diffuse_texture: resolve_texture(&material.diffuse_texture),
+                normal_texture: resolve_texture(&material.normal_texture),
+                name: material.name,
+                ambient: material.ambient,
+                diffuse: material.diffuse,
+                specular: material.specular,
+                shininess: material.shininess,
+                dissolve: material.dissolve,
+            })
+            .collect::<Vec<_>>();
+
+        let meshes = models
+            .into_iter()
+            .filter(|model| !model.mesh.indices.is_empty())
+            .map(|model| ObjMesh {
+                data: build_mesh_data(&model.mesh, normal_generation),
+                material: model.mesh.material_id.filter(|&material| material < materials.len()),
+                name: model.name,
+            })
+            .collect();
+
+        Ok(ObjModel {
+            meshes,
+            materials,
+        })
+    }
+
+    /// Uploads every mesh, in the same order as `meshes`
+    pub fn upload(&self, upload_manager: &mut UploadManager) -> Result<Vec<Mesh>, Box<dyn Error>> {
+        self.meshes
+            .iter()
+            .map(|mesh| -> Result<Mesh, Box<dyn Error>> {
+                let uploaded = mesh.data.upload(upload_manager)?;
+                let sub_meshes = uploaded.sub_meshes
+                    .iter()
+                    .map(|sub_mesh| SubMesh { material: mesh.material, .. sub_mesh.clone() })
+                    .collect();
+                Ok(uploaded.with_sub_meshes(sub_meshes))
+            })
+            .collect()
+    }
+}
+
+fn build_mesh_data(mesh: &tobj::Mesh, normal_generation: NormalGeneration) -> MeshData {
+
+    let vertex_count = mesh.positions.len() / 3;
+    let has_normals = mesh.normals.len() == vertex_count * 3;
+    let has_uvs = mesh.texcoords.len() == vertex_count * 2;
+
+    let vertices = (0..vertex_count)
+        .map(|index| MeshVertex {
+            position: [mesh.positions[3 * index], mesh.positions[3 * index + 1], mesh.positions[3 * index + 2]],
+            normal: if has_normals {
+                [mesh.normals[3 * index], mesh.normals[3 * index + 1], mesh.normals[3 * index + 2]]
+            } else {
+                [0.0; 3]
+            },
+            // OBJ puts the V origin at the bottom of the image, Vulkan samples from the top
+            uv: if has_uvs {
+                [mesh.texcoords[2 * index], 1.0 - mesh.texcoords[2 * index + 1]]
+            } else {
+                [0.0; 2]
+            },
+            .. MeshVertex::default()
+        })
+        .collect();
+
+    let mut data = MeshData {
+        vertices,
+        indices: mesh.indices.clone(),
+    };
+
+    if !has_normals {
+        match normal_generation {
+            NormalGeneration::Flat => data.compute_flat_normals(),
+            NormalGeneration::Smooth => data.compute_smooth_normals(),
+        }
+    }
+
+    if has_uvs {
+        data.compute_tangents();
+    }
+
+    // tobj's single index mode can still emit duplicates when the source shares a position/uv/normal
+    // triple across faces via different indices
+    data.deduplicate();
+
+    data
+}
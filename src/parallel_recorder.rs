@@ -0,0 +1,277 @@
+use std::{
+    any::Any,
+    error::Error,
+    ops::Range,
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::{
+        mpsc,
+        Arc,
+        Mutex,
+    },
+    thread,
+};
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBuffer,
+        AutoCommandBufferBuilder,
+    },
+    command_buffer::pool::standard::StandardCommandPoolAlloc,
+    device::{
+        DeviceOwned,
+        Queue,
+    },
+    framebuffer::{
+        RenderPassAbstract,
+        Subpass,
+    },
+};
+
+pub type SecondaryCommandBuffer = AutoCommandBuffer<StandardCommandPoolAlloc>;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of threads that run jobs until the pool is dropped
+pub struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl WorkerPool {
+
+    pub fn new(thread_count: usize) -> WorkerPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = (0..thread_count.max(1))
+            .map(|index| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("worker {}", index))
+                    .spawn(move || loop {
+                        // The lock is released before running the job so other workers can take the next one
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            // Sender dropped, the pool is shutting down
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Unable to spawn worker thread")
+            })
+            .collect();
+
+        WorkerPool { sender: Some(sender), threads }
+    }
+
+    /// One thread per available CPU
+    pub fn with_available_parallelism() -> WorkerPool {
+        WorkerPool::new(thread::available_parallelism().map_or(1, |count| count.get()))
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs every job on the pool and returns their results in the order given, blocking until
+    /// all have finished.  A job that panics is caught (the worker carries on) and fails the
+    /// whole map.
+    pub fn map<R: Send + 'static>(&self, jobs: Vec<Box<dyn FnOnce() -> R + Send>>) -> Result<Vec<R>, Box<dyn Error>> {
+        let (result_sender, result_receiver) = mpsc::channel();
+        let count = jobs.len();
+
+        for (index, job) in jobs.into_iter().enumerate() {
+            let result_sender = result_sender.clone();
+            self.sender
+                .as_ref()
+                .expect("Worker pool sender is only taken on drop")
+                .send(Box::new(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(job)).map_err(panic_message);
+                    let _ = result_sender.send((index, result));
+                }))
+                .map_err(|_| "Worker threads exited early")?;
+        }
+
+        drop(result_sender);
+
+        let mut results = result_receiver.iter().collect::<Vec<_>>();
+        if results.len() != count {
+            return Err("Worker threads exited early".into());
+        }
+        results.sort_by_key(|(index, _)| *index);
+
+        results
+            .into_iter()
+            .map(|(_, result)| result.map_err(|message| format!("Worker job panicked: {}", message).into()))
+            .collect()
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic", |message| *message).to_string(),
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.sender = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Splits `len` items into at most `chunk_count` contiguous ranges of near equal size, none
+/// smaller than `min_chunk_size` (except when there are fewer items than that)
+pub fn chunk_ranges(len: usize, chunk_count: usize, min_chunk_size: usize) -> Vec<Range<usize>> {
+    if len == 0 {
+        return Vec::new();
+    }
+
+    let chunk_count = chunk_count.clamp(1, (len / min_chunk_size.max(1)).max(1));
+    let base = len / chunk_count;
+    let remainder = len % chunk_count;
+
+    let mut start = 0;
+    (0..chunk_count)
+        .map(|index| {
+            let size = base + if index < remainder { 1 } else { 0 };
+            let range = start..start + size;
+            start += size;
+            range
+        })
+        .collect()
+}
+
+/// Records a draw list into secondary command buffers across a `WorkerPool`
+///
+/// Each worker allocates from the device's standard command pool, which keeps a separate
+/// pool per thread, so recording needs no synchronisation.  Begin the primary buffer's render
+/// pass with secondary contents and pass the results to `execute`.
+pub struct ParallelRecorder {
+    pool: WorkerPool,
+    /// Chunks smaller than this are not worth a thread's overhead
+    pub min_chunk_size: usize,
+}
+
+impl ParallelRecorder {
+
+    pub fn new(pool: WorkerPool) -> ParallelRecorder {
+        ParallelRecorder { pool, min_chunk_size: 64 }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.pool.thread_count()
+    }
+
+    /// Calls `record` for one contiguous chunk of `items` per worker, in `subpass`
+    pub fn record<T, F>(
+        &self,
+        queue: &Arc<Queue>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+        items: Arc<Vec<T>>,
+        record: Arc<F>,
+    ) -> Result<Vec<SecondaryCommandBuffer>, Box<dyn Error>>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&mut AutoCommandBufferBuilder, &[T]) -> Result<(), Box<dyn Error>> + Send + Sync + 'static,
+    {
+        type RecordJob = Box<dyn FnOnce() -> Result<SecondaryCommandBuffer, String> + Send>;
+
+        let jobs = chunk_ranges(items.len(), self.pool.thread_count(), self.min_chunk_size)
+            .into_iter()
+            .map(|range| -> RecordJob {
+                let queue = queue.clone();
+                let subpass = subpass.clone();
+                let items = items.clone();
+                let record = record.clone();
+
+                // Box<dyn Error> isn't Send, so errors come back as strings
+                Box::new(move || {
+                    let mut builder = AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                        queue.device().clone(),
+                        queue.family(),
+                        subpass,
+                    ).map_err(|e| e.to_string())?;
+
+                    record(&mut builder, &items[range]).map_err(|e| e.to_string())?;
+
+                    builder.build().map_err(|e| e.to_string())
+                })
+            })
+            .collect();
+
+        self.pool
+            .map(jobs)?
+            .into_iter()
+            .map(|result| result.map_err(|e| e.into()))
+            .collect()
+    }
+
+    /// Executes `command_buffers` in the primary buffer's current subpass
+    pub fn execute(
+        builder: &mut AutoCommandBufferBuilder,
+        command_buffers: Vec<SecondaryCommandBuffer>,
+    ) -> Result<(), Box<dyn Error>> {
+        for command_buffer in command_buffers {
+            // @TODO - vulkano doesn't validate execute_commands yet, the buffers are only
+            // correct if recorded for the subpass the primary buffer is in
+            unsafe {
+                builder.execute_commands(command_buffer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_ranges_split_evenly_above_min_size() {
+        assert_eq!(chunk_ranges(0, 4, 1), vec![]);
+        assert_eq!(chunk_ranges(10, 4, 1), vec![0..3, 3..6, 6..8, 8..10]);
+        assert_eq!(chunk_ranges(10, 4, 5), vec![0..5, 5..10]);
+        assert_eq!(chunk_ranges(3, 4, 64), vec![0..3]);
+        assert_eq!(chunk_ranges(5, 0, 0), vec![0..5]);
+    }
+
+    #[test]
+    fn worker_pool_map_keeps_job_order() {
+        let pool = WorkerPool::new(3);
+        assert_eq!(pool.thread_count(), 3);
+
+        let jobs = (0..20u64)
+            .map(|value| -> Box<dyn FnOnce() -> u64 + Send> { Box::new(move || value * value) })
+            .collect();
+
+        assert_eq!(pool.map(jobs).unwrap(), (0..20u64).map(|value| value * value).collect::<Vec<_>>());
+        assert!(pool.map(Vec::<Box<dyn FnOnce() -> u64 + Send>>::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn panicking_job_fails_the_map_and_keeps_the_workers() {
+        let pool = WorkerPool::new(2);
+
+        let jobs: Vec<Box<dyn FnOnce() -> u64 + Send>> = vec![
+            Box::new(|| 1),
+            Box::new(|| panic!("job failed")),
+            Box::new(|| 3),
+        ];
+
+        let error = pool.map(jobs).unwrap_err();
+        assert_eq!(error.to_string(), "Worker job panicked: job failed");
+
+        let jobs = (0..4u64)
+            .map(|value| -> Box<dyn FnOnce() -> u64 + Send> { Box::new(move || value) })
+            .collect();
+        assert_eq!(pool.map(jobs).unwrap(), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,975 @@
+use crate::vulkan_app::AcquiredImage;
+
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    error::Error,
+    sync::Arc,
+};
+
+use winit::window::Window;
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        DynamicState,
+    },
+    device::Device,
+    format::{
+        ClearValue,
+        Format,
+    },
+    framebuffer::{
+        AttachmentDescription,
+        Framebuffer,
+        FramebufferAbstract,
+        LoadOp,
+        PassDependencyDescription,
+        PassDescription,
+        RenderPass,
+        RenderPassAbstract,
+        RenderPassDesc,
+        RenderPassDescClearValues,
+        StoreOp,
+        Subpass,
+    },
+    image::{
+        AttachmentImage,
+        ImageLayout,
+        ImageUsage,
+        ImageViewAccess,
+        SwapchainImage,
+    },
+    pipeline::viewport::Viewport,
+};
+
+/// The acquired swapchain image, always available to passes
+pub const SWAPCHAIN: &str = "swapchain";
+
+/// Attachments per pass, framebuffers are built by `build_framebuffer` for each count
+const MAX_ATTACHMENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageSize {
+    Swapchain,
+    /// Fraction of the swapchain size, e.g. 0.5 for a half resolution bloom chain
+    Scaled(f32),
+    Absolute([u32; 2]),
+}
+
+impl ImageSize {
+
+    pub fn resolve(self, swapchain: [u32; 2]) -> [u32; 2] {
+        match self {
+            ImageSize::Swapchain => swapchain,
+            ImageSize::Scaled(scale) => [
+                ((swapchain[0] as f32 * scale) as u32).max(1),
+                ((swapchain[1] as f32 * scale) as u32).max(1),
+            ],
+            ImageSize::Absolute(size) => size,
+        }
+    }
+}
+
+/// A transient image, allocated by the graph and only valid while it records a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageDesc {
+    pub size: ImageSize,
+    pub format: Format,
+    pub samples: u32,
+}
+
+impl ImageDesc {
+
+    pub fn new(size: ImageSize, format: Format) -> ImageDesc {
+        ImageDesc { size, format, samples: 1 }
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> ImageDesc {
+        self.samples = samples;
+        self
+    }
+}
+
+/// What an attachment holds at the start of a pass
+#[derive(Debug, Clone, Copy)]
+pub enum Load {
+    Clear(ClearValue),
+    /// The previous pass' output
+    Load,
+    DontCare,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Access {
+    Color(Load),
+    Depth(Load),
+    /// Resolve target for the color attachment in the same position
+    Resolve,
+    Sampled,
+    ReadBuffer,
+    WriteBuffer,
+}
+
+impl Access {
+
+    fn writes(self) -> bool {
+        !matches!(self, Access::Sampled | Access::ReadBuffer)
+    }
+
+    fn reads(self) -> bool {
+        matches!(
+            self,
+            Access::Sampled | Access::ReadBuffer | Access::Color(Load::Load) | Access::Depth(Load::Load)
+        )
+    }
+
+    fn is_attachment(self) -> bool {
+        matches!(self, Access::Color(_) | Access::Depth(_) | Access::Resolve)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PassDecl {
+    name: String,
+    accesses: Vec<(String, Access)>,
+}
+
+/// Declares a pass' resources, in attachment order for attachments
+pub struct PassBuilder<'a> {
+    pass: &'a mut PassDecl,
+}
+
+impl<'a> PassBuilder<'a> {
+
+    fn access(self, resource: &str, access: Access) -> PassBuilder<'a> {
+        self.pass.accesses.push((resource.to_string(), access));
+        self
+    }
+
+    pub fn color(self, image: &str, load: Load) -> PassBuilder<'a> {
+        self.access(image, Access::Color(load))
+    }
+
+    pub fn depth(self, image: &str, load: Load) -> PassBuilder<'a> {
+        self.access(image, Access::Depth(load))
+    }
+
+    /// Resolves the multisampled color attachment declared in the same position
+    pub fn resolve(self, image: &str) -> PassBuilder<'a> {
+        self.access(image, Access::Resolve)
+    }
+
+    /// Read in shaders, fetch it with `PassContext::image`
+    pub fn sample(self, image: &str) -> PassBuilder<'a> {
+        self.access(image, Access::Sampled)
+    }
+
+    /// Read-modify-write passes may declare both `read_buffer` and `write_buffer`, which counts
+    /// as a single write
+    pub fn read_buffer(self, buffer: &str) -> PassBuilder<'a> {
+        if self.pass.accesses.iter().any(|(resource, access)| resource == buffer && matches!(access, Access::WriteBuffer)) {
+            return self;
+        }
+        self.access(buffer, Access::ReadBuffer)
+    }
+
+    pub fn write_buffer(self, buffer: &str) -> PassBuilder<'a> {
+        self.pass.accesses.retain(|(resource, access)| !(resource == buffer && matches!(access, Access::ReadBuffer)));
+        self.access(buffer, Access::WriteBuffer)
+    }
+}
+
+/// A frame declared as passes reading and writing named resources
+///
+/// Passes may be declared in any order: every pass writing a resource runs before the passes
+/// that only read it, and writers of the same resource keep their declaration order.  Passes
+/// that contribute to neither the swapchain nor a buffer are culled.
+#[derive(Debug, Clone, Default)]
+pub struct RenderGraphBuilder {
+    images: Vec<(String, ImageDesc)>,
+    /// Owned outside the graph, declared so passes using them are ordered and kept
+    buffers: Vec<String>,
+    passes: Vec<PassDecl>,
+}
+
+/// Physical image shared by transient images with the same description and usage whose
+/// lifetimes don't overlap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageSlot {
+    pub desc: ImageDesc,
+    pub depth: bool,
+    pub sampled: bool,
+}
+
+/// Order, culling, aliasing and store ops derived from the declarations
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphPlan {
+    /// Indices of the declared passes in execution order, culled passes are left out
+    pub order: Vec<usize>,
+    /// Slot of each declared image, `None` if only culled passes use it
+    pub aliases: Vec<Option<usize>>,
+    pub slots: Vec<ImageSlot>,
+    /// For each pass in `order` and each of its accesses, whether an attachment's contents
+    /// are needed afterwards
+    stores: Vec<Vec<bool>>,
+}
+
+impl RenderGraphBuilder {
+
+    pub fn new() -> RenderGraphBuilder {
+        RenderGraphBuilder::default()
+    }
+
+    pub fn image(&mut self, name: &str, desc: ImageDesc) -> &mut RenderGraphBuilder {
+        self.images.push((name.to_string(), desc));
+        self
+    }
+
+    pub fn buffer(&mut self, name: &str) -> &mut RenderGraphBuilder {
+        self.buffers.push(name.to_string());
+        self
+    }
+
+    pub fn pass(&mut self, name: &str) -> PassBuilder<'_> {
+        self.passes.push(PassDecl { name: name.to_string(), accesses: Vec::new() });
+        PassBuilder { pass: self.passes.last_mut().unwrap() }
+    }
+
+    fn image_index(&self, name: &str) -> Option<usize> {
+        self.images.iter().position(|(image, _)| image == name)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            if self.passes[..index].iter().any(|other| other.name == pass.name) {
+                return Err(format!("Pass '{}' is declared twice", pass.name).into());
+            }
+
+            for (resource, access) in pass.accesses.iter() {
+                let known = match access {
+                    Access::ReadBuffer | Access::WriteBuffer => self.buffers.contains(resource),
+                    _ => resource == SWAPCHAIN || self.image_index(resource).is_some(),
+                };
+                if !known {
+                    return Err(format!("Pass '{}' uses undeclared resource '{}'", pass.name, resource).into());
+                }
+
+                if resource == SWAPCHAIN && matches!(access, Access::Depth(_) | Access::Sampled) {
+                    return Err(format!("Pass '{}' can only use the swapchain as a color attachment", pass.name).into());
+                }
+            }
+
+            let attachments = pass.accesses.iter().filter(|(_, access)| access.is_attachment()).count();
+            if attachments > MAX_ATTACHMENTS {
+                return Err(format!(
+                    "Pass '{}' has {} attachments, at most {} are supported",
+                    pass.name,
+                    attachments,
+                    MAX_ATTACHMENTS,
+                ).into());
+            }
+
+            let colors = pass.accesses.iter().filter(|(_, access)| matches!(access, Access::Color(_))).count();
+            let resolves = pass.accesses.iter().filter(|(_, access)| matches!(access, Access::Resolve)).count();
+            if resolves != 0 && resolves != colors {
+                return Err(format!("Pass '{}' must resolve all or none of its color attachments", pass.name).into());
+            }
+
+            let mut sizes = pass.accesses
+                .iter()
+                .filter(|(_, access)| access.is_attachment())
+                .map(|(resource, _)| self.image_size(resource));
+            if let Some(first) = sizes.next() {
+                if sizes.any(|size| size != first) {
+                    return Err(format!("Pass '{}' has attachments of different sizes", pass.name).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn image_size(&self, name: &str) -> ImageSize {
+        self.image_index(name).map_or(ImageSize::Swapchain, |index| self.images[index].1.size)
+    }
+
+    /// For each pass, the passes that must run before it
+    fn dependencies(&self) -> Result<Vec<Vec<usize>>, Box<dyn Error>> {
+        let mut writers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (resource, access) in pass.accesses.iter() {
+                if access.writes() {
+                    writers.entry(resource.as_str()).or_default().push(index);
+                }
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (resource, access) in pass.accesses.iter() {
+                let resource_writers = writers.get(resource.as_str()).map_or(&[][..], |writers| writers.as_slice());
+
+                let dependency = if access.writes() {
+                    resource_writers.iter().rev().find(|&&writer| writer < index).copied()
+                } else {
+                    match resource_writers.last() {
+                        Some(&writer) if writer == index => {
+                            return Err(format!("Pass '{}' reads '{}' while writing it", pass.name, resource).into());
+                        }
+                        Some(&writer) => Some(writer),
+                        // Buffers may be filled outside the graph
+                        None if matches!(access, Access::ReadBuffer) => None,
+                        None => {
+                            return Err(format!("Pass '{}' reads '{}' but no pass writes it", pass.name, resource).into());
+                        }
+                    }
+                };
+
+                if let Some(dependency) = dependency {
+                    if !dependencies[index].contains(&dependency) {
+                        dependencies[index].push(dependency);
+                    }
+                }
+            }
+        }
+
+        Ok(dependencies)
+    }
+
+    pub fn plan(&self) -> Result<GraphPlan, Box<dyn Error>> {
+        self.validate()?;
+        let dependencies = self.dependencies()?;
+
+        // Keep everything the swapchain and buffer writes depend on
+        let mut live = vec![false; self.passes.len()];
+        let mut stack = self.passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| {
+                pass.accesses.iter().any(|(resource, access)| {
+                    matches!(access, Access::WriteBuffer) || (resource == SWAPCHAIN && access.writes())
+                })
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if stack.is_empty() {
+            return Err("Render graph has no pass writing the swapchain or a buffer".into());
+        }
+
+        while let Some(index) = stack.pop() {
+            if !live[index] {
+                live[index] = true;
+                stack.extend(dependencies[index].iter().copied());
+            }
+        }
+
+        // Kahn's algorithm, ties broken by declaration order
+        let mut remaining = dependencies
+            .iter()
+            .map(|dependencies| dependencies.len())
+            .collect::<Vec<_>>();
+        let mut ready = (0..self.passes.len())
+            .filter(|&index| live[index] && remaining[index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::new();
+
+        while let Some(index) = ready.iter().next().copied() {
+            ready.remove(&index);
+            order.push(index);
+
+            for (dependent, dependencies) in dependencies.iter().enumerate() {
+                if live[dependent] && dependencies.contains(&index) {
+                    remaining[dependent] -= 1;
+                    if remaining[dependent] == 0 {
+                        ready.insert(dependent);
+                    }
+                }
+            }
+        }
+
+        if order.len() != live.iter().filter(|&&live| live).count() {
+            return Err("Render graph passes depend on each other in a cycle".into());
+        }
+
+        let (aliases, slots) = self.alias_images(&order);
+        let stores = self.stores(&order);
+
+        Ok(GraphPlan { order, aliases, slots, stores })
+    }
+
+    /// Greedily reuses the first compatible slot whose previous image is no longer used
+    fn alias_images(&self, order: &[usize]) -> (Vec<Option<usize>>, Vec<ImageSlot>) {
+        let mut lifetimes = Vec::new();
+
+        for (index, (name, desc)) in self.images.iter().enumerate() {
+            let mut first = None;
+            let mut last = 0;
+            let mut slot = ImageSlot { desc: *desc, depth: false, sampled: false };
+
+            for (position, &pass) in order.iter().enumerate() {
+                for (resource, access) in self.passes[pass].accesses.iter() {
+                    if resource == name {
+                        first = first.or(Some(position));
+                        last = position;
+                        slot.depth |= matches!(access, Access::Depth(_));
+                        slot.sampled |= matches!(access, Access::Sampled);
+                    }
+                }
+            }
+
+            if let Some(first) = first {
+                lifetimes.push((first, last, index, slot));
+            }
+        }
+
+        lifetimes.sort_by_key(|(first, _, index, _)| (*first, *index));
+
+        let mut aliases = vec![None; self.images.len()];
+        let mut slots: Vec<(ImageSlot, usize)> = Vec::new();
+
+        for (first, last, index, slot) in lifetimes {
+            let free = slots
+                .iter()
+                .position(|(existing, existing_last)| *existing == slot && *existing_last < first);
+
+            aliases[index] = Some(match free {
+                Some(free) => {
+                    slots[free].1 = last;
+                    free
+                }
+                None => {
+                    slots.push((slot, last));
+                    slots.len() - 1
+                }
+            });
+        }
+
+        (aliases, slots.into_iter().map(|(slot, _)| slot).collect())
+    }
+
+    /// Attachments are stored when presented or read by a later pass
+    fn stores(&self, order: &[usize]) -> Vec<Vec<bool>> {
+        order
+            .iter()
+            .enumerate()
+            .map(|(position, &pass)| {
+                self.passes[pass].accesses
+                    .iter()
+                    .map(|(resource, access)| {
+                        access.is_attachment() && (resource == SWAPCHAIN || order[position + 1..].iter().any(|&later| {
+                            self.passes[later].accesses
+                                .iter()
+                                .any(|(later_resource, later_access)| later_resource == resource && later_access.reads())
+                        }))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl GraphPlan {
+
+    fn stores(&self, position: usize) -> &[bool] {
+        &self.stores[position]
+    }
+}
+
+/// Render pass description built from one pass' attachments
+struct GraphPassDesc {
+    attachments: Vec<AttachmentDescription>,
+    subpass: PassDescription,
+}
+
+unsafe impl RenderPassDesc for GraphPassDesc {
+
+    fn num_attachments(&self) -> usize {
+        self.attachments.len()
+    }
+
+    fn attachment_desc(&self, num: usize) -> Option<AttachmentDescription> {
+        self.attachments.get(num).cloned()
+    }
+
+    fn num_subpasses(&self) -> usize {
+        1
+    }
+
+    fn subpass_desc(&self, num: usize) -> Option<PassDescription> {
+        if num == 0 { Some(self.subpass.clone()) } else { None }
+    }
+
+    fn num_dependencies(&self) -> usize {
+        0
+    }
+
+    fn dependency_desc(&self, _num: usize) -> Option<PassDependencyDescription> {
+        None
+    }
+}
+
+unsafe impl RenderPassDescClearValues<Vec<ClearValue>> for GraphPassDesc {
+
+    fn convert_clear_values(&self, values: Vec<ClearValue>) -> Box<dyn Iterator<Item = ClearValue>> {
+        Box::new(values.into_iter())
+    }
+}
+
+type CachedFramebuffer = (Arc<SwapchainImage<Window>>, Arc<dyn FramebufferAbstract + Send + Sync>);
+
+/// A compiled `RenderGraphBuilder`
+///
+/// Render passes are created once, so pipelines built against `subpass` stay valid.  Transient
+/// images and framebuffers are (re)created whenever the swapchain size changes, which bumps
+/// `generation` so descriptor sets holding transient images can be rebuilt.  Barriers and
+/// layout transitions between passes are inserted by vulkano from the resources each command
+/// uses.
+pub struct RenderGraph {
+    device: Arc<Device>,
+    graph: RenderGraphBuilder,
+    plan: GraphPlan,
+    /// Per declared pass, `None` for culled passes and passes without attachments
+    render_passes: Vec<Option<Arc<dyn RenderPassAbstract + Send + Sync>>>,
+    dimensions: [u32; 2],
+    images: Vec<Arc<AttachmentImage>>,
+    /// Per declared pass, for passes that don't render to the swapchain
+    framebuffers: Vec<Option<Arc<dyn FramebufferAbstract + Send + Sync>>>,
+    /// Keyed by pass and swapchain image index
+    swapchain_framebuffers: HashMap<(usize, usize), CachedFramebuffer>,
+    generation: u64,
+}
+
+/// Given to the record callback for each pass
+pub struct PassContext<'a> {
+    graph: &'a RenderGraph,
+    /// Viewport covering the pass' attachments
+    pub dynamic_state: DynamicState,
+    pub dimensions: [u32; 2],
+}
+
+impl<'a> PassContext<'a> {
+
+    /// A transient image, e.g. one this pass samples
+    pub fn image(&self, name: &str) -> Result<Arc<AttachmentImage>, Box<dyn Error>> {
+        self.graph.image(name)
+    }
+}
+
+impl RenderGraph {
+
+    pub fn new(device: Arc<Device>, graph: RenderGraphBuilder, swapchain_format: Format) -> Result<RenderGraph, Box<dyn Error>> {
+        let plan = graph.plan()?;
+
+        let mut render_passes = vec![None; graph.passes.len()];
+
+        for (position, &pass) in plan.order.iter().enumerate() {
+            let accesses = &graph.passes[pass].accesses;
+            let stores = plan.stores(position);
+
+            let mut attachments = Vec::new();
+            let mut subpass = PassDescription {
+                color_attachments: Vec::new(),
+                depth_stencil: None,
+                input_attachments: Vec::new(),
+                resolve_attachments: Vec::new(),
+                preserve_attachments: Vec::new(),
+            };
+
+            for ((resource, access), &store) in accesses.iter().zip(stores) {
+                let (load, layout) = match access {
+                    Access::Color(load) => (*load, ImageLayout::ColorAttachmentOptimal),
+                    Access::Depth(load) => (*load, ImageLayout::DepthStencilAttachmentOptimal),
+                    Access::Resolve => (Load::DontCare, ImageLayout::ColorAttachmentOptimal),
+                    _ => continue,
+                };
+
+                let (format, samples) = match graph.image_index(resource) {
+                    Some(index) => (graph.images[index].1.format, graph.images[index].1.samples),
+                    None => (swapchain_format, 1),
+                };
+
+                let load = match load {
+                    Load::Clear(_) => LoadOp::Clear,
+                    Load::Load => LoadOp::Load,
+                    Load::DontCare => LoadOp::DontCare,
+                };
+                let store = if store { StoreOp::Store } else { StoreOp::DontCare };
+
+                let attachment = attachments.len();
+                attachments.push(AttachmentDescription {
+                    format,
+                    samples,
+                    load,
+                    store,
+                    stencil_load: load,
+                    stencil_store: store,
+                    initial_layout: layout,
+                    final_layout: layout,
+                });
+
+                match access {
+                    Access::Color(_) => subpass.color_attachments.push((attachment, layout)),
+                    Access::Depth(_) => subpass.depth_stencil = Some((attachment, layout)),
+                    _ => subpass.resolve_attachments.push((attachment, layout)),
+                }
+            }
+
+            if !attachments.is_empty() {
+                let render_pass = RenderPass::new(device.clone(), GraphPassDesc { attachments, subpass })?;
+                render_passes[pass] = Some(Arc::new(render_pass) as Arc<dyn RenderPassAbstract + Send + Sync>);
+            }
+        }
+
+        Ok(RenderGraph {
+            device,
+            framebuffers: vec![None; graph.passes.len()],
+            graph,
+            plan,
+            render_passes,
+            dimensions: [0, 0],
+            images: Vec::new(),
+            swapchain_framebuffers: HashMap::new(),
+            generation: 0,
+        })
+    }
+
+    fn pass_index(&self, pass: &str) -> Result<usize, Box<dyn Error>> {
+        self.graph.passes
+            .iter()
+            .position(|declared| declared.name == pass)
+            .ok_or_else(|| format!("Unknown pass '{}'", pass).into())
+    }
+
+    /// `None` for culled passes and passes without attachments
+    pub fn render_pass(&self, pass: &str) -> Result<Option<Arc<dyn RenderPassAbstract + Send + Sync>>, Box<dyn Error>> {
+        Ok(self.render_passes[self.pass_index(pass)?].clone())
+    }
+
+    /// For building the pipelines a pass draws with
+    pub fn subpass(&self, pass: &str) -> Result<Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>, Box<dyn Error>> {
+        let render_pass = self.render_pass(pass)?.ok_or_else(|| format!("Pass '{}' has no attachments or was culled", pass))?;
+        Ok(Subpass::from(render_pass, 0).ok_or("Unable to build subpass")?)
+    }
+
+    /// Names of the passes that will be recorded, in order
+    pub fn order(&self) -> Vec<&str> {
+        self.plan.order.iter().map(|&pass| self.graph.passes[pass].name.as_str()).collect()
+    }
+
+    /// Incremented whenever transient images are recreated
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Only valid after the first `record`
+    pub fn image(&self, name: &str) -> Result<Arc<AttachmentImage>, Box<dyn Error>> {
+        let index = self.graph.image_index(name).ok_or_else(|| format!("Unknown image '{}'", name))?;
+        let slot = self.plan.aliases[index].ok_or_else(|| format!("Image '{}' is only used by culled passes", name))?;
+
+        Ok(self.images.get(slot).ok_or("Render graph images have not been created yet")?.clone())
+    }
+
+    fn resize(&mut self, dimensions: [u32; 2]) -> Result<(), Box<dyn Error>> {
+        self.images = self.plan.slots
+            .iter()
+            .map(|slot| {
+                let usage = ImageUsage {
+                    color_attachment: !slot.depth,
+                    depth_stencil_attachment: slot.depth,
+                    sampled: slot.sampled,
+                    .. ImageUsage::none()
+                };
+                let size = slot.desc.size.resolve(dimensions);
+
+                if slot.desc.samples > 1 {
+                    AttachmentImage::multisampled_with_usage(self.device.clone(), size, slot.desc.samples, slot.desc.format, usage)
+                } else {
+                    AttachmentImage::with_usage(self.device.clone(), size, slot.desc.format, usage)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.dimensions = dimensions;
+        self.swapchain_framebuffers.clear();
+
+        for &pass in self.plan.order.iter() {
+            self.framebuffers[pass] = match &self.render_passes[pass] {
+                Some(render_pass) if !self.uses_swapchain(pass) => {
+                    Some(self.build_framebuffer(render_pass.clone(), pass, None)?)
+                }
+                _ => None,
+            };
+        }
+
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    fn uses_swapchain(&self, pass: usize) -> bool {
+        self.graph.passes[pass].accesses.iter().any(|(resource, _)| resource == SWAPCHAIN)
+    }
+
+    fn build_framebuffer(
+        &self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        pass: usize,
+        swapchain_image: Option<&Arc<SwapchainImage<Window>>>,
+    ) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, Box<dyn Error>> {
+
+        let attachments = self.graph.passes[pass].accesses
+            .iter()
+            .filter(|(_, access)| access.is_attachment())
+            .map(|(resource, _)| -> Result<Arc<dyn ImageViewAccess + Send + Sync>, Box<dyn Error>> {
+                if resource == SWAPCHAIN {
+                    Ok(swapchain_image.ok_or("Swapchain image required")?.clone())
+                } else {
+                    Ok(self.image(resource)?)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // vulkano's framebuffer builder is typed by its attachments, hence a fixed set of counts
+        // up to `MAX_ATTACHMENTS` (checked when planning)
+        Ok(match attachments.as_slice() {
+            [a] => Arc::new(Framebuffer::start(render_pass).add(a.clone())?.build()?),
+            [a, b] => Arc::new(Framebuffer::start(render_pass).add(a.clone())?.add(b.clone())?.build()?),
+            [a, b, c] => Arc::new(Framebuffer::start(render_pass)
+                .add(a.clone())?.add(b.clone())?.add(c.clone())?.build()?),
+            [a, b, c, d] => Arc::new(Framebuffer::start(render_pass)
+                .add(a.clone())?.add(b.clone())?.add(c.clone())?.add(d.clone())?.build()?),
+            _ => return Err(format!("Pass '{}' has more than {} attachments", self.graph.passes[pass].name, MAX_ATTACHMENTS).into()),
+        })
+    }
+
+    fn framebuffer(
+        &mut self,
+        pass: usize,
+        acquired_image: &AcquiredImage,
+    ) -> Result<Option<Arc<dyn FramebufferAbstract + Send + Sync>>, Box<dyn Error>> {
+
+        let render_pass = match &self.render_passes[pass] {
+            Some(render_pass) => render_pass.clone(),
+            None => return Ok(None),
+        };
+
+        if !self.uses_swapchain(pass) {
+            return Ok(self.framebuffers[pass].clone());
+        }
+
+        let key = (pass, acquired_image.image_num);
+        if let Some((image, framebuffer)) = self.swapchain_framebuffers.get(&key) {
+            if Arc::ptr_eq(image, &acquired_image.image) {
+                return Ok(Some(framebuffer.clone()));
+            }
+        }
+
+        let framebuffer = self.build_framebuffer(render_pass, pass, Some(&acquired_image.image))?;
+        self.swapchain_framebuffers.insert(key, (acquired_image.image.clone(), framebuffer.clone()));
+
+        Ok(Some(framebuffer))
+    }
+
+    /// Records every pass in order, calling `record_pass` with each pass' name inside its render
+    /// pass (or outside any render pass for passes without attachments)
+    ///
+    /// Must be recorded outside of any render pass.
+    pub fn record<F>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        acquired_image: &AcquiredImage,
+        mut record_pass: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(&str, &mut AutoCommandBufferBuilder, &PassContext) -> Result<(), Box<dyn Error>>,
+    {
+        let swapchain_dimensions = acquired_image.image.dimensions();
+        if swapchain_dimensions != self.dimensions || self.images.len() != self.plan.slots.len() {
+            self.resize(swapchain_dimensions)?;
+        }
+
+        for position in 0..self.plan.order.len() {
+            let pass = self.plan.order[position];
+            let framebuffer = self.framebuffer(pass, acquired_image)?;
+
+            let declared = &self.graph.passes[pass];
+            let dimensions = declared.accesses
+                .iter()
+                .find(|(_, access)| access.is_attachment())
+                .map_or(swapchain_dimensions, |(resource, _)| self.graph.image_size(resource).resolve(swapchain_dimensions));
+
+            let context = PassContext {
+                graph: self,
+                dynamic_state: DynamicState {
+                    viewports: Some(vec![Viewport {
+                        origin: [0.0, 0.0],
+                        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                        depth_range: 0.0..1.0,
+                    }]),
+                    .. DynamicState::none()
+                },
+                dimensions,
+            };
+
+            match framebuffer {
+                Some(framebuffer) => {
+                    let clear_values = declared.accesses
+                        .iter()
+                        .filter(|(_, access)| access.is_attachment())
+                        .map(|(_, access)| match access {
+                            Access::Color(Load::Clear(value)) | Access::Depth(Load::Clear(value)) => *value,
+                            _ => ClearValue::None,
+                        })
+                        .collect();
+
+                    builder.begin_render_pass(framebuffer, false, clear_values)?;
+                    record_pass(&declared.name, builder, &context)?;
+                    builder.end_render_pass()?;
+                }
+                None => record_pass(&declared.name, builder, &context)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_desc() -> ImageDesc {
+        ImageDesc::new(ImageSize::Swapchain, Format::R8G8B8A8Unorm)
+    }
+
+    fn names(graph: &RenderGraphBuilder, plan: &GraphPlan) -> Vec<String> {
+        plan.order.iter().map(|&pass| graph.passes[pass].name.clone()).collect()
+    }
+
+    #[test]
+    fn readers_run_after_writers_and_unused_passes_are_culled() {
+        let mut graph = RenderGraphBuilder::new();
+        graph
+            .image("scene", color_desc())
+            .image("unused", color_desc())
+            .image("depth", ImageDesc::new(ImageSize::Swapchain, Format::D32Sfloat));
+
+        // Declared out of order, the composite needs the scene first
+        graph.pass("composite").sample("scene").color(SWAPCHAIN, Load::DontCare);
+        graph.pass("debug").color("unused", Load::Clear(ClearValue::None));
+        graph.pass("scene").color("scene", Load::Clear(ClearValue::None)).depth("depth", Load::Clear(ClearValue::None));
+
+        let plan = graph.plan().unwrap();
+        assert_eq!(names(&graph, &plan), vec!["scene", "composite"]);
+
+        assert_eq!(plan.aliases[1], None);
+        // Scene color is sampled later, depth isn't
+        assert_eq!(plan.stores(0), &[true, false]);
+        assert_eq!(plan.stores(1), &[false, true]);
+    }
+
+    #[test]
+    fn dead_images_share_slots() {
+        let mut graph = RenderGraphBuilder::new();
+        graph
+            .image("a", color_desc())
+            .image("b", color_desc())
+            .image("c", color_desc())
+            .image("half", ImageDesc::new(ImageSize::Scaled(0.5), Format::R8G8B8A8Unorm));
+
+        graph.pass("write a").color("a", Load::DontCare);
+        graph.pass("a to b").sample("a").color("b", Load::DontCare);
+        graph.pass("b to half").sample("b").color("half", Load::DontCare);
+        graph.pass("half to c").sample("half").color("c", Load::DontCare);
+        graph.pass("present").sample("c").color(SWAPCHAIN, Load::DontCare);
+
+        let plan = graph.plan().unwrap();
+        assert_eq!(names(&graph, &plan), vec!["write a", "a to b", "b to half", "half to c", "present"]);
+
+        // `a` is dead once `b` has been written, so `c` can reuse its memory
+        assert_eq!(plan.aliases, vec![Some(0), Some(1), Some(0), Some(2)]);
+        assert_eq!(plan.slots.len(), 3);
+        assert!(plan.slots.iter().all(|slot| slot.sampled && !slot.depth));
+    }
+
+    #[test]
+    fn writers_keep_declaration_order() {
+        let mut graph = RenderGraphBuilder::new();
+        graph.buffer("particles");
+
+        graph.pass("draw").read_buffer("particles").color(SWAPCHAIN, Load::Clear(ClearValue::None));
+        graph.pass("overlay").color(SWAPCHAIN, Load::Load);
+        graph.pass("simulate").write_buffer("particles");
+
+        let plan = graph.plan().unwrap();
+        assert_eq!(names(&graph, &plan), vec!["simulate", "draw", "overlay"]);
+        assert_eq!(plan.stores(1), &[false, true]);
+    }
+
+    #[test]
+    fn read_modify_write_buffers_count_as_writes() {
+        let mut graph = RenderGraphBuilder::new();
+        graph.buffer("particles");
+
+        graph.pass("draw").read_buffer("particles").color(SWAPCHAIN, Load::Clear(ClearValue::None));
+        graph.pass("simulate").read_buffer("particles").write_buffer("particles");
+        graph.pass("collide").write_buffer("particles").read_buffer("particles");
+
+        let plan = graph.plan().unwrap();
+        assert_eq!(names(&graph, &plan), vec!["simulate", "collide", "draw"]);
+        assert_eq!(graph.passes[1].accesses.len(), 1);
+        assert_eq!(graph.passes[2].accesses.len(), 1);
+    }
+
+    #[test]
+    fn invalid_graphs_fail_to_plan() {
+        let mut graph = RenderGraphBuilder::new();
+        graph.pass("present").sample("missing").color(SWAPCHAIN, Load::DontCare);
+        assert!(graph.plan().is_err());
+
+        let mut graph = RenderGraphBuilder::new();
+        graph.image("a", color_desc());
+        graph.pass("present").sample("a").color(SWAPCHAIN, Load::DontCare);
+        assert!(graph.plan().is_err(), "reads of unwritten images are errors");
+
+        let mut graph = RenderGraphBuilder::new();
+        graph.image("a", color_desc()).image("b", color_desc());
+        graph.pass("a").sample("b").color("a", Load::DontCare);
+        graph.pass("b").sample("a").color("b", Load::DontCare);
+        graph.pass("present").sample("a").color(SWAPCHAIN, Load::DontCare);
+        assert!(graph.plan().is_err(), "cycles are errors");
+
+        let mut graph = RenderGraphBuilder::new();
+        graph.image("half", ImageDesc::new(ImageSize::Scaled(0.5), Format::R8G8B8A8Unorm));
+        graph.pass("present").color("half", Load::DontCare).color(SWAPCHAIN, Load::DontCare);
+        assert!(graph.plan().is_err(), "attachment sizes must match");
+
+        let mut graph = RenderGraphBuilder::new();
+        graph.image("a", color_desc());
+        graph.pass("offscreen").color("a", Load::DontCare);
+        assert!(graph.plan().is_err(), "nothing reaches the swapchain");
+
+        let mut graph = RenderGraphBuilder::new();
+        graph.image("a", color_desc()).image("b", color_desc()).image("c", color_desc()).image("d", color_desc());
+        graph.pass("present")
+            .color("a", Load::DontCare)
+            .color("b", Load::DontCare)
+            .color("c", Load::DontCare)
+            .color("d", Load::DontCare)
+            .color(SWAPCHAIN, Load::DontCare);
+        assert!(graph.plan().is_err(), "framebuffers have at most 4 attachments");
+    }
+
+    #[test]
+    fn image_sizes_resolve_against_swapchain() {
+        assert_eq!(ImageSize::Swapchain.resolve([800, 600]), [800, 600]);
+        assert_eq!(ImageSize::Scaled(0.5).resolve([800, 600]), [400, 300]);
+        assert_eq!(ImageSize::Scaled(0.001).resolve([800, 600]), [1, 1]);
+        assert_eq!(ImageSize::Absolute([64, 32]).resolve([800, 600]), [64, 32]);
+    }
+}
@@ -0,0 +1,743 @@
+pub mod many_objects;
+pub mod particles;
+pub mod simple_triangle;
+
+use crate::{
+    app::{
+        App,
+        AppConfig,
+        AppContext,
+        AppEventHandler,
+        AppEventHandlerFactory,
+        UpdateFrequency,
+        WindowRequest,
+    },
+    benchmark::{
+        BenchmarkReport,
+        DeviceInfo,
+    },
+    cli,
+    compute,
+    gpu_profiler::GpuProfiler,
+    profile_scope,
+    screenshot::Screenshot,
+    vulkan_app::{
+        AcquiredImage,
+        DefaultSwapchainFactory,
+        find_physical_device,
+        GpuSelection,
+        RenderState,
+        VulkanApp,
+    },
+    vulkan_device_factories::graphics_and_compute_queues::GraphicsAndComputeQueuesDeviceFactory,
+};
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    error::Error,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use winit::{
+    event::{
+        ElementState,
+        KeyboardInput,
+        VirtualKeyCode,
+        WindowEvent,
+    },
+    window::{
+        Window,
+        WindowId,
+    },
+};
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBuffer,
+        AutoCommandBufferBuilder,
+    },
+    device::{
+        Device,
+        Features,
+        Queue,
+    },
+    framebuffer::RenderPassAbstract,
+    image::SwapchainImage,
+    instance::{
+        Instance,
+        InstanceExtensions,
+    },
+    swapchain::{
+        AcquireError,
+        Surface,
+        Swapchain,
+        SwapchainCreationError,
+    },
+    sync,
+    sync::{
+        FlushError,
+        GpuFuture,
+    },
+};
+
+/// A runnable sample, registered with `SampleRegistry` so the launcher can find it
+pub trait Sample {
+    /// Unique, used on the launcher's command line
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Enabled on the shared device when supported, samples are skipped on devices that don't
+    /// support all of them
+    fn required_features(&self) -> Features {
+        Features::none()
+    }
+    fn update_frequency(&self) -> UpdateFrequency {
+        UpdateFrequency::Continuous
+    }
+    /// Builds the sample around resources shared by every sample, which it hands back through
+    /// `SampleHandler::context` when another sample is switched to
+    fn create_handler(&self, context: SampleContext, config: &AppConfig) -> Result<Box<dyn SampleHandler>, Box<dyn Error>>;
+}
+
+/// The Vulkan objects that outlive any one sample
+#[derive(Clone)]
+pub struct SampleContext {
+    pub device: Arc<Device>,
+    /// Graphics queue first, then any dedicated transfer and compute queues (see
+    /// `transfer_queue` and `compute_queue`)
+    pub queues: Vec<Arc<Queue>>,
+    pub surface: Arc<Surface<Window>>,
+    pub swapchain: Arc<Swapchain<Window>>,
+    pub swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+    /// For requesting redraws from `UpdateFrequency::OnEvent` samples
+    pub app: AppContext,
+}
+
+impl SampleContext {
+
+    pub fn graphics_queue(&self) -> Result<Arc<Queue>, Box<dyn Error>> {
+        Ok(self.queues.get(0).ok_or("Device has no available queues")?.clone())
+    }
+
+    /// For `UploadManager`, `None` if the device has no dedicated transfer family
+    pub fn transfer_queue(&self) -> Option<Arc<Queue>> {
+        self.queues
+            .iter()
+            .skip(1)
+            .find(|queue| !queue.family().supports_graphics() && !queue.family().supports_compute())
+            .cloned()
+    }
+
+    /// The async compute queue, or the graphics queue if the device has no dedicated one
+    pub fn compute_queue(&self) -> Result<Arc<Queue>, Box<dyn Error>> {
+        match compute::dedicated_compute_queue(&self.queues) {
+            Some(queue) => Ok(queue),
+            None => self.graphics_queue(),
+        }
+    }
+
+    /// Framebuffers for `render_pass` around the shared swapchain
+    pub fn render_state(
+        &self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<RenderState, Box<dyn Error>> {
+        RenderState::from_swapchain(
+            self.swapchain.clone(),
+            self.swapchain_images.clone(),
+            self.surface.clone(),
+            render_pass,
+        )
+    }
+}
+
+pub trait SampleHandler: AppEventHandler {
+    /// The shared resources, with the current swapchain if it has been recreated since
+    fn context(&self) -> SampleContext;
+}
+
+/// Acquire, record, submit and present for samples drawing one command buffer per frame on the
+/// graphics queue, recreating the swapchain when needed and taking the `--screenshot`
+///
+/// Each frame's commands are timed in a "frame" GPU profiler scope, reported in benchmarks.
+pub struct FrameLoop {
+    device: Arc<Device>,
+    queues: Vec<Arc<Queue>>,
+    app: AppContext,
+    graphics_queue: Arc<Queue>,
+    render_state: RenderState,
+    recreate_render_state: bool,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    frame: u32,
+    /// Frame number and path from `--screenshot`
+    screenshot: Option<(u32, PathBuf)>,
+    pending_screenshot: Option<Screenshot>,
+    gpu_profiler: GpuProfiler,
+}
+
+impl FrameLoop {
+
+    /// The first frame waits on `previous_frame_end`, e.g. the sample's uploads
+    pub fn new(
+        context: SampleContext,
+        config: &AppConfig,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        previous_frame_end: Box<dyn GpuFuture>,
+    ) -> Result<FrameLoop, Box<dyn Error>> {
+        let render_state = context.render_state(render_pass)?;
+        FrameLoop::with_render_state(context, config, render_state, previous_frame_end)
+    }
+
+    /// For samples whose `RenderGraph` builds the framebuffers, `AcquiredImage::framebuffer`
+    /// is unavailable
+    pub fn without_render_pass(
+        context: SampleContext,
+        config: &AppConfig,
+        previous_frame_end: Box<dyn GpuFuture>,
+    ) -> Result<FrameLoop, Box<dyn Error>> {
+        let render_state = RenderState::without_render_pass(
+            context.swapchain.clone(),
+            context.swapchain_images.clone(),
+            context.surface.clone(),
+        )?;
+        FrameLoop::with_render_state(context, config, render_state, previous_frame_end)
+    }
+
+    fn with_render_state(
+        context: SampleContext,
+        config: &AppConfig,
+        render_state: RenderState,
+        previous_frame_end: Box<dyn GpuFuture>,
+    ) -> Result<FrameLoop, Box<dyn Error>> {
+        let graphics_queue = context.graphics_queue()?;
+
+        FrameLoop::from_render_state(
+            context.device,
+            context.queues,
+            context.app,
+            graphics_queue,
+            render_state,
+            previous_frame_end,
+            config.screenshot_frame().zip(config.screenshot.clone()),
+        )
+    }
+
+    /// Another frame loop on the same device, presenting to a secondary window (see
+    /// `AppEventHandler::on_window_created`) with this one's present mode
+    ///
+    /// `render_pass` is built before the window's swapchain exists, so use this one's format
+    /// (the swapchain factory picks the same one for windows on the same display).
+    /// `--screenshot` only captures the main window.
+    pub fn for_window(
+        &self,
+        window: Window,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<FrameLoop, Box<dyn Error>> {
+        let render_state = RenderState::for_window(
+            self.device.clone(),
+            DefaultSwapchainFactory::with_present_mode(self.render_state.swapchain.present_mode()),
+            &self.graphics_queue,
+            window,
+            render_pass,
+        )?;
+
+        FrameLoop::from_render_state(
+            self.device.clone(),
+            self.queues.clone(),
+            self.app.clone(),
+            self.graphics_queue.clone(),
+            render_state,
+            sync::now(self.device.clone()).boxed(),
+            None,
+        )
+    }
+
+    fn from_render_state(
+        device: Arc<Device>,
+        queues: Vec<Arc<Queue>>,
+        app: AppContext,
+        graphics_queue: Arc<Queue>,
+        render_state: RenderState,
+        previous_frame_end: Box<dyn GpuFuture>,
+        screenshot: Option<(u32, PathBuf)>,
+    ) -> Result<FrameLoop, Box<dyn Error>> {
+
+        // More slots than frames the swapchain lets us get ahead by, so a slot's frame has
+        // finished by the time it is reused
+        let gpu_profiler = GpuProfiler::new(&graphics_queue, render_state.images().len() + 1)?;
+
+        Ok(FrameLoop {
+            device,
+            queues,
+            app,
+            graphics_queue,
+            render_state,
+            recreate_render_state: false,
+            previous_frame_end: Some(previous_frame_end),
+            frame: 0,
+            screenshot,
+            pending_screenshot: None,
+            gpu_profiler,
+        })
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// For opening secondary windows and scheduling redraws
+    pub fn app(&self) -> &AppContext {
+        &self.app
+    }
+
+    pub fn render_state(&self) -> &RenderState {
+        &self.render_state
+    }
+
+    /// For showing the frame timings with `GpuProfiler::draw_ui`
+    pub fn gpu_profiler(&self) -> &GpuProfiler {
+        &self.gpu_profiler
+    }
+
+    /// For `SampleHandler::context`
+    pub fn context(&self) -> SampleContext {
+        SampleContext {
+            device: self.device.clone(),
+            queues: self.queues.clone(),
+            surface: self.render_state.surface().clone(),
+            swapchain: self.render_state.swapchain.clone(),
+            swapchain_images: self.render_state.images().to_vec(),
+            app: self.app.clone(),
+        }
+    }
+
+    /// Call from `AppEventHandler::on_window_resize`
+    pub fn on_window_resize(&mut self) {
+        self.recreate_render_state = true;
+    }
+
+    /// Call from `AppEventHandler::on_benchmark_report`
+    pub fn on_benchmark_report(&self, report: &mut BenchmarkReport) {
+        report.device = Some(DeviceInfo::from_physical_device(&self.device.physical_device()));
+        report.present_mode = Some(format!("{:?}", self.render_state.swapchain.present_mode()));
+        report.gpu_scopes = self.gpu_profiler
+            .averages()
+            .into_iter()
+            .map(|(name, _depth, average)| (name.to_string(), average))
+            .collect();
+    }
+
+    /// Draws a frame, `record` fills its command buffer outside of any render pass
+    ///
+    /// Returns whether the frame was presented, for `AppEventHandler::on_redraw`
+    pub fn draw<F>(&mut self, record: F) -> Result<bool, Box<dyn Error>>
+    where
+        F: FnOnce(&mut AutoCommandBufferBuilder, &RenderState, &AcquiredImage) -> Result<(), Box<dyn Error>>,
+    {
+        self.draw_after(Ok, record)
+    }
+
+    /// Like `draw`, with `before` chaining submissions the frame depends on (e.g. compute work
+    /// it reads) after the previous frame's end
+    pub fn draw_after<B, F>(&mut self, before: B, record: F) -> Result<bool, Box<dyn Error>>
+    where
+        B: FnOnce(Box<dyn GpuFuture>) -> Result<Box<dyn GpuFuture>, Box<dyn Error>>,
+        F: FnOnce(&mut AutoCommandBufferBuilder, &RenderState, &AcquiredImage) -> Result<(), Box<dyn Error>>,
+    {
+        // Counted like `App` counts frames, so `--screenshot` lines up with `--frames`
+        self.frame += 1;
+
+        self.previous_frame_end.as_mut().unwrap().cleanup_finished();
+
+        if self.recreate_render_state {
+            match self.render_state.recreate() {
+                Ok(()) => self.recreate_render_state = false,
+                Err(e) => match e.downcast_ref::<SwapchainCreationError>() {
+                    // Return okay to indicate non-fatal error
+                    Some(SwapchainCreationError::UnsupportedDimensions) => return Ok(false),
+                    _ => return Err(e),
+                },
+            };
+        };
+
+        let acquired_image = {
+            profile_scope!("acquire");
+            self.render_state.acquire_next_image()
+        };
+
+        let acquired_image = match acquired_image {
+            Ok(result) => result,
+            Err(AcquireError::OutOfDate) => {
+                self.recreate_render_state = true;
+                return Ok(false);
+            },
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        self.recreate_render_state = acquired_image.suboptimal;
+
+        let command_buffer = {
+            profile_scope!("record");
+            self.record(&acquired_image, record)?
+        };
+
+        profile_scope!("submit and present");
+        let previous_frame_end = before(self.previous_frame_end.take().expect("Previous frame end should never be none"))?;
+
+        let future = previous_frame_end
+            .join(acquired_image.acquire_future)
+            .then_execute(self.graphics_queue.clone(), command_buffer)?
+            .then_swapchain_present(self.graphics_queue.clone(), self.render_state.swapchain.clone(), acquired_image.image_num)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                if let Some(screenshot) = self.pending_screenshot.take() {
+                    future.wait(None)?;
+                    screenshot.save()?;
+                }
+
+                self.previous_frame_end = Some(future.boxed());
+                Ok(true)
+            }
+            Err(FlushError::OutOfDate) => {
+
+                warn!("Present future error: 'FlushError::OutOfDate'");
+                self.recreate_render_state = true;
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+
+                Ok(false)
+            }
+            Err(e) => {
+                warn!("Failed to flush future: {:?}", e);
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+
+                Ok(false)
+            }
+        }
+    }
+
+    fn record<F>(&mut self, acquired_image: &AcquiredImage, record: F) -> Result<AutoCommandBuffer, Box<dyn Error>>
+    where
+        F: FnOnce(&mut AutoCommandBufferBuilder, &RenderState, &AcquiredImage) -> Result<(), Box<dyn Error>>,
+    {
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.graphics_queue.family(),
+        )?;
+
+        self.gpu_profiler.begin_frame(&mut builder)?;
+        self.gpu_profiler.begin_scope(&mut builder, "frame")?;
+        record(&mut builder, &self.render_state, acquired_image)?;
+        self.gpu_profiler.end_scope(&mut builder)?;
+
+        // After the sample's last pass, so the overlay is included
+        if let Some((frame, path)) = &self.screenshot {
+            if self.frame >= *frame {
+                self.pending_screenshot = Some(Screenshot::record(&mut builder, acquired_image.image.clone(), path)?);
+                self.screenshot = None;
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+#[derive(Default)]
+pub struct SampleRegistry {
+    samples: Vec<Box<dyn Sample>>,
+}
+
+impl SampleRegistry {
+
+    pub fn new() -> SampleRegistry {
+        SampleRegistry { samples: Vec::new() }
+    }
+
+    /// Every sample in this crate
+    pub fn with_builtin_samples() -> SampleRegistry {
+        let mut registry = SampleRegistry::new();
+        registry.register(Box::new(simple_triangle::SimpleTriangle));
+        registry.register(Box::new(many_objects::ManyObjects));
+        registry.register(Box::new(particles::Particles));
+        registry
+    }
+
+    /// Replaces any sample already registered under the same name
+    pub fn register(&mut self, sample: Box<dyn Sample>) {
+        self.samples.retain(|registered| registered.name() != sample.name());
+        self.samples.push(sample);
+    }
+
+    pub fn samples(&self) -> &[Box<dyn Sample>] {
+        &self.samples
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn Sample> {
+        self.samples.iter().find(|sample| sample.name() == name).map(|sample| sample.as_ref())
+    }
+
+    /// Every feature any registered sample requires
+    pub fn required_features(&self) -> Features {
+        self.samples
+            .iter()
+            .fold(Features::none(), |required, sample| union(&required, &sample.required_features()))
+    }
+}
+
+/// vulkano's `Features` has no union, so take the complement of what neither enables
+fn union(a: &Features, b: &Features) -> Features {
+    let all = Features::all();
+    all.difference(&all.difference(a).difference(b))
+}
+
+/// Enabled on the shared device where supported, for the library's loaders rather than any one
+/// sample (BCn KTX2 textures)
+fn optional_features() -> Features {
+    Features {
+        texture_compression_bc: true,
+        .. Features::none()
+    }
+}
+
+/// Forwards events to the active sample, PageDown / PageUp switch to the next / previous
+/// registered sample without recreating the window, device or swapchain
+struct SampleSwitcher {
+    registry: SampleRegistry,
+    active: usize,
+    /// Only `None` while switching, or after neither the new nor the old sample could be built
+    handler: Option<Box<dyn SampleHandler>>,
+    config: AppConfig,
+    /// Samples requiring anything else are skipped when switching
+    enabled_features: Features,
+    pending_switch: Option<usize>,
+}
+
+impl SampleSwitcher {
+
+    /// Queues a switch to the nearest sample in `direction` (1 or -1) the device can run
+    fn request_switch(&mut self, direction: isize) {
+        let samples = self.registry.samples();
+        let count = samples.len() as isize;
+
+        self.pending_switch = (1..count)
+            .map(|offset| (self.active as isize + direction * offset).rem_euclid(count) as usize)
+            .find(|&index| self.enabled_features.superset_of(&samples[index].required_features()));
+
+        if self.pending_switch.is_none() {
+            info!("No other sample can run with this device's features");
+        }
+    }
+
+    /// Rebuilds the current sample if the new one fails to build
+    fn switch(&mut self, index: usize) -> Result<(), Box<dyn Error>> {
+        let context = match &self.handler {
+            Some(handler) => handler.context(),
+            None => return Err("No sample to switch from".into()),
+        };
+
+        // The old sample may still have frames in flight using the swapchain images, and is
+        // dropped before the new one is built so both are never resident at once
+        context.device.wait()?;
+        self.handler = None;
+
+        let samples = self.registry.samples();
+        let (index, handler) = match samples[index].create_handler(context.clone(), &self.config) {
+            Ok(handler) => {
+                info!("Switched to sample '{}'", samples[index].name());
+                (index, handler)
+            }
+            Err(e) => {
+                error!("Unable to switch to sample '{}': {}", samples[index].name(), e);
+
+                let handler = samples[self.active]
+                    .create_handler(context.clone(), &self.config)
+                    .map_err(|e| format!("Unable to rebuild sample '{}': {}", samples[self.active].name(), e))?;
+                (self.active, handler)
+            }
+        };
+
+        let sample = &samples[index];
+        context.surface.window().set_title(sample.name());
+        context.app.set_update_frequency(sample.update_frequency());
+
+        self.handler = Some(handler);
+        self.active = index;
+
+        Ok(())
+    }
+}
+
+impl AppEventHandler for SampleSwitcher {
+
+    fn on_update(&mut self, delta_time: Duration) {
+        if let Some(handler) = &mut self.handler {
+            handler.on_update(delta_time);
+        }
+    }
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+            ..
+        } = event {
+            match key {
+                VirtualKeyCode::PageDown => self.request_switch(1),
+                VirtualKeyCode::PageUp => self.request_switch(-1),
+                _ => {}
+            }
+        }
+
+        if let Some(handler) = &mut self.handler {
+            handler.on_window_event(event);
+        }
+    }
+
+    fn on_window_resize(&mut self, width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_window_resize(width, height))
+    }
+
+    fn on_redraw(&mut self) -> Result<bool, Box<dyn Error>> {
+        if let Some(index) = self.pending_switch.take() {
+            self.switch(index)?;
+        }
+
+        self.handler.as_mut().map_or(Ok(false), |handler| handler.on_redraw())
+    }
+
+    fn on_window_created(&mut self, request: WindowRequest, window: Window) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_window_created(request, window))
+    }
+
+    /// A sample's secondary windows close when it is switched away from, as it owns them
+    fn on_secondary_window_event(&mut self, window_id: WindowId, event: &WindowEvent) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_secondary_window_event(window_id, event))
+    }
+
+    fn on_secondary_window_redraw(&mut self, window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_secondary_window_redraw(window_id))
+    }
+
+    fn on_window_closed(&mut self, window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_window_closed(window_id))
+    }
+
+    /// Events sent by a previous sample's threads arrive at whichever sample is active, so
+    /// samples should ignore types they don't recognise
+    fn on_user_event(&mut self, event: Box<dyn Any + Send>) -> Result<(), Box<dyn Error>> {
+        self.handler.as_mut().map_or(Ok(()), |handler| handler.on_user_event(event))
+    }
+
+    fn on_benchmark_report(&self, report: &mut BenchmarkReport) {
+        report.sample = self.registry.samples()[self.active].name().to_string();
+        if let Some(handler) = &self.handler {
+            handler.on_benchmark_report(report);
+        }
+    }
+}
+
+struct SampleSwitcherFactory {
+    /// Moved into the switcher, as the factory is only borrowed
+    registry: RefCell<Option<SampleRegistry>>,
+    initial: usize,
+}
+
+impl AppEventHandlerFactory for SampleSwitcherFactory {
+
+    fn create_event_handler(
+        &self,
+        window: Window,
+        config: &AppConfig,
+        app: AppContext,
+    ) -> Result<Box<dyn AppEventHandler>, Box<dyn Error>> {
+        let registry = self.registry.borrow_mut().take().ok_or("Sample switcher has already been created")?;
+
+        let (vulkan_app, surface) = VulkanApp::new(
+            config.instance_factory(),
+            GraphicsAndComputeQueuesDeviceFactory::with_optional_features(
+                config.gpu.clone(),
+                union(&registry.required_features(), &optional_features()),
+            ),
+            window,
+        )?;
+
+        let enabled_features = vulkan_app.device.enabled_features().clone();
+        let sample = &registry.samples()[self.initial];
+        if !enabled_features.superset_of(&sample.required_features()) {
+            return Err(format!("Sample '{}' requires features the device doesn't support", sample.name()).into());
+        }
+
+        let graphics_queue = vulkan_app.queues.get(0).ok_or("Device has no available queues")?;
+
+        let (swapchain, swapchain_images) = config.swapchain_factory().create_swapchain(
+            vulkan_app.device.clone(),
+            graphics_queue,
+            surface.clone(),
+        )?;
+
+        let context = SampleContext {
+            device: vulkan_app.device,
+            queues: vulkan_app.queues,
+            surface,
+            swapchain,
+            swapchain_images,
+            app,
+        };
+
+        context.surface.window().set_title(sample.name());
+        let handler = sample.create_handler(context, config)?;
+
+        Ok(Box::new(SampleSwitcher {
+            registry,
+            active: self.initial,
+            handler: Some(handler),
+            config: config.clone(),
+            enabled_features,
+            pending_switch: None,
+        }))
+    }
+}
+
+/// Runs the sample named `initial` with `config` until its window closes (never returns once
+/// running), the other samples in `registry` can be switched to at runtime
+pub fn run_with_config(registry: SampleRegistry, initial: &str, config: AppConfig) -> Result<(), Box<dyn Error>> {
+    let initial = registry.samples()
+        .iter()
+        .position(|sample| sample.name() == initial)
+        .ok_or_else(|| format!("Unknown sample '{}'", initial))?;
+
+    let factory = Box::new(SampleSwitcherFactory {
+        registry: RefCell::new(Some(registry)),
+        initial,
+    });
+
+    App::with_config(config, factory)?.run();
+    Ok(())
+}
+
+/// Entry point for a sample's own binary: parses the process arguments and runs it
+pub fn run(sample: Box<dyn Sample>) {
+    let name = sample.name();
+    let config = cli::from_env(AppConfig::new(sample.update_frequency()));
+
+    let mut registry = SampleRegistry::new();
+    registry.register(sample);
+
+    if let Err(e) = run_with_config(registry, name, config) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Whether the device `select_physical_device` would pick supports `required`
+///
+/// Uses a throwaway instance, so can be checked before any window exists.  Without a surface
+/// presentation support can't be checked, so any graphics queue family qualifies a device.
+pub fn device_supports(required: &Features, gpu: Option<&GpuSelection>) -> Result<bool, Box<dyn Error>> {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)?;
+
+    let (physical_device, _) = find_physical_device(&instance, gpu, |queue_family| queue_family.supports_graphics())?;
+
+    Ok(physical_device.supported_features().superset_of(required))
+}
@@ -0,0 +1,368 @@
+use crate::{
+        app::{
+            AppConfig,
+            AppEventHandler,
+        },
+        benchmark::BenchmarkReport,
+        parallel_recorder::{
+            ParallelRecorder,
+            WorkerPool,
+        },
+        profile_scope,
+        samples::{
+            FrameLoop,
+            Sample,
+            SampleContext,
+            SampleHandler,
+        },
+        vulkan_app::{
+            create_color_render_pass,
+            RenderState,
+        },
+        ui_overlay::UiOverlay,
+        upload::UploadManager,
+};
+
+use std::{
+        sync::Arc,
+        error::Error,
+        time::Duration,
+};
+
+use winit::event::WindowEvent;
+
+use imgui::{
+        im_str,
+        Condition,
+        Window,
+};
+
+use vulkano::{
+        buffer::BufferAccess,
+        command_buffer::AutoCommandBufferBuilder,
+        device::{
+            Device,
+            Queue
+        },
+        framebuffer::{
+            Subpass,
+            RenderPassAbstract,
+        },
+        pipeline::{
+            GraphicsPipeline,
+            GraphicsPipelineAbstract,
+        },
+};
+
+const OBJECT_COUNT: usize = 10_000;
+
+/// Thousands of spinning quads, one draw each, recorded into secondary command buffers on
+/// worker threads
+pub struct ManyObjects;
+
+impl Sample for ManyObjects {
+
+    fn name(&self) -> &'static str {
+        "many_objects"
+    }
+
+    fn description(&self) -> &'static str {
+        "Thousands of draws recorded in parallel into secondary command buffers"
+    }
+
+    fn create_handler(&self, context: SampleContext, config: &AppConfig) -> Result<Box<dyn SampleHandler>, Box<dyn Error>> {
+        Ok(Box::new(ManyObjectsEventHandler::new(context, config)?))
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec2 position;
+
+            layout(location = 0) out vec4 v_color;
+
+            layout(push_constant) uniform PushConstants {
+                vec4 color;
+                vec2 offset;
+                float scale;
+                float rotation;
+            } object;
+
+            void main() {
+                float s = sin(object.rotation);
+                float c = cos(object.rotation);
+                vec2 rotated = vec2(c * position.x - s * position.y, s * position.x + c * position.y);
+
+                gl_Position = vec4(rotated * object.scale + object.offset, 0.0, 1.0);
+                v_color = object.color;
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color;
+            }
+        "
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+#[derive(Debug, Clone)]
+struct Object {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    rotation: f32,
+    angular_velocity: f32,
+    scale: f32,
+    color: [f32; 4],
+}
+
+impl Object {
+
+    /// Deterministic scatter, so benchmark runs are comparable
+    fn scatter(count: usize) -> Vec<Object> {
+        let mut seed = 0x2545_f491_u32;
+        let mut random = move || {
+            // xorshift32
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        };
+
+        (0..count)
+            .map(|_| Object {
+                position: [random() * 2.0 - 1.0, random() * 2.0 - 1.0],
+                velocity: [random() * 0.4 - 0.2, random() * 0.4 - 0.2],
+                rotation: random() * std::f32::consts::TAU,
+                angular_velocity: random() * 4.0 - 2.0,
+                scale: 0.01 + random() * 0.02,
+                color: [random(), random(), random(), 1.0],
+            })
+            .collect()
+    }
+
+    /// Bounces off the edges of clip space
+    fn update(&mut self, delta_time: f32) {
+        for axis in 0..2 {
+            self.position[axis] += self.velocity[axis] * delta_time;
+            if self.position[axis].abs() > 1.0 {
+                self.position[axis] = self.position[axis].clamp(-1.0, 1.0);
+                self.velocity[axis] = -self.velocity[axis];
+            }
+        }
+        self.rotation += self.angular_velocity * delta_time;
+    }
+
+    fn push_constants(&self) -> vs::ty::PushConstants {
+        vs::ty::PushConstants {
+            color: self.color,
+            offset: self.position,
+            scale: self.scale,
+            rotation: self.rotation,
+        }
+    }
+}
+
+/// The objects and what draws them, apart from the frame loop so both can be borrowed while
+/// recording
+struct ObjectRenderer {
+    graphics_queue: Arc<Queue>,
+    vertex_buffer: Arc<dyn BufferAccess + Send + Sync>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    recorder: ParallelRecorder,
+    objects: Vec<Object>,
+    /// Secondary command buffers in the last frame, shown in the overlay
+    secondary_count: usize,
+}
+
+impl ObjectRenderer {
+
+    /// Draws the objects with secondary command buffers recorded on the worker threads, must be
+    /// called inside the render pass begun with secondary contents
+    fn record(&mut self, builder: &mut AutoCommandBufferBuilder, render_state: &RenderState) -> Result<(), Box<dyn Error>> {
+
+        let render_pass = render_state.render_pass.clone().ok_or("Render state has no render pass")?;
+        let subpass = Subpass::from(render_pass, 0).ok_or("Unable to build subpass")?;
+
+        let objects = Arc::new(self.objects.iter().map(Object::push_constants).collect::<Vec<_>>());
+
+        let pipeline = self.graphics_pipeline.clone();
+        let dynamic_state = render_state.dynamic_state.clone();
+        let vertex_buffer = self.vertex_buffer.clone();
+
+        let record = Arc::new(move |builder: &mut AutoCommandBufferBuilder, objects: &[vs::ty::PushConstants]| -> Result<(), Box<dyn Error>> {
+            for object in objects {
+                builder.draw(pipeline.clone(), &dynamic_state, vec![vertex_buffer.clone()], (), *object)?;
+            }
+            Ok(())
+        });
+
+        let secondary_command_buffers = {
+            profile_scope!("record secondary");
+            self.recorder.record(&self.graphics_queue, subpass, objects, record)?
+        };
+        self.secondary_count = secondary_command_buffers.len();
+
+        ParallelRecorder::execute(builder, secondary_command_buffers)?;
+
+        Ok(())
+    }
+}
+
+struct ManyObjectsEventHandler{
+    renderer: ObjectRenderer,
+    ui_overlay: UiOverlay,
+    frame_loop: FrameLoop,
+}
+
+impl ManyObjectsEventHandler {
+
+    fn new(context: SampleContext, config: &AppConfig) -> Result<ManyObjectsEventHandler, Box<dyn Error>> {
+        let device = context.device.clone();
+
+        let graphics_queue = context.graphics_queue()?;
+
+        let surface_format = context.swapchain.format();
+
+        let render_pass = create_color_render_pass(&device, surface_format, config.msaa_samples)?;
+
+        let graphics_pipeline = ManyObjectsEventHandler::create_pipeline(&device, render_pass.clone())?;
+
+        let mut upload_manager = UploadManager::new(
+            device.clone(),
+            graphics_queue.clone(),
+            context.transfer_queue(),
+        );
+
+        vulkano::impl_vertex!(Vertex, position);
+
+        let vertex_buffer = upload_manager.upload_vertex_buffer(
+            [
+                Vertex { position: [-0.5, -0.5] },
+                Vertex { position: [ 0.5, -0.5] },
+                Vertex { position: [ 0.5,  0.5] },
+                Vertex { position: [-0.5, -0.5] },
+                Vertex { position: [ 0.5,  0.5] },
+                Vertex { position: [-0.5,  0.5] },
+            ]
+            .iter()
+            .cloned(),
+        )?;
+
+        let ui_overlay = UiOverlay::new(&mut upload_manager, context.surface.window(), surface_format)?;
+
+        // First frame waits on the vertex and UI font uploads
+        let previous_frame_end = upload_manager.flush()?;
+
+        let frame_loop = FrameLoop::new(context, config, render_pass, previous_frame_end)?;
+
+        let recorder = ParallelRecorder::new(WorkerPool::with_available_parallelism());
+        info!("Recording {} objects on {} threads", OBJECT_COUNT, recorder.thread_count());
+
+        Ok(ManyObjectsEventHandler{
+            renderer: ObjectRenderer {
+                graphics_queue,
+                vertex_buffer,
+                graphics_pipeline,
+                recorder,
+                objects: Object::scatter(OBJECT_COUNT),
+                secondary_count: 0,
+            },
+            ui_overlay,
+            frame_loop,
+        })
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, Box<dyn Error>> {
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        let subpass = Subpass::from(render_pass, 0).ok_or("Unable to build subpass")?;
+
+        Ok(Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .render_pass(subpass)
+            .build(device.clone())?))
+    }
+}
+
+impl AppEventHandler for ManyObjectsEventHandler {
+
+    fn on_update(&mut self, delta_time: Duration) {
+        let delta_time = delta_time.as_secs_f32();
+        for object in &mut self.renderer.objects {
+            object.update(delta_time);
+        }
+    }
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        self.ui_overlay.on_window_event(event);
+    }
+
+    fn on_window_resize(&mut self, _width: u32, _height: u32) -> Result<(), Box<dyn Error>> {
+        self.frame_loop.on_window_resize();
+        Ok(())
+    }
+
+    fn on_benchmark_report(&self, report: &mut BenchmarkReport) {
+        self.frame_loop.on_benchmark_report(report);
+    }
+
+    fn on_redraw(&mut self) -> Result<bool, Box<dyn Error>> {
+        let ManyObjectsEventHandler { renderer, ui_overlay, frame_loop } = self;
+
+        frame_loop.draw(|builder, render_state, acquired_image| {
+            builder.begin_render_pass(acquired_image.framebuffer()?, true, render_state.clear_values([0.0, 0.0, 0.0, 1.0]))?;
+            renderer.record(builder, render_state)?;
+            builder.end_render_pass()?;
+
+            let object_count = renderer.objects.len();
+            let thread_count = renderer.recorder.thread_count();
+            let secondary_count = renderer.secondary_count;
+
+            ui_overlay.record(builder, render_state, acquired_image, |ui| {
+                Window::new(im_str!("Many Objects"))
+                    .position([10.0, 120.0], Condition::FirstUseEver)
+                    .always_auto_resize(true)
+                    .build(ui, || {
+                        ui.text(format!("{} draws", object_count));
+                        ui.text(format!("{} secondary command buffers on {} threads", secondary_count, thread_count));
+                    });
+            })
+        })
+    }
+}
+
+impl SampleHandler for ManyObjectsEventHandler {
+
+    fn context(&self) -> SampleContext {
+        self.frame_loop.context()
+    }
+}
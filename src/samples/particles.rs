@@ -0,0 +1,390 @@
+use crate::{
+        app::{
+            AppConfig,
+            AppEventHandler,
+        },
+        benchmark::BenchmarkReport,
+        compute::{
+            self,
+            ComputeKernel,
+        },
+        samples::{
+            FrameLoop,
+            Sample,
+            SampleContext,
+            SampleHandler,
+        },
+        vulkan_app::create_color_render_pass,
+        ui_overlay::UiOverlay,
+        upload::UploadManager,
+};
+
+use std::{
+        sync::Arc,
+        error::Error,
+        time::Duration,
+};
+
+use winit::event::WindowEvent;
+
+use imgui::{
+        im_str,
+        Condition,
+        Window,
+};
+
+use vulkano::{
+        buffer::{
+            BufferAccess,
+            BufferUsage,
+            DeviceLocalBuffer,
+        },
+        command_buffer::AutoCommandBufferBuilder,
+        descriptor::descriptor_set::DescriptorSet,
+        device::{
+            Device,
+            Queue
+        },
+        framebuffer::{
+            Subpass,
+            RenderPassAbstract,
+        },
+        pipeline::{
+            GraphicsPipeline,
+            GraphicsPipelineAbstract,
+        },
+        sync::GpuFuture,
+};
+
+const PARTICLE_COUNT: u32 = 65_536;
+
+/// Must match `local_size_x` in the compute shader
+const LOCAL_SIZE: [u32; 3] = [256, 1, 1];
+
+/// Particles orbiting a moving attractor, simulated in a compute shader on the async compute
+/// queue (when the device has one) and drawn as points
+pub struct Particles;
+
+impl Sample for Particles {
+
+    fn name(&self) -> &'static str {
+        "particles"
+    }
+
+    fn description(&self) -> &'static str {
+        "GPU particle simulation on the async compute queue"
+    }
+
+    fn create_handler(&self, context: SampleContext, config: &AppConfig) -> Result<Box<dyn SampleHandler>, Box<dyn Error>> {
+        Ok(Box::new(ParticlesEventHandler::new(context, config)?))
+    }
+}
+
+mod cs {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        src: "
+            #version 450
+
+            layout(local_size_x = 256, local_size_y = 1, local_size_z = 1) in;
+
+            struct Particle {
+                vec2 position;
+                vec2 velocity;
+            };
+
+            layout(set = 0, binding = 0) buffer Particles {
+                Particle particles[];
+            };
+
+            layout(push_constant) uniform PushConstants {
+                vec2 attractor;
+                float delta_time;
+                uint count;
+            } params;
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= params.count) {
+                    return;
+                }
+
+                Particle particle = particles[index];
+
+                vec2 to_attractor = params.attractor - particle.position;
+                float distance_squared = max(dot(to_attractor, to_attractor), 0.05);
+
+                particle.velocity += to_attractor / distance_squared * 0.2 * params.delta_time;
+                particle.velocity *= pow(0.8, params.delta_time);
+                particle.position += particle.velocity * params.delta_time;
+
+                particles[index] = particle;
+            }
+        "
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 velocity;
+
+            layout(location = 0) out vec4 v_color;
+
+            void main() {
+                gl_Position = vec4(position, 0.0, 1.0);
+                gl_PointSize = 1.0;
+
+                float speed = clamp(length(velocity), 0.0, 1.0);
+                v_color = vec4(mix(vec3(0.1, 0.3, 1.0), vec3(1.0, 0.6, 0.1), speed), 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color;
+            }
+        "
+    }
+}
+
+/// Matches the compute shader's `Particle` (std430)
+#[derive(Default, Debug, Clone, Copy)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+}
+
+impl Particle {
+
+    /// A disc of particles with a slight swirl
+    fn disc(count: u32) -> Vec<Particle> {
+        (0..count)
+            .map(|index| {
+                // Golden angle spiral, evenly covers the disc without randomness
+                let t = index as f32 / count as f32;
+                let angle = index as f32 * 2.399_963;
+                let radius = 0.8 * t.sqrt();
+                let (sin, cos) = angle.sin_cos();
+
+                Particle {
+                    position: [cos * radius, sin * radius],
+                    velocity: [-sin * 0.2, cos * 0.2],
+                }
+            })
+            .collect()
+    }
+}
+
+struct ParticlesEventHandler{
+    graphics_queue: Arc<Queue>,
+    compute_queue: Arc<Queue>,
+    particles: Arc<DeviceLocalBuffer<[Particle]>>,
+    kernel: ComputeKernel,
+    particle_set: Arc<dyn DescriptorSet + Send + Sync>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    ui_overlay: UiOverlay,
+    frame_loop: FrameLoop,
+    time: f32,
+    /// Simulation step for the next frame, accumulated by `on_update`
+    delta_time: f32,
+}
+
+impl ParticlesEventHandler {
+
+    fn new(context: SampleContext, config: &AppConfig) -> Result<ParticlesEventHandler, Box<dyn Error>> {
+        let device = context.device.clone();
+
+        let graphics_queue = context.graphics_queue()?;
+        let compute_queue = context.compute_queue()?;
+
+        info!(
+            "Simulating {} particles on queue family {} ({})",
+            PARTICLE_COUNT,
+            compute_queue.family().id(),
+            if compute_queue.family().supports_graphics() { "shared with graphics" } else { "async compute" },
+        );
+
+        let surface_format = context.swapchain.format();
+
+        let render_pass = create_color_render_pass(&device, surface_format, config.msaa_samples)?;
+
+        let graphics_pipeline = ParticlesEventHandler::create_pipeline(&device, render_pass.clone())?;
+
+        let cs = cs::Shader::load(device.clone())?;
+        let kernel = ComputeKernel::new(&device, &cs.main_entry_point(), &(), LOCAL_SIZE)?;
+
+        let (particles, particles_uploaded) = compute::storage_buffer_from_iter(
+            &[graphics_queue.clone(), compute_queue.clone()],
+            BufferUsage {
+                storage_buffer: true,
+                vertex_buffer: true,
+                .. BufferUsage::none()
+            },
+            Particle::disc(PARTICLE_COUNT).into_iter(),
+        )?;
+
+        let particle_set = kernel.buffer_set(0, particles.clone())?;
+
+        let mut upload_manager = UploadManager::new(
+            device.clone(),
+            graphics_queue.clone(),
+            context.transfer_queue(),
+        );
+
+        let ui_overlay = UiOverlay::new(&mut upload_manager, context.surface.window(), surface_format)?;
+
+        // First frame waits on the particle and UI font uploads
+        let previous_frame_end = upload_manager.flush()?.join(particles_uploaded).boxed();
+
+        let frame_loop = FrameLoop::new(context, config, render_pass, previous_frame_end)?;
+
+        Ok(ParticlesEventHandler{
+            graphics_queue,
+            compute_queue,
+            particles,
+            kernel,
+            particle_set,
+            graphics_pipeline,
+            ui_overlay,
+            frame_loop,
+            time: 0.0,
+            delta_time: 0.0,
+        })
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, Box<dyn Error>> {
+
+        vulkano::impl_vertex!(Particle, position, velocity);
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        let subpass = Subpass::from(render_pass, 0).ok_or("Unable to build subpass")?;
+
+        Ok(Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Particle>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .point_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .render_pass(subpass)
+            .build(device.clone())?))
+    }
+}
+
+impl AppEventHandler for ParticlesEventHandler {
+
+    fn on_update(&mut self, delta_time: Duration) {
+        self.time += delta_time.as_secs_f32();
+        self.delta_time += delta_time.as_secs_f32();
+    }
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        self.ui_overlay.on_window_event(event);
+    }
+
+    fn on_window_resize(&mut self, _width: u32, _height: u32) -> Result<(), Box<dyn Error>> {
+        self.frame_loop.on_window_resize();
+        Ok(())
+    }
+
+    fn on_benchmark_report(&self, report: &mut BenchmarkReport) {
+        self.frame_loop.on_benchmark_report(report);
+    }
+
+    fn on_redraw(&mut self) -> Result<bool, Box<dyn Error>> {
+        let ParticlesEventHandler {
+            graphics_queue,
+            compute_queue,
+            particles,
+            kernel,
+            particle_set,
+            graphics_pipeline,
+            ui_overlay,
+            frame_loop,
+            time,
+            delta_time,
+        } = self;
+
+        // The previous frame ends in a fence the compute queue can't wait on, so a semaphore
+        // signalled after its draw keeps the simulation from overwriting particles still being
+        // drawn.  The draw then waits on the simulation's semaphore.
+        let simulate = |previous_frame_end: Box<dyn GpuFuture>| -> Result<Box<dyn GpuFuture>, Box<dyn Error>> {
+
+            // Clamped so a long stall (e.g. dragging the window) doesn't fling every particle away
+            let step = delta_time.min(0.05);
+            *delta_time = 0.0;
+
+            let attractor = [0.5 * (*time * 0.7).cos(), 0.5 * (*time * 1.1).sin()];
+
+            let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+                compute_queue.device().clone(),
+                compute_queue.family(),
+            )?;
+
+            kernel.dispatch(
+                &mut builder,
+                [PARTICLE_COUNT, 1, 1],
+                particle_set.clone(),
+                cs::ty::PushConstants { attractor, delta_time: step, count: PARTICLE_COUNT },
+            )?;
+
+            let drawn = compute::signal_after_queue(previous_frame_end, graphics_queue.clone())?;
+
+            compute::submit_after(drawn, compute_queue.clone(), builder.build()?)
+        };
+
+        let async_compute = !compute_queue.family().supports_graphics();
+
+        frame_loop.draw_after(simulate, |builder, render_state, acquired_image| {
+            let particles: Arc<dyn BufferAccess + Send + Sync> = particles.clone();
+
+            builder
+                .begin_render_pass(acquired_image.framebuffer()?, false, render_state.clear_values([0.0, 0.0, 0.0, 1.0]))?
+                .draw(
+                    graphics_pipeline.clone(),
+                    &render_state.dynamic_state,
+                    vec![particles],
+                    (),
+                    (),
+                )?
+                .end_render_pass()?;
+
+            ui_overlay.record(builder, render_state, acquired_image, |ui| {
+                Window::new(im_str!("Particles"))
+                    .position([10.0, 120.0], Condition::FirstUseEver)
+                    .always_auto_resize(true)
+                    .build(ui, || {
+                        ui.text(format!("{} particles", PARTICLE_COUNT));
+                        ui.text(if async_compute { "Async compute queue" } else { "Compute on the graphics queue" });
+                    });
+            })
+        })
+    }
+}
+
+impl SampleHandler for ParticlesEventHandler {
+
+    fn context(&self) -> SampleContext {
+        self.frame_loop.context()
+    }
+}
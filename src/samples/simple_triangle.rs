@@ -0,0 +1,323 @@
+use crate::{
+        app::{
+            AppConfig,
+            AppEventHandler,
+            WindowRequest,
+        },
+        benchmark::BenchmarkReport,
+        render_graph::{
+            ImageDesc,
+            ImageSize,
+            Load,
+            RenderGraph,
+            RenderGraphBuilder,
+            SWAPCHAIN,
+        },
+        samples::{
+            FrameLoop,
+            Sample,
+            SampleContext,
+            SampleHandler,
+        },
+        vulkan_app::create_color_render_pass,
+        ui_overlay::UiOverlay,
+        upload::UploadManager,
+};
+
+use std::{
+        collections::HashMap,
+        sync::Arc,
+        error::Error,
+};
+
+use winit::{
+        event::{
+            ElementState,
+            KeyboardInput,
+            VirtualKeyCode,
+            WindowEvent,
+        },
+        window::{
+            Window,
+            WindowId,
+        },
+};
+
+use vulkano::{
+        buffer::BufferAccess,
+        device::Device,
+        format::Format,
+        framebuffer::{
+            Subpass,
+            RenderPassAbstract,
+        },
+        pipeline::{
+            GraphicsPipeline,
+            GraphicsPipelineAbstract,
+        },
+};
+
+/// A single triangle with the UI overlay, the minimal starting point for new samples, drawn
+/// through a `RenderGraph`
+///
+/// N opens another window showing the triangle, presented from the same device.
+pub struct SimpleTriangle;
+
+impl Sample for SimpleTriangle {
+
+    fn name(&self) -> &'static str {
+        "simple_triangle"
+    }
+
+    fn description(&self) -> &'static str {
+        "A single triangle with the stats overlay"
+    }
+
+    fn create_handler(&self, context: SampleContext, config: &AppConfig) -> Result<Box<dyn SampleHandler>, Box<dyn Error>> {
+        Ok(Box::new(SimpleTriangleEventHandler::new(context, config)?))
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+impl SimpleTriangleEventHandler {
+
+    fn new(context: SampleContext, config: &AppConfig) -> Result<SimpleTriangleEventHandler, Box<dyn Error>> {
+        let device = context.device.clone();
+
+        let graphics_queue = context.graphics_queue()?;
+
+        let surface_format = context.swapchain.format();
+
+        let render_graph = SimpleTriangleEventHandler::create_render_graph(&device, surface_format, config.msaa_samples)?;
+
+        let graphics_pipeline = SimpleTriangleEventHandler::create_pipeline(&device, render_graph.subpass("triangle")?)?;
+
+        let mut upload_manager = UploadManager::new(
+            device.clone(),
+            graphics_queue,
+            context.transfer_queue(),
+        );
+
+        let vertex_buffer = SimpleTriangleEventHandler::create_vertex_buffer(&mut upload_manager)?;
+
+        let ui_overlay = UiOverlay::new(&mut upload_manager, context.surface.window(), surface_format)?;
+
+        // First frame waits on the vertex and UI font uploads
+        let previous_frame_end = upload_manager.flush()?;
+
+        // The graph builds its own framebuffers around the swapchain images
+        let frame_loop = FrameLoop::without_render_pass(context, config, previous_frame_end)?;
+
+        Ok(SimpleTriangleEventHandler{
+            graphics_pipeline,
+            vertex_buffer,
+            render_graph,
+            ui_overlay,
+            frame_loop,
+            secondary_windows: HashMap::new(),
+        })
+    }
+
+    /// With MSAA the triangle is drawn into a multisampled image resolved into the swapchain
+    fn create_render_graph(device: &Arc<Device>, format: Format, samples: u32) -> Result<RenderGraph, Box<dyn Error>> {
+        let clear = Load::Clear([0.0, 0.0, 1.0, 1.0].into());
+
+        let mut graph = RenderGraphBuilder::new();
+
+        if samples > 1 {
+            graph.image("multisampled", ImageDesc::new(ImageSize::Swapchain, format).with_samples(samples));
+            graph.pass("triangle").color("multisampled", clear).resolve(SWAPCHAIN);
+        } else {
+            graph.pass("triangle").color(SWAPCHAIN, clear);
+        }
+
+        RenderGraph::new(device.clone(), graph, format)
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        subpass: Subpass<Arc<dyn RenderPassAbstract + Send + Sync>>,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, Box<dyn Error>> {
+
+        mod vs {
+            vulkano_shaders::shader!{
+                ty: "vertex",
+                src: "
+                    #version 450
+
+                    layout(location = 0) in vec2 position;
+
+                    void main() {
+                        gl_Position = vec4(position, 0.0, 1.0);
+                    }
+                "
+            }
+        }
+
+        mod fs {
+            vulkano_shaders::shader!{
+                ty: "fragment",
+                src: "
+                    #version 450
+
+                    layout(location = 0) out vec4 f_color;
+
+                    void main() {
+                        f_color = vec4(1.0, 0.0, 0.0, 1.0);
+                    }
+                "
+            }
+        }
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        Ok(Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .render_pass(subpass)
+            .build(device.clone())?))
+    }
+
+    fn create_vertex_buffer(upload_manager: &mut UploadManager) -> Result<Arc<dyn BufferAccess + Send + Sync>, Box<dyn Error>> {
+
+        vulkano::impl_vertex!(Vertex, position);
+
+        Ok(upload_manager.upload_vertex_buffer(
+            [
+                Vertex { position: [-0.5, -0.25] },
+                Vertex { position: [ 0.0,  0.5] },
+                Vertex { position: [ 0.25, -0.1] },
+            ]
+            .iter()
+            .cloned(),
+        )?)
+    }
+}
+
+/// The triangle without the overlay, in a single sampled pass
+struct SecondaryWindow {
+    frame_loop: FrameLoop,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+struct SimpleTriangleEventHandler{
+    vertex_buffer: Arc<dyn BufferAccess + Send + Sync>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    render_graph: RenderGraph,
+    ui_overlay: UiOverlay,
+    frame_loop: FrameLoop,
+    secondary_windows: HashMap<WindowId, SecondaryWindow>,
+}
+
+impl AppEventHandler for SimpleTriangleEventHandler {
+
+    fn on_window_event(&mut self, event: &WindowEvent) {
+        self.ui_overlay.on_window_event(event);
+
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::N), .. },
+            ..
+        } = event {
+            let request = WindowRequest { title: "Simple Triangle".to_string(), size: Some([400, 300]) };
+            if let Err(e) = self.frame_loop.app().create_window(request) {
+                warn!("Unable to open window: {}", e);
+            }
+        }
+    }
+
+    fn on_window_resize(&mut self, _width: u32, _height: u32) -> Result<(), Box<dyn Error>> {
+        self.frame_loop.on_window_resize();
+        Ok(())
+    }
+
+    fn on_benchmark_report(&self, report: &mut BenchmarkReport) {
+        self.frame_loop.on_benchmark_report(report);
+    }
+
+    fn on_redraw(&mut self) -> Result<bool, Box<dyn Error>> {
+        let SimpleTriangleEventHandler { vertex_buffer, graphics_pipeline, render_graph, ui_overlay, frame_loop, .. } = self;
+
+        frame_loop.draw(|builder, render_state, acquired_image| {
+            render_graph.record(builder, acquired_image, |_pass, builder, context| {
+                builder.draw(
+                    graphics_pipeline.clone(),
+                    &context.dynamic_state,
+                    vec![vertex_buffer.clone()],
+                    (),
+                    (),
+                )?;
+                Ok(())
+            })?;
+
+            ui_overlay.record(builder, render_state, acquired_image, |_ui| {})
+        })
+    }
+
+    fn on_window_created(&mut self, _request: WindowRequest, window: Window) -> Result<(), Box<dyn Error>> {
+        let window_id = window.id();
+
+        let format = self.frame_loop.render_state().swapchain.format();
+        let render_pass = create_color_render_pass(self.frame_loop.device(), format, 1)?;
+        let graphics_pipeline = SimpleTriangleEventHandler::create_pipeline(
+            self.frame_loop.device(),
+            Subpass::from(render_pass.clone(), 0).ok_or("Unable to build subpass")?,
+        )?;
+
+        let frame_loop = self.frame_loop.for_window(window, render_pass)?;
+
+        self.secondary_windows.insert(window_id, SecondaryWindow { frame_loop, graphics_pipeline });
+        Ok(())
+    }
+
+    fn on_secondary_window_event(&mut self, window_id: WindowId, event: &WindowEvent) -> Result<(), Box<dyn Error>> {
+        if let (Some(window), WindowEvent::Resized(_)) = (self.secondary_windows.get_mut(&window_id), event) {
+            window.frame_loop.on_window_resize();
+        }
+        Ok(())
+    }
+
+    fn on_secondary_window_redraw(&mut self, window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        let vertex_buffer = &self.vertex_buffer;
+
+        let SecondaryWindow { frame_loop, graphics_pipeline } = match self.secondary_windows.get_mut(&window_id) {
+            Some(window) => window,
+            // Closed, waiting for the window to be destroyed
+            None => return Ok(()),
+        };
+
+        frame_loop.draw(|builder, render_state, acquired_image| {
+            builder
+                .begin_render_pass(acquired_image.framebuffer()?, false, render_state.clear_values([0.0, 0.0, 1.0, 1.0]))?
+                .draw(
+                    graphics_pipeline.clone(),
+                    &render_state.dynamic_state,
+                    vec![vertex_buffer.clone()],
+                    (),
+                    (),
+                )?
+                .end_render_pass()?;
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn on_window_closed(&mut self, window_id: WindowId) -> Result<(), Box<dyn Error>> {
+        self.secondary_windows.remove(&window_id);
+        Ok(())
+    }
+}
+
+impl SampleHandler for SimpleTriangleEventHandler {
+
+    fn context(&self) -> SampleContext {
+        self.frame_loop.context()
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{
+    camera::Camera,
+    math::{
+        self,
+        Matrix4,
+        Vector3,
+        Vector4,
+        IDENTITY,
+    },
+    mesh::Mesh,
+};
+
+use std::{
+    error::Error,
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+
+    pub fn new(min: Vector3, max: Vector3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// `None` for an empty iterator
+    pub fn from_points<I: IntoIterator<Item = Vector3>>(points: I) -> Option<Aabb> {
+        points.into_iter().fold(None, |bounds, point| match bounds {
+            Some(bounds) => Some(bounds.merge(&Aabb::new(point, point))),
+            None => Some(Aabb::new(point, point)),
+        })
+    }
+
+    pub fn center(&self) -> Vector3 {
+        math::scale(math::add(self.min, self.max), 0.5)
+    }
+
+    pub fn extents(&self) -> Vector3 {
+        math::scale(math::sub(self.max, self.min), 0.5)
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1]), self.min[2].min(other.min[2])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1]), self.max[2].max(other.max[2])],
+        }
+    }
+
+    /// Smallest box containing this box after transformation
+    pub fn transform(&self, m: &Matrix4) -> Aabb {
+        let center = math::transform_point(m, self.center());
+        let extents = self.extents();
+
+        let mut world_extents = [0.0; 3];
+        for (row, extent) in world_extents.iter_mut().enumerate() {
+            *extent = (0..3).map(|column| m[column][row].abs() * extents[column]).sum();
+        }
+
+        Aabb {
+            min: math::sub(center, world_extents),
+            max: math::add(center, world_extents),
+        }
+    }
+}
+
+/// Six inward facing planes (xyz normal, w distance), a point is inside when `dot(n, p) + w >= 0`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4; 6],
+}
+
+impl Frustum {
+
+    /// Extracts the planes from a view-projection matrix using Vulkan's 0..1 depth range
+    ///
+    /// Works for reversed-Z too since only the roles of the near and far planes swap
+    pub fn from_matrix(m: &Matrix4) -> Frustum {
+        let row = |r: usize| [m[0][r], m[1][r], m[2][r], m[3][r]];
+        let combine = |a: Vector4, b: Vector4, sign: f32| {
+            [a[0] + sign * b[0], a[1] + sign * b[1], a[2] + sign * b[2], a[3] + sign * b[3]]
+        };
+
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [
+            combine(w, x, 1.0),
+            combine(w, x, -1.0),
+            combine(w, y, 1.0),
+            combine(w, y, -1.0),
+            z,
+            combine(w, z, -1.0),
+        ];
+
+        let normalize = |plane: Vector4| {
+            let length = math::length([plane[0], plane[1], plane[2]]);
+            if length > f32::EPSILON {
+                [plane[0] / length, plane[1] / length, plane[2] / length, plane[3] / length]
+            } else {
+                // Degenerate plane (e.g. the far plane of an infinite projection) culls nothing
+                [0.0, 0.0, 0.0, 1.0]
+            }
+        };
+
+        Frustum {
+            planes: [
+                normalize(planes[0]),
+                normalize(planes[1]),
+                normalize(planes[2]),
+                normalize(planes[3]),
+                normalize(planes[4]),
+                normalize(planes[5]),
+            ],
+        }
+    }
+
+    /// Conservative test, boxes near the frustum corners may be reported as visible
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let corner = [
+                if plane[0] >= 0.0 { bounds.max[0] } else { bounds.min[0] },
+                if plane[1] >= 0.0 { bounds.max[1] } else { bounds.min[1] },
+                if plane[2] >= 0.0 { bounds.max[2] } else { bounds.min[2] },
+            ];
+            math::dot([plane[0], plane[1], plane[2]], corner) + plane[3] >= 0.0
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+pub struct Node {
+    pub name: Option<String>,
+    pub mesh: Option<Arc<Mesh>>,
+    /// Index into the application's material table
+    pub material: Option<usize>,
+    /// Object space bounds, nodes without bounds are never culled
+    bounds: Option<Aabb>,
+    local_transform: Matrix4,
+    world_transform: Matrix4,
+    world_bounds: Option<Aabb>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    dirty: bool,
+}
+
+impl Node {
+
+    pub fn bounds(&self) -> Option<&Aabb> {
+        self.bounds.as_ref()
+    }
+
+    pub fn local_transform(&self) -> &Matrix4 {
+        &self.local_transform
+    }
+
+    /// Only valid after `Scene::update_world_transforms`
+    pub fn world_transform(&self) -> &Matrix4 {
+        &self.world_transform
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// A visible mesh node
+#[derive(Clone)]
+pub struct DrawItem {
+    pub node: NodeId,
+    pub mesh: Arc<Mesh>,
+    pub material: Option<usize>,
+    pub world_transform: Matrix4,
+}
+
+/// Flat storage of nodes linked into a transform hierarchy
+///
+/// World transforms are cached and only recomputed for nodes whose local transform (or an
+/// ancestor's) changed since the last update.
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, local_transform: Matrix4) -> NodeId {
+
+        let id = NodeId(self.nodes.len());
+
+        self.nodes.push(Node {
+            name: None,
+            mesh: None,
+            material: None,
+            bounds: None,
+            local_transform,
+            world_transform: IDENTITY,
+            world_bounds: None,
+            parent,
+            children: Vec::new(),
+            dirty: true,
+        });
+
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    /// Adds a node drawing `mesh` with the given object space bounds
+    pub fn add_mesh_node(
+        &mut self,
+        parent: Option<NodeId>,
+        local_transform: Matrix4,
+        mesh: Arc<Mesh>,
+        material: Option<usize>,
+        bounds: Aabb,
+    ) -> NodeId {
+        let id = self.add_node(parent, local_transform);
+        let node = self.node_mut(id);
+        node.mesh = Some(mesh);
+        node.material = material;
+        node.bounds = Some(bounds);
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    /// Mutable access to the attachments, bounds, transforms and hierarchy changes go through
+    /// the scene
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().map(|(index, node)| (NodeId(index), node))
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, local_transform: Matrix4) {
+        let node = &mut self.nodes[id.0];
+        node.local_transform = local_transform;
+        node.dirty = true;
+    }
+
+    /// Object space bounds, `None` stops the node from being culled
+    pub fn set_bounds(&mut self, id: NodeId, bounds: Option<Aabb>) {
+        let node = &mut self.nodes[id.0];
+        node.bounds = bounds;
+        node.dirty = true;
+    }
+
+    /// Re-parents a node, keeping its local transform
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), Box<dyn Error>> {
+
+        let mut ancestor = parent;
+        while let Some(current) = ancestor {
+            if current == id {
+                return Err("A node cannot be parented to itself or one of its descendants".into());
+            }
+            ancestor = self.nodes[current.0].parent;
+        }
+
+        match self.nodes[id.0].parent {
+            Some(old_parent) => self.nodes[old_parent.0].children.retain(|&child| child != id),
+            None => self.roots.retain(|&root| root != id),
+        }
+
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = &mut self.nodes[id.0];
+        node.parent = parent;
+        node.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn update_world_transforms(&mut self) {
+        for root in self.roots.clone() {
+            self.update_node(root, &IDENTITY, false);
+        }
+    }
+
+    fn update_node(&mut self, id: NodeId, parent_transform: &Matrix4, parent_changed: bool) {
+
+        let node = &mut self.nodes[id.0];
+        let changed = node.dirty || parent_changed;
+
+        if changed {
+            node.world_transform = math::multiply(parent_transform, &node.local_transform);
+            node.world_bounds = node.bounds.map(|bounds| bounds.transform(&node.world_transform));
+            node.dirty = false;
+        }
+
+        let world_transform = node.world_transform;
+
+        for index in 0..self.nodes[id.0].children.len() {
+            let child = self.nodes[id.0].children[index];
+            self.update_node(child, &world_transform, changed);
+        }
+    }
+
+    /// Nodes whose world bounds intersect `frustum` (or that have no bounds)
+    ///
+    /// Requires world transforms to be up to date
+    pub fn visible_nodes<'a>(&'a self, frustum: &'a Frustum) -> impl Iterator<Item = NodeId> + 'a {
+        self.nodes()
+            .filter(move |(_, node)| match &node.world_bounds {
+                Some(bounds) => frustum.intersects(bounds),
+                None => true,
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// Updates transforms and returns the visible mesh nodes, grouped by material to reduce
+    /// pipeline and descriptor set changes
+    pub fn draw_list(&mut self, camera: &Camera) -> Vec<DrawItem> {
+
+        self.update_world_transforms();
+
+        let frustum = Frustum::from_matrix(&camera.view_projection_matrix());
+
+        let mut items = self
+            .visible_nodes(&frustum)
+            .filter_map(|id| {
+                let node = self.node(id);
+                node.mesh.as_ref().map(|mesh| DrawItem {
+                    node: id,
+                    mesh: mesh.clone(),
+                    material: node.material,
+                    world_transform: node.world_transform,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        items.sort_by_key(|item| item.material);
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::translation;
+
+    fn unit_box() -> Aabb {
+        Aabb::new([-0.5, -0.5, -0.5], [0.5, 0.5, 0.5])
+    }
+
+    #[test]
+    fn world_transforms_follow_parents() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(None, translation([1.0, 0.0, 0.0]));
+        let child = scene.add_node(Some(parent), translation([0.0, 2.0, 0.0]));
+
+        scene.update_world_transforms();
+        assert_eq!(scene.node(child).world_transform()[3], [1.0, 2.0, 0.0, 1.0]);
+
+        scene.set_local_transform(parent, translation([0.0, 0.0, 3.0]));
+        scene.update_world_transforms();
+        assert_eq!(scene.node(child).world_transform()[3], [0.0, 2.0, 3.0, 1.0]);
+
+        assert!(scene.set_parent(parent, Some(child)).is_err());
+    }
+
+    #[test]
+    fn frustum_culls_boxes_behind_the_camera() {
+        let mut camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 0.1, 100.0);
+        camera.position = [0.0, 0.0, 0.0];
+        camera.target = [0.0, 0.0, -1.0];
+
+        let mut scene = Scene::new();
+        let in_front = scene.add_node(None, translation([0.0, 0.0, -5.0]));
+        let behind = scene.add_node(None, translation([0.0, 0.0, 5.0]));
+        let beyond_far = scene.add_node(None, translation([0.0, 0.0, -200.0]));
+        for &id in [in_front, behind, beyond_far].iter() {
+            scene.set_bounds(id, Some(unit_box()));
+        }
+
+        scene.update_world_transforms();
+        let frustum = Frustum::from_matrix(&camera.view_projection_matrix());
+        let visible = scene.visible_nodes(&frustum).collect::<Vec<_>>();
+
+        assert_eq!(visible, vec![in_front]);
+
+        camera.reversed_z = true;
+        let frustum = Frustum::from_matrix(&camera.view_projection_matrix());
+        assert_eq!(scene.visible_nodes(&frustum).collect::<Vec<_>>(), vec![in_front]);
+    }
+
+    #[test]
+    fn changed_bounds_are_culled_after_the_next_update() {
+        let mut camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 0.1, 100.0);
+        camera.position = [0.0, 0.0, 0.0];
+        camera.target = [0.0, 0.0, -1.0];
+        let frustum = Frustum::from_matrix(&camera.view_projection_matrix());
+
+        let mut scene = Scene::new();
+        let node = scene.add_node(None, translation([0.0, 0.0, -5.0]));
+        scene.set_bounds(node, Some(unit_box()));
+        scene.update_world_transforms();
+        assert_eq!(scene.visible_nodes(&frustum).count(), 1);
+
+        // Moved behind the camera without touching the transform
+        scene.set_bounds(node, Some(Aabb::new([-0.5, -0.5, 9.5], [0.5, 0.5, 10.5])));
+        scene.update_world_transforms();
+        assert_eq!(scene.visible_nodes(&frustum).count(), 0);
+    }
+}
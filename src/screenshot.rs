@@ -0,0 +1,89 @@
+use std::{
+    error::Error,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+};
+
+use winit::window::Window;
+
+use vulkano::{
+    buffer::{
+        BufferUsage,
+        CpuAccessibleBuffer,
+    },
+    command_buffer::AutoCommandBufferBuilder,
+    device::DeviceOwned,
+    format::Format,
+    image::SwapchainImage,
+};
+
+/// A swapchain image copy waiting for its command buffer to finish
+///
+/// Record it after the frame's last pass, then call `save` once the submission's fence has
+/// been waited on.  Requires swapchain images created with transfer source usage, which
+/// `DefaultSwapchainFactory` requests whenever the surface supports it.
+pub struct Screenshot {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    dimensions: [u32; 2],
+    format: Format,
+    path: PathBuf,
+}
+
+impl Screenshot {
+
+    pub fn record(
+        builder: &mut AutoCommandBufferBuilder,
+        image: Arc<SwapchainImage<Window>>,
+        path: &Path,
+    ) -> Result<Screenshot, Box<dyn Error>> {
+
+        let dimensions = image.dimensions();
+        let format = image.swapchain().format();
+
+        if bgra_order(format).is_none() {
+            return Err(format!("Screenshots of {:?} swapchains are not supported", format).into());
+        }
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            image.swapchain().device().clone(),
+            BufferUsage::transfer_destination(),
+            false,
+            (0..dimensions[0] * dimensions[1] * 4).map(|_| 0u8),
+        )?;
+
+        builder.copy_image_to_buffer(image, buffer.clone())?;
+
+        Ok(Screenshot { buffer, dimensions, format, path: path.to_path_buf() })
+    }
+
+    /// Writes the image (format chosen by the path's extension), the copy must have completed
+    pub fn save(self) -> Result<(), Box<dyn Error>> {
+        let texels = self.buffer.read()?;
+        let mut rgba = texels.to_vec();
+
+        if bgra_order(self.format) == Some(true) {
+            for texel in rgba.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+        }
+
+        image::save_buffer(&self.path, &rgba, self.dimensions[0], self.dimensions[1], image::ColorType::Rgba8)
+            .map_err(|error| format!("Unable to save screenshot {}: {}", self.path.display(), error))?;
+
+        info!("Saved screenshot to {}", self.path.display());
+
+        Ok(())
+    }
+}
+
+/// `Some(true)` for BGRA, `Some(false)` for RGBA, `None` for formats that can't be saved as 8 bit RGBA
+fn bgra_order(format: Format) -> Option<bool> {
+    match format {
+        Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb => Some(true),
+        Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,1363 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    ffi::CString,
+    sync::Arc,
+    vec,
+};
+
+use vulkano::{
+    buffer::BufferAccess,
+    descriptor::{
+        descriptor::{
+            DescriptorBufferDesc,
+            DescriptorDesc,
+            DescriptorDescTy,
+            DescriptorImageDesc,
+            DescriptorImageDescArray,
+            DescriptorImageDescDimensions,
+            ShaderStages,
+        },
+        descriptor_set::{
+            PersistentDescriptorSet,
+            PersistentDescriptorSetBuf,
+            PersistentDescriptorSetBuilder,
+            PersistentDescriptorSetImg,
+            PersistentDescriptorSetSampler,
+            UnsafeDescriptorSetLayout,
+        },
+        pipeline_layout::{
+            PipelineLayoutDesc,
+            PipelineLayoutDescPcRange,
+        },
+    },
+    device::Device,
+    format::Format,
+    image::ImageViewAccess,
+    pipeline::shader::{
+        ComputeEntryPoint,
+        GraphicsEntryPoint,
+        GraphicsShaderType,
+        ShaderInterfaceDef,
+        ShaderInterfaceDefEntry,
+        ShaderModule,
+    },
+    sampler::Sampler,
+};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+
+    fn from_execution_model(model: u32) -> Result<ShaderStage, Box<dyn Error>> {
+        match model {
+            0 => Ok(ShaderStage::Vertex),
+            1 => Ok(ShaderStage::TessellationControl),
+            2 => Ok(ShaderStage::TessellationEvaluation),
+            3 => Ok(ShaderStage::Geometry),
+            4 => Ok(ShaderStage::Fragment),
+            5 => Ok(ShaderStage::Compute),
+            _ => Err(format!("Unsupported SPIR-V execution model {}", model).into()),
+        }
+    }
+
+    pub fn stages(self) -> ShaderStages {
+        let mut stages = ShaderStages::none();
+        match self {
+            ShaderStage::Vertex => stages.vertex = true,
+            ShaderStage::TessellationControl => stages.tessellation_control = true,
+            ShaderStage::TessellationEvaluation => stages.tessellation_evaluation = true,
+            ShaderStage::Geometry => stages.geometry = true,
+            ShaderStage::Fragment => stages.fragment = true,
+            ShaderStage::Compute => stages.compute = true,
+        };
+        stages
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorKind {
+    Sampler,
+    CombinedImageSampler,
+    SampledImage,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    UniformBuffer,
+    StorageBuffer,
+    InputAttachment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageDimensions {
+    OneDimensional,
+    TwoDimensional,
+    ThreeDimensional,
+    Cube,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub kind: DescriptorKind,
+    pub array_count: u32,
+    /// Declared size in bytes of uniform/storage blocks (runtime arrays count as zero)
+    pub block_size: Option<u32>,
+    pub image_dimensions: Option<ImageDimensions>,
+    pub image_arrayed: bool,
+    pub image_multisampled: bool,
+    pub stages: ShaderStages,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushConstantRange {
+    pub offset: u32,
+    pub size: u32,
+    pub stages: ShaderStages,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    Float,
+    Double,
+    Int,
+    Uint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub component_type: ComponentType,
+    pub components: u32,
+    /// Number of consecutive locations consumed (matrices consume one per column)
+    pub locations: u32,
+    pub name: Option<String>,
+}
+
+impl InterfaceVariable {
+
+    pub fn format(&self) -> Format {
+        match (self.component_type, self.components) {
+            (ComponentType::Float, 1) => Format::R32Sfloat,
+            (ComponentType::Float, 2) => Format::R32G32Sfloat,
+            (ComponentType::Float, 3) => Format::R32G32B32Sfloat,
+            (ComponentType::Float, _) => Format::R32G32B32A32Sfloat,
+            (ComponentType::Double, 1) => Format::R64Sfloat,
+            (ComponentType::Double, 2) => Format::R64G64Sfloat,
+            (ComponentType::Double, 3) => Format::R64G64B64Sfloat,
+            (ComponentType::Double, _) => Format::R64G64B64A64Sfloat,
+            (ComponentType::Int, 1) => Format::R32Sint,
+            (ComponentType::Int, 2) => Format::R32G32Sint,
+            (ComponentType::Int, 3) => Format::R32G32B32Sint,
+            (ComponentType::Int, _) => Format::R32G32B32A32Sint,
+            (ComponentType::Uint, 1) => Format::R32Uint,
+            (ComponentType::Uint, 2) => Format::R32G32Uint,
+            (ComponentType::Uint, 3) => Format::R32G32B32Uint,
+            (ComponentType::Uint, _) => Format::R32G32B32A32Uint,
+        }
+    }
+}
+
+/// Binding, push constant and interface information extracted from a SPIR-V module
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub stage: ShaderStage,
+    pub entry_point: String,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub push_constant_ranges: Vec<PushConstantRange>,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    /// Only set for compute shaders
+    pub local_size: Option<[u32; 3]>,
+}
+
+#[derive(Debug, Clone)]
+enum SpirvType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, arrayed: bool, multisampled: bool, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    block: bool,
+    buffer_block: bool,
+    built_in: bool,
+    location: Option<u32>,
+    binding: Option<u32>,
+    descriptor_set: Option<u32>,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+    built_in: bool,
+}
+
+struct Variable {
+    id: u32,
+    type_id: u32,
+    storage_class: u32,
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, u32, String)>,
+    local_size: Option<[u32; 3]>,
+    names: HashMap<u32, String>,
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    variables: Vec<Variable>,
+}
+
+fn parse_string(words: &[u32]) -> String {
+    let mut bytes = Vec::new();
+
+    'words: for word in words {
+        for byte in word.to_le_bytes().iter() {
+            if *byte == 0 {
+                break 'words;
+            }
+            bytes.push(*byte);
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+
+    fn parse(words: &[u32]) -> Result<Module, Box<dyn Error>> {
+
+        if words.len() < 5 || words[0] != SPIRV_MAGIC {
+            return Err("Invalid SPIR-V module header".into());
+        }
+
+        let mut module = Module::default();
+        let mut position = 5;
+
+        while position < words.len() {
+            let word_count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+
+            if word_count == 0 || position + word_count > words.len() {
+                return Err("Truncated SPIR-V instruction".into());
+            }
+
+            let operands = &words[position + 1..position + word_count];
+            module.parse_instruction(opcode, operands)?;
+
+            position += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), Box<dyn Error>> {
+
+        let operand = |index: usize| -> Result<u32, Box<dyn Error>> {
+            operands.get(index).cloned().ok_or_else(|| format!("Malformed SPIR-V instruction (opcode {})", opcode).into())
+        };
+
+        match opcode {
+            OP_NAME => {
+                let name = parse_string(operands.get(1..).unwrap_or(&[]));
+                if !name.is_empty() {
+                    self.names.insert(operand(0)?, name);
+                }
+            },
+            OP_ENTRY_POINT => {
+                // Only the first entry point is reflected
+                if self.entry_point.is_none() {
+                    let name = parse_string(operands.get(2..).unwrap_or(&[]));
+                    self.entry_point = Some((operand(0)?, operand(1)?, name));
+                }
+            },
+            OP_EXECUTION_MODE => {
+                if operand(1)? == EXECUTION_MODE_LOCAL_SIZE {
+                    self.local_size = Some([operand(2)?, operand(3)?, operand(4)?]);
+                }
+            },
+            OP_TYPE_BOOL => {
+                self.types.insert(operand(0)?, SpirvType::Bool);
+            },
+            OP_TYPE_INT => {
+                self.types.insert(operand(0)?, SpirvType::Int { width: operand(1)?, signed: operand(2)? != 0 });
+            },
+            OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, SpirvType::Float { width: operand(1)? });
+            },
+            OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, SpirvType::Vector { component: operand(1)?, count: operand(2)? });
+            },
+            OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, SpirvType::Matrix { column: operand(1)?, count: operand(2)? });
+            },
+            OP_TYPE_IMAGE => {
+                self.types.insert(operand(0)?, SpirvType::Image {
+                    dim: operand(2)?,
+                    arrayed: operand(4)? != 0,
+                    multisampled: operand(5)? != 0,
+                    sampled: operand(6)?,
+                });
+            },
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, SpirvType::Sampler);
+            },
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, SpirvType::SampledImage { image: operand(1)? });
+            },
+            OP_TYPE_ARRAY => {
+                let id = operand(0)?;
+                // Length is the id of a constant, specialization constants are never recorded
+                let length = self.constants.get(&operand(2)?).cloned().ok_or_else(|| {
+                    format!("SPIR-V array type {} has a specialization constant length, which is not supported", id)
+                })?;
+                self.types.insert(id, SpirvType::Array { element: operand(1)?, length });
+            },
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, SpirvType::RuntimeArray { element: operand(1)? });
+            },
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, SpirvType::Struct { members: operands.get(1..).unwrap_or(&[]).to_vec() });
+            },
+            OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, SpirvType::Pointer { pointee: operand(2)? });
+            },
+            OP_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            },
+            OP_VARIABLE => {
+                self.variables.push(Variable {
+                    type_id: operand(0)?,
+                    id: operand(1)?,
+                    storage_class: operand(2)?,
+                });
+            },
+            OP_DECORATE => {
+                let decorations = self.decorations.entry(operand(0)?).or_default();
+                match operand(1)? {
+                    DECORATION_BLOCK => decorations.block = true,
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    DECORATION_LOCATION => decorations.location = Some(operand(2)?),
+                    DECORATION_BINDING => decorations.binding = Some(operand(2)?),
+                    DECORATION_DESCRIPTOR_SET => decorations.descriptor_set = Some(operand(2)?),
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                    _ => (),
+                }
+            },
+            OP_MEMBER_DECORATE => {
+                let decorations = self.member_decorations.entry((operand(0)?, operand(1)?)).or_default();
+                match operand(2)? {
+                    DECORATION_OFFSET => decorations.offset = Some(operand(3)?),
+                    DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    _ => (),
+                }
+            },
+            _ => (),
+        };
+
+        Ok(())
+    }
+
+    fn get_type(&self, id: u32) -> Result<&SpirvType, Box<dyn Error>> {
+        self.types.get(&id).ok_or_else(|| format!("Unknown SPIR-V type id {}", id).into())
+    }
+
+    /// Size in bytes of a type laid out with explicit offsets/strides
+    fn type_size(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, Box<dyn Error>> {
+        Ok(match self.get_type(id)? {
+            SpirvType::Bool => 4,
+            SpirvType::Int { width, .. } | SpirvType::Float { width } => width / 8,
+            SpirvType::Vector { component, count } => self.type_size(*component, None)? * count,
+            SpirvType::Matrix { column, count } => match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.type_size(*column, None)? * count,
+            },
+            SpirvType::Array { element, length } => {
+                let stride = match self.decorations.get(&id).and_then(|decorations| decorations.array_stride) {
+                    Some(stride) => stride,
+                    None => self.type_size(*element, matrix_stride)?,
+                };
+                stride * length
+            },
+            SpirvType::RuntimeArray { .. } => 0,
+            SpirvType::Struct { members } => {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(id, index as u32));
+                    let offset = decorations.and_then(|decorations| decorations.offset).unwrap_or(size);
+                    let member_size = self.type_size(*member, decorations.and_then(|decorations| decorations.matrix_stride))?;
+                    size = size.max(offset + member_size);
+                }
+                size
+            },
+            _ => return Err(format!("SPIR-V type {} has no defined size", id).into()),
+        })
+    }
+
+    fn is_built_in_block(&self, id: u32) -> bool {
+        match self.types.get(&id) {
+            Some(SpirvType::Struct { members }) => (0..members.len() as u32).any(|member| {
+                self.member_decorations.get(&(id, member)).map_or(false, |decorations| decorations.built_in)
+            }),
+            _ => false,
+        }
+    }
+
+    fn interface_variable(&self, variable: &Variable, pointee: u32) -> Result<Option<InterfaceVariable>, Box<dyn Error>> {
+
+        let decorations = self.decorations.get(&variable.id);
+
+        if decorations.map_or(false, |decorations| decorations.built_in) || self.is_built_in_block(pointee) {
+            return Ok(None);
+        }
+
+        let location = decorations
+            .and_then(|decorations| decorations.location)
+            .ok_or_else(|| format!("Interface variable {} has no location", variable.id))?;
+
+        let (element, array_length) = match self.get_type(pointee)? {
+            SpirvType::Array { element, length } => (*element, *length),
+            _ => (pointee, 1),
+        };
+
+        let (scalar, components, columns) = match self.get_type(element)? {
+            SpirvType::Vector { component, count } => (*component, *count, 1),
+            SpirvType::Matrix { column, count } => match self.get_type(*column)? {
+                SpirvType::Vector { component, count: rows } => (*component, *rows, *count),
+                _ => return Err("Matrix column must be a vector".into()),
+            },
+            _ => (element, 1, 1),
+        };
+
+        let component_type = match self.get_type(scalar)? {
+            SpirvType::Float { width: 64 } => ComponentType::Double,
+            SpirvType::Float { .. } => ComponentType::Float,
+            SpirvType::Int { signed: true, .. } => ComponentType::Int,
+            SpirvType::Int { signed: false, .. } | SpirvType::Bool => ComponentType::Uint,
+            _ => return Err(format!("Unsupported interface variable type for {}", variable.id).into()),
+        };
+
+        Ok(Some(InterfaceVariable {
+            location,
+            component_type,
+            components,
+            locations: columns * array_length,
+            name: self.names.get(&variable.id).cloned(),
+        }))
+    }
+
+    fn descriptor_binding(&self, variable: &Variable, pointee: u32, stages: ShaderStages) -> Result<DescriptorBinding, Box<dyn Error>> {
+
+        let decorations = self.decorations.get(&variable.id);
+        let set = decorations.and_then(|decorations| decorations.descriptor_set).unwrap_or(0);
+        let binding = decorations
+            .and_then(|decorations| decorations.binding)
+            .ok_or_else(|| format!("Descriptor variable {} has no binding", variable.id))?;
+
+        let (element, array_count) = match self.get_type(pointee)? {
+            SpirvType::Array { element, length } => (*element, *length),
+            SpirvType::RuntimeArray { element } => (*element, 0),
+            _ => (pointee, 1),
+        };
+
+        let mut descriptor = DescriptorBinding {
+            set,
+            binding,
+            kind: DescriptorKind::Sampler,
+            array_count,
+            block_size: None,
+            image_dimensions: None,
+            image_arrayed: false,
+            image_multisampled: false,
+            stages,
+            name: self.names.get(&variable.id).cloned(),
+        };
+
+        let image = match self.get_type(element)? {
+            SpirvType::Sampler => None,
+            SpirvType::SampledImage { image } => {
+                descriptor.kind = DescriptorKind::CombinedImageSampler;
+                Some(*image)
+            },
+            SpirvType::Image { .. } => Some(element),
+            SpirvType::Struct { .. } => {
+                let struct_decorations = self.decorations.get(&element);
+                let buffer_block = struct_decorations.map_or(false, |decorations| decorations.buffer_block);
+                descriptor.kind = if variable.storage_class == STORAGE_STORAGE_BUFFER || buffer_block {
+                    DescriptorKind::StorageBuffer
+                } else {
+                    DescriptorKind::UniformBuffer
+                };
+                descriptor.block_size = Some(self.type_size(element, None)?);
+                None
+            },
+            _ => return Err(format!("Unsupported descriptor type for binding {}", binding).into()),
+        };
+
+        if let Some(image) = image {
+            if let SpirvType::Image { dim, arrayed, multisampled, sampled } = self.get_type(image)? {
+                descriptor.image_arrayed = *arrayed;
+                descriptor.image_multisampled = *multisampled;
+                descriptor.image_dimensions = match dim {
+                    0 => Some(ImageDimensions::OneDimensional),
+                    1 | 4 => Some(ImageDimensions::TwoDimensional),
+                    2 => Some(ImageDimensions::ThreeDimensional),
+                    3 => Some(ImageDimensions::Cube),
+                    _ => None,
+                };
+
+                if descriptor.kind != DescriptorKind::CombinedImageSampler {
+                    descriptor.kind = match (dim, sampled) {
+                        // Buffer dimension
+                        (5, 2) => DescriptorKind::StorageTexelBuffer,
+                        (5, _) => DescriptorKind::UniformTexelBuffer,
+                        // SubpassData dimension
+                        (6, _) => DescriptorKind::InputAttachment,
+                        (_, 2) => DescriptorKind::StorageImage,
+                        _ => DescriptorKind::SampledImage,
+                    };
+                }
+            }
+        }
+
+        Ok(descriptor)
+    }
+}
+
+impl ShaderReflection {
+
+    pub fn from_bytes(spirv: &[u8]) -> Result<ShaderReflection, Box<dyn Error>> {
+
+        if spirv.len() % 4 != 0 {
+            return Err("SPIR-V byte code length must be a multiple of 4".into());
+        }
+
+        let words = spirv
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect::<Vec<_>>();
+
+        ShaderReflection::from_words(&words)
+    }
+
+    pub fn from_words(spirv: &[u32]) -> Result<ShaderReflection, Box<dyn Error>> {
+
+        let module = Module::parse(spirv)?;
+
+        let (execution_model, _, entry_point) = module.entry_point.clone().ok_or("SPIR-V module has no entry point")?;
+        let stage = ShaderStage::from_execution_model(execution_model)?;
+
+        let mut reflection = ShaderReflection {
+            stage,
+            entry_point,
+            descriptor_bindings: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            local_size: module.local_size,
+        };
+
+        for variable in module.variables.iter() {
+
+            let pointee = match module.get_type(variable.type_id)? {
+                SpirvType::Pointer { pointee, .. } => *pointee,
+                _ => return Err(format!("Variable {} is not a pointer", variable.id).into()),
+            };
+
+            match variable.storage_class {
+                STORAGE_INPUT => {
+                    if let Some(input) = module.interface_variable(variable, pointee)? {
+                        reflection.inputs.push(input);
+                    }
+                },
+                STORAGE_OUTPUT => {
+                    if let Some(output) = module.interface_variable(variable, pointee)? {
+                        reflection.outputs.push(output);
+                    }
+                },
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    reflection.descriptor_bindings.push(module.descriptor_binding(variable, pointee, stage.stages())?);
+                },
+                STORAGE_PUSH_CONSTANT => {
+                    let offset = match module.get_type(pointee)? {
+                        SpirvType::Struct { members } => (0..members.len() as u32)
+                            .filter_map(|member| module.member_decorations.get(&(pointee, member)).and_then(|decorations| decorations.offset))
+                            .min()
+                            .unwrap_or(0),
+                        _ => 0,
+                    };
+                    let size = module.type_size(pointee, None)?;
+
+                    reflection.push_constant_ranges.push(PushConstantRange {
+                        offset,
+                        size: size - offset,
+                        stages: stage.stages(),
+                    });
+                },
+                _ => (),
+            }
+        }
+
+        reflection.descriptor_bindings.sort_by_key(|descriptor| (descriptor.set, descriptor.binding));
+        reflection.inputs.sort_by_key(|input| input.location);
+        reflection.outputs.sort_by_key(|output| output.location);
+
+        Ok(reflection)
+    }
+
+    pub fn binding(&self, set: u32, binding: u32) -> Option<&DescriptorBinding> {
+        self.descriptor_bindings
+            .iter()
+            .find(|descriptor| descriptor.set == set && descriptor.binding == binding)
+    }
+}
+
+impl DescriptorBinding {
+
+    fn image_desc(&self, sampled: bool) -> DescriptorImageDesc {
+        DescriptorImageDesc {
+            sampled,
+            dimensions: match self.image_dimensions {
+                Some(ImageDimensions::OneDimensional) => DescriptorImageDescDimensions::OneDimensional,
+                Some(ImageDimensions::ThreeDimensional) => DescriptorImageDescDimensions::ThreeDimensional,
+                Some(ImageDimensions::Cube) => DescriptorImageDescDimensions::Cube,
+                _ => DescriptorImageDescDimensions::TwoDimensional,
+            },
+            format: None,
+            multisampled: self.image_multisampled,
+            array_layers: if self.image_arrayed {
+                DescriptorImageDescArray::Arrayed { max_layers: None }
+            } else {
+                DescriptorImageDescArray::NonArrayed
+            },
+        }
+    }
+
+    /// Converts to a vulkano descriptor description
+    ///
+    /// Dynamic uniform/storage buffers cannot be distinguished in SPIR-V so `dynamic_buffers` selects
+    /// which variant the layout is created with
+    pub fn descriptor_desc(&self, dynamic_buffers: bool) -> DescriptorDesc {
+
+        let ty = match self.kind {
+            DescriptorKind::Sampler => DescriptorDescTy::Sampler,
+            DescriptorKind::CombinedImageSampler => DescriptorDescTy::CombinedImageSampler(self.image_desc(true)),
+            DescriptorKind::SampledImage => DescriptorDescTy::Image(self.image_desc(true)),
+            DescriptorKind::StorageImage => DescriptorDescTy::Image(self.image_desc(false)),
+            DescriptorKind::UniformTexelBuffer => DescriptorDescTy::TexelBuffer { storage: false, format: None },
+            DescriptorKind::StorageTexelBuffer => DescriptorDescTy::TexelBuffer { storage: true, format: None },
+            DescriptorKind::UniformBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc {
+                dynamic: Some(dynamic_buffers),
+                storage: false,
+            }),
+            DescriptorKind::StorageBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc {
+                dynamic: Some(dynamic_buffers),
+                storage: true,
+            }),
+            DescriptorKind::InputAttachment => DescriptorDescTy::InputAttachment {
+                multisampled: self.image_multisampled,
+                array_layers: DescriptorImageDescArray::NonArrayed,
+            },
+        };
+
+        DescriptorDesc {
+            ty,
+            array_count: self.array_count.max(1),
+            stages: self.stages,
+            readonly: !matches!(self.kind, DescriptorKind::StorageBuffer | DescriptorKind::StorageImage | DescriptorKind::StorageTexelBuffer),
+        }
+    }
+}
+
+/// Pipeline layout built from the union of one or more reflected shader stages
+#[derive(Debug, Clone)]
+pub struct ReflectedPipelineLayout {
+    sets: Vec<Vec<Option<DescriptorBinding>>>,
+    push_constant_ranges: Vec<PushConstantRange>,
+    dynamic_buffers: bool,
+}
+
+impl ReflectedPipelineLayout {
+
+    pub fn new(stages: &[&ShaderReflection]) -> Result<ReflectedPipelineLayout, Box<dyn Error>> {
+
+        let mut sets: Vec<Vec<Option<DescriptorBinding>>> = Vec::new();
+        let mut push_constant_ranges: Vec<PushConstantRange> = Vec::new();
+
+        for reflection in stages {
+            for descriptor in reflection.descriptor_bindings.iter() {
+                let set = descriptor.set as usize;
+                let binding = descriptor.binding as usize;
+
+                if sets.len() <= set {
+                    sets.resize(set + 1, Vec::new());
+                }
+                if sets[set].len() <= binding {
+                    sets[set].resize(binding + 1, None);
+                }
+
+                match &mut sets[set][binding] {
+                    Some(existing) => {
+                        if existing.kind != descriptor.kind || existing.array_count != descriptor.array_count {
+                            return Err(format!(
+                                "Descriptor set {} binding {} is declared with conflicting types across shader stages",
+                                set, binding
+                            ).into());
+                        }
+                        existing.stages = existing.stages | descriptor.stages;
+                        existing.block_size = existing.block_size.max(descriptor.block_size);
+                    },
+                    slot => *slot = Some(descriptor.clone()),
+                };
+            }
+
+            for range in reflection.push_constant_ranges.iter() {
+                match push_constant_ranges.iter_mut().find(|existing| existing.offset == range.offset && existing.size == range.size) {
+                    Some(existing) => existing.stages = existing.stages | range.stages,
+                    None => push_constant_ranges.push(*range),
+                }
+            }
+        }
+
+        Ok(ReflectedPipelineLayout {
+            sets,
+            push_constant_ranges,
+            dynamic_buffers: false,
+        })
+    }
+
+    /// Creates uniform/storage buffer descriptors as dynamic (for use with dynamic offsets)
+    pub fn with_dynamic_buffers(mut self) -> ReflectedPipelineLayout {
+        self.dynamic_buffers = true;
+        self
+    }
+
+    pub fn set_bindings(&self, set: usize) -> &[Option<DescriptorBinding>] {
+        self.sets.get(set).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn descriptor_set_layout(&self, device: Arc<Device>, set: usize) -> Result<Arc<UnsafeDescriptorSetLayout>, Box<dyn Error>> {
+        let descriptors = self.set_bindings(set)
+            .iter()
+            .map(|binding| binding.as_ref().map(|binding| binding.descriptor_desc(self.dynamic_buffers)));
+
+        Ok(Arc::new(UnsafeDescriptorSetLayout::new(device, descriptors)?))
+    }
+
+    pub fn descriptor_set_layouts(&self, device: Arc<Device>) -> Result<Vec<Arc<UnsafeDescriptorSetLayout>>, Box<dyn Error>> {
+        (0..self.sets.len())
+            .map(|set| self.descriptor_set_layout(device.clone(), set))
+            .collect()
+    }
+
+    /// Starts a descriptor set that is validated against the reflected bindings of `set`
+    ///
+    /// `layout` must be compatible with this set, e.g. obtained from the pipeline
+    /// (`pipeline.descriptor_set_layout(set)`) or from `descriptor_set_layout`
+    pub fn descriptor_set_builder(
+        &self,
+        layout: Arc<UnsafeDescriptorSetLayout>,
+        set: usize,
+    ) -> DescriptorSetBuilder<()> {
+        DescriptorSetBuilder {
+            bindings: self.set_bindings(set).to_vec(),
+            next_binding: 0,
+            inner: PersistentDescriptorSet::start(layout),
+        }
+    }
+}
+
+unsafe impl PipelineLayoutDesc for ReflectedPipelineLayout {
+
+    fn num_sets(&self) -> usize {
+        self.sets.len()
+    }
+
+    fn num_bindings_in_set(&self, set: usize) -> Option<usize> {
+        self.sets.get(set).map(|bindings| bindings.len())
+    }
+
+    fn descriptor(&self, set: usize, binding: usize) -> Option<DescriptorDesc> {
+        self.sets
+            .get(set)
+            .and_then(|bindings| bindings.get(binding))
+            .and_then(|binding| binding.as_ref())
+            .map(|binding| binding.descriptor_desc(self.dynamic_buffers))
+    }
+
+    fn num_push_constants_ranges(&self) -> usize {
+        self.push_constant_ranges.len()
+    }
+
+    fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> {
+        self.push_constant_ranges.get(num).map(|range| PipelineLayoutDescPcRange {
+            offset: range.offset as usize,
+            size: range.size as usize,
+            stages: range.stages,
+        })
+    }
+}
+
+/// Shader interface (inputs or outputs) built from reflection
+#[derive(Debug, Clone)]
+pub struct ReflectedInterface {
+    entries: Vec<ShaderInterfaceDefEntry>,
+}
+
+impl ReflectedInterface {
+
+    fn new(variables: &[InterfaceVariable]) -> ReflectedInterface {
+        ReflectedInterface {
+            entries: variables
+                .iter()
+                .flat_map(|variable| {
+                    // Each column of a matrix (or element of an array) occupies its own location
+                    (0..variable.locations).map(move |index| ShaderInterfaceDefEntry {
+                        location: variable.location + index..variable.location + index + 1,
+                        format: variable.format(),
+                        name: variable.name.clone().map(Cow::Owned),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+unsafe impl ShaderInterfaceDef for ReflectedInterface {
+    type Iter = vec::IntoIter<ShaderInterfaceDefEntry>;
+
+    fn elements(&self) -> Self::Iter {
+        self.entries.clone().into_iter()
+    }
+}
+
+/// A shader module loaded from SPIR-V at runtime together with its reflection
+pub struct ReflectedShader {
+    pub module: Arc<ShaderModule>,
+    pub reflection: ShaderReflection,
+    entry_point: CString,
+    layout: ReflectedPipelineLayout,
+    inputs: ReflectedInterface,
+    outputs: ReflectedInterface,
+}
+
+impl ReflectedShader {
+
+    pub fn load(device: Arc<Device>, spirv: &[u8]) -> Result<ReflectedShader, Box<dyn Error>> {
+
+        let reflection = ShaderReflection::from_bytes(spirv)?;
+        let module = unsafe { ShaderModule::new(device, spirv)? };
+
+        let entry_point = CString::new(reflection.entry_point.clone())?;
+        let layout = ReflectedPipelineLayout::new(&[&reflection])?;
+        let inputs = ReflectedInterface::new(&reflection.inputs);
+        let outputs = ReflectedInterface::new(&reflection.outputs);
+
+        Ok(ReflectedShader {
+            module,
+            reflection,
+            entry_point,
+            layout,
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn layout(&self) -> &ReflectedPipelineLayout {
+        &self.layout
+    }
+
+    pub fn graphics_entry_point(
+        &self,
+    ) -> Result<GraphicsEntryPoint<(), ReflectedInterface, ReflectedInterface, ReflectedPipelineLayout>, Box<dyn Error>> {
+
+        let ty = match self.reflection.stage {
+            ShaderStage::Vertex => GraphicsShaderType::Vertex,
+            ShaderStage::Fragment => GraphicsShaderType::Fragment,
+            ShaderStage::TessellationControl => GraphicsShaderType::TessellationControl,
+            ShaderStage::TessellationEvaluation => GraphicsShaderType::TessellationEvaluation,
+            // @TODO - reflect geometry execution mode
+            _ => return Err(format!("{:?} shaders are not supported as graphics entry points", self.reflection.stage).into()),
+        };
+
+        // Safe since interfaces and layout were reflected from the module itself
+        Ok(unsafe {
+            self.module.graphics_entry_point(
+                &self.entry_point,
+                self.inputs.clone(),
+                self.outputs.clone(),
+                self.layout.clone(),
+                ty,
+            )
+        })
+    }
+
+    pub fn compute_entry_point(&self) -> Result<ComputeEntryPoint<(), ReflectedPipelineLayout>, Box<dyn Error>> {
+
+        if self.reflection.stage != ShaderStage::Compute {
+            return Err(format!("{:?} shader is not a compute shader", self.reflection.stage).into());
+        }
+
+        Ok(unsafe { self.module.compute_entry_point(&self.entry_point, self.layout.clone()) })
+    }
+}
+
+/// Wraps `PersistentDescriptorSetBuilder`, checking each resource against the shader's declared binding
+///
+/// Resources must be added in binding order; bindings that the shaders do not declare are skipped automatically
+pub struct DescriptorSetBuilder<R> {
+    bindings: Vec<Option<DescriptorBinding>>,
+    next_binding: usize,
+    inner: PersistentDescriptorSetBuilder<R>,
+}
+
+impl<R> DescriptorSetBuilder<R> {
+
+    /// Skips undeclared bindings and returns the next declared one
+    fn next_declared(mut self) -> Result<(Self, DescriptorBinding), Box<dyn Error>> {
+        loop {
+            match self.bindings.get(self.next_binding).cloned() {
+                Some(Some(binding)) => return Ok((self, binding)),
+                Some(None) => {
+                    // vulkano requires an explicit entry for every binding number
+                    self.inner = self.inner.add_empty()?;
+                    self.next_binding += 1;
+                },
+                None => return Err("More resources were supplied than the shader declares".into()),
+            }
+        }
+    }
+
+    fn expect_kind(binding: &DescriptorBinding, supplied: &str, kinds: &[DescriptorKind]) -> Result<(), Box<dyn Error>> {
+        if kinds.contains(&binding.kind) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Set {} binding {} ({}) is declared as {:?} but a {} was supplied",
+                binding.set,
+                binding.binding,
+                binding.name.as_deref().unwrap_or("unnamed"),
+                binding.kind,
+                supplied,
+            ).into())
+        }
+    }
+
+    fn advance<N>(self, inner: PersistentDescriptorSetBuilder<N>) -> DescriptorSetBuilder<N> {
+        DescriptorSetBuilder {
+            bindings: self.bindings,
+            next_binding: self.next_binding + 1,
+            inner,
+        }
+    }
+
+    pub fn add_buffer<T>(self, buffer: T) -> Result<DescriptorSetBuilder<(R, PersistentDescriptorSetBuf<T>)>, Box<dyn Error>>
+    where
+        T: BufferAccess,
+    {
+        let (this, binding) = self.next_declared()?;
+        Self::expect_kind(&binding, "buffer", &[DescriptorKind::UniformBuffer, DescriptorKind::StorageBuffer])?;
+
+        if let Some(block_size) = binding.block_size {
+            if buffer.size() < block_size as usize {
+                return Err(format!(
+                    "Set {} binding {} requires at least {} bytes but the supplied buffer is {} bytes",
+                    binding.set,
+                    binding.binding,
+                    block_size,
+                    buffer.size(),
+                ).into());
+            }
+        }
+
+        let inner = this.inner.add_buffer(buffer)?;
+        Ok(this.advance(inner))
+    }
+
+    pub fn add_sampled_image<T>(
+        self,
+        image: T,
+        sampler: Arc<Sampler>,
+    ) -> Result<DescriptorSetBuilder<((R, PersistentDescriptorSetImg<T>), PersistentDescriptorSetSampler)>, Box<dyn Error>>
+    where
+        T: ImageViewAccess,
+    {
+        let (this, binding) = self.next_declared()?;
+        Self::expect_kind(&binding, "combined image sampler", &[DescriptorKind::CombinedImageSampler])?;
+
+        let inner = this.inner.add_sampled_image(image, sampler)?;
+        Ok(this.advance(inner))
+    }
+
+    pub fn add_image<T>(self, image: T) -> Result<DescriptorSetBuilder<(R, PersistentDescriptorSetImg<T>)>, Box<dyn Error>>
+    where
+        T: ImageViewAccess,
+    {
+        let (this, binding) = self.next_declared()?;
+        Self::expect_kind(
+            &binding,
+            "image",
+            &[DescriptorKind::SampledImage, DescriptorKind::StorageImage, DescriptorKind::InputAttachment],
+        )?;
+
+        let inner = this.inner.add_image(image)?;
+        Ok(this.advance(inner))
+    }
+
+    pub fn add_sampler(self, sampler: Arc<Sampler>) -> Result<DescriptorSetBuilder<(R, PersistentDescriptorSetSampler)>, Box<dyn Error>> {
+        let (this, binding) = self.next_declared()?;
+        Self::expect_kind(&binding, "sampler", &[DescriptorKind::Sampler])?;
+
+        let inner = this.inner.add_sampler(sampler)?;
+        Ok(this.advance(inner))
+    }
+
+    /// Fails if any binding declared by the shaders has not been supplied
+    pub fn build(self) -> Result<PersistentDescriptorSet<R>, Box<dyn Error>> {
+
+        if let Some(binding) = self.bindings.iter().skip(self.next_binding).flatten().next() {
+            return Err(format!(
+                "Set {} binding {} ({}) was not supplied",
+                binding.set,
+                binding.binding,
+                binding.name.as_deref().unwrap_or("unnamed"),
+            ).into());
+        }
+
+        Ok(self.inner.build()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    /// Hand assembled equivalent of:
+    ///
+    ///     layout(location = 0) in vec2 position;
+    ///     layout(set = 0, binding = 1) uniform Data { mat4 transform; vec4 color; } data;
+    ///     layout(push_constant) uniform Constants { float time; } constants;
+    fn test_module() -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 32, 0];
+
+        // OpEntryPoint Vertex %1 "main"
+        words.extend(instruction(OP_ENTRY_POINT, &[0, 1, u32::from_le_bytes(*b"main"), 0]));
+        words.extend(instruction(OP_NAME, &[10, u32::from_le_bytes(*b"posi"), u32::from_le_bytes(*b"tion"), 0]));
+
+        words.extend(instruction(OP_DECORATE, &[10, DECORATION_LOCATION, 0]));
+        words.extend(instruction(OP_DECORATE, &[20, DECORATION_BLOCK]));
+        words.extend(instruction(OP_MEMBER_DECORATE, &[20, 0, DECORATION_OFFSET, 0]));
+        words.extend(instruction(OP_MEMBER_DECORATE, &[20, 0, DECORATION_MATRIX_STRIDE, 16]));
+        words.extend(instruction(OP_MEMBER_DECORATE, &[20, 1, DECORATION_OFFSET, 64]));
+        words.extend(instruction(OP_DECORATE, &[22, DECORATION_DESCRIPTOR_SET, 0]));
+        words.extend(instruction(OP_DECORATE, &[22, DECORATION_BINDING, 1]));
+        words.extend(instruction(OP_DECORATE, &[25, DECORATION_BLOCK]));
+        words.extend(instruction(OP_MEMBER_DECORATE, &[25, 0, DECORATION_OFFSET, 0]));
+
+        // float, vec2, vec4, mat4
+        words.extend(instruction(OP_TYPE_FLOAT, &[2, 32]));
+        words.extend(instruction(OP_TYPE_VECTOR, &[3, 2, 2]));
+        words.extend(instruction(OP_TYPE_VECTOR, &[4, 2, 4]));
+        words.extend(instruction(OP_TYPE_MATRIX, &[5, 4, 4]));
+
+        words.extend(instruction(OP_TYPE_POINTER, &[11, STORAGE_INPUT, 3]));
+        words.extend(instruction(OP_VARIABLE, &[11, 10, STORAGE_INPUT]));
+
+        words.extend(instruction(OP_TYPE_STRUCT, &[20, 5, 4]));
+        words.extend(instruction(OP_TYPE_POINTER, &[21, STORAGE_UNIFORM, 20]));
+        words.extend(instruction(OP_VARIABLE, &[21, 22, STORAGE_UNIFORM]));
+
+        words.extend(instruction(OP_TYPE_STRUCT, &[25, 2]));
+        words.extend(instruction(OP_TYPE_POINTER, &[26, STORAGE_PUSH_CONSTANT, 25]));
+        words.extend(instruction(OP_VARIABLE, &[26, 27, STORAGE_PUSH_CONSTANT]));
+
+        words
+    }
+
+    #[test]
+    fn reflects_bindings_push_constants_and_inputs() {
+        let reflection = ShaderReflection::from_words(&test_module()).unwrap();
+
+        assert_eq!(reflection.stage, ShaderStage::Vertex);
+        assert_eq!(reflection.entry_point, "main");
+
+        assert_eq!(reflection.inputs.len(), 1);
+        assert_eq!(reflection.inputs[0].location, 0);
+        assert_eq!(reflection.inputs[0].components, 2);
+        assert_eq!(reflection.inputs[0].name.as_deref(), Some("position"));
+
+        let binding = reflection.binding(0, 1).unwrap();
+        assert_eq!(binding.kind, DescriptorKind::UniformBuffer);
+        assert_eq!(binding.block_size, Some(80));
+
+        assert_eq!(reflection.push_constant_ranges.len(), 1);
+        assert_eq!(reflection.push_constant_ranges[0].offset, 0);
+        assert_eq!(reflection.push_constant_ranges[0].size, 4);
+    }
+
+    /// Module with a "main" entry point for `execution_model` followed by `instructions`
+    fn module(execution_model: u32, instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 64, 0];
+        words.extend(instruction(OP_ENTRY_POINT, &[execution_model, 1, u32::from_le_bytes(*b"main"), 0]));
+        words.extend(instructions.iter().flatten());
+        words
+    }
+
+    /// Push constant block `{ float a; vec4 b; }` with the members at `offsets`
+    fn push_constant_module(execution_model: u32, offsets: [u32; 2]) -> Vec<u32> {
+        module(execution_model, &[
+            instruction(OP_DECORATE, &[20, DECORATION_BLOCK]),
+            instruction(OP_MEMBER_DECORATE, &[20, 0, DECORATION_OFFSET, offsets[0]]),
+            instruction(OP_MEMBER_DECORATE, &[20, 1, DECORATION_OFFSET, offsets[1]]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_VECTOR, &[3, 2, 4]),
+            instruction(OP_TYPE_STRUCT, &[20, 2, 3]),
+            instruction(OP_TYPE_POINTER, &[21, STORAGE_PUSH_CONSTANT, 20]),
+            instruction(OP_VARIABLE, &[21, 22, STORAGE_PUSH_CONSTANT]),
+        ])
+    }
+
+    #[test]
+    fn rejects_invalid_header() {
+        assert!(ShaderReflection::from_words(&[0, 0, 0, 0, 0]).is_err());
+    }
+
+    /// Hand assembled equivalent of:
+    ///
+    ///     layout(location = 2) in vec4 weights[2];
+    ///     layout(set = 0, binding = 0) uniform sampler2D textures[3];
+    ///     layout(set = 0, binding = 1) uniform Lights { vec4 colors[4]; } lights;
+    ///     layout(set = 1, binding = 0) buffer Particles { vec4 positions[]; } particles;
+    #[test]
+    fn reflects_arrays() {
+        let words = module(4, &[
+            instruction(OP_DECORATE, &[12, DECORATION_LOCATION, 2]),
+            instruction(OP_DECORATE, &[22, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[22, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[30, DECORATION_ARRAY_STRIDE, 16]),
+            instruction(OP_DECORATE, &[31, DECORATION_BLOCK]),
+            instruction(OP_MEMBER_DECORATE, &[31, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_DECORATE, &[33, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[33, DECORATION_BINDING, 1]),
+            instruction(OP_DECORATE, &[40, DECORATION_ARRAY_STRIDE, 16]),
+            instruction(OP_DECORATE, &[41, DECORATION_BLOCK]),
+            instruction(OP_MEMBER_DECORATE, &[41, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_DECORATE, &[43, DECORATION_DESCRIPTOR_SET, 1]),
+            instruction(OP_DECORATE, &[43, DECORATION_BINDING, 0]),
+
+            // float, vec4, uint and the array lengths 2, 3 and 4
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_VECTOR, &[3, 2, 4]),
+            instruction(OP_TYPE_INT, &[4, 32, 0]),
+            instruction(OP_CONSTANT, &[4, 5, 2]),
+            instruction(OP_CONSTANT, &[4, 6, 3]),
+            instruction(OP_CONSTANT, &[4, 7, 4]),
+
+            instruction(OP_TYPE_ARRAY, &[10, 3, 5]),
+            instruction(OP_TYPE_POINTER, &[11, STORAGE_INPUT, 10]),
+            instruction(OP_VARIABLE, &[11, 12, STORAGE_INPUT]),
+
+            instruction(OP_TYPE_IMAGE, &[15, 2, 1, 0, 0, 0, 1, 0]),
+            instruction(OP_TYPE_SAMPLED_IMAGE, &[16, 15]),
+            instruction(OP_TYPE_ARRAY, &[20, 16, 6]),
+            instruction(OP_TYPE_POINTER, &[21, STORAGE_UNIFORM_CONSTANT, 20]),
+            instruction(OP_VARIABLE, &[21, 22, STORAGE_UNIFORM_CONSTANT]),
+
+            instruction(OP_TYPE_ARRAY, &[30, 3, 7]),
+            instruction(OP_TYPE_STRUCT, &[31, 30]),
+            instruction(OP_TYPE_POINTER, &[32, STORAGE_UNIFORM, 31]),
+            instruction(OP_VARIABLE, &[32, 33, STORAGE_UNIFORM]),
+
+            instruction(OP_TYPE_RUNTIME_ARRAY, &[40, 3]),
+            instruction(OP_TYPE_STRUCT, &[41, 40]),
+            instruction(OP_TYPE_POINTER, &[42, STORAGE_STORAGE_BUFFER, 41]),
+            instruction(OP_VARIABLE, &[42, 43, STORAGE_STORAGE_BUFFER]),
+        ]);
+
+        let reflection = ShaderReflection::from_words(&words).unwrap();
+
+        assert_eq!(reflection.stage, ShaderStage::Fragment);
+
+        assert_eq!(reflection.inputs.len(), 1);
+        assert_eq!(reflection.inputs[0].location, 2);
+        assert_eq!(reflection.inputs[0].components, 4);
+        assert_eq!(reflection.inputs[0].locations, 2);
+
+        let textures = reflection.binding(0, 0).unwrap();
+        assert_eq!(textures.kind, DescriptorKind::CombinedImageSampler);
+        assert_eq!(textures.array_count, 3);
+        assert_eq!(textures.image_dimensions, Some(ImageDimensions::TwoDimensional));
+
+        let lights = reflection.binding(0, 1).unwrap();
+        assert_eq!(lights.kind, DescriptorKind::UniformBuffer);
+        assert_eq!(lights.array_count, 1);
+        assert_eq!(lights.block_size, Some(64));
+
+        let particles = reflection.binding(1, 0).unwrap();
+        assert_eq!(particles.kind, DescriptorKind::StorageBuffer);
+        assert_eq!(particles.block_size, Some(0));
+    }
+
+    #[test]
+    fn rejects_specialization_constant_array_lengths() {
+        const OP_SPEC_CONSTANT: u32 = 50;
+
+        let words = module(4, &[
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_INT, &[4, 32, 0]),
+            instruction(OP_SPEC_CONSTANT, &[4, 5, 8]),
+            instruction(OP_TYPE_ARRAY, &[10, 2, 5]),
+        ]);
+
+        assert!(ShaderReflection::from_words(&words).is_err());
+    }
+
+    #[test]
+    fn push_constant_ranges_start_at_the_first_member() {
+        let reflection = ShaderReflection::from_words(&push_constant_module(0, [16, 32])).unwrap();
+
+        assert_eq!(reflection.push_constant_ranges, vec![PushConstantRange {
+            offset: 16,
+            size: 32,
+            stages: ShaderStages { vertex: true, ..ShaderStages::none() },
+        }]);
+    }
+
+    #[test]
+    fn pipeline_layouts_merge_identical_push_constant_ranges() {
+        let vertex = ShaderReflection::from_words(&push_constant_module(0, [0, 16])).unwrap();
+        let fragment = ShaderReflection::from_words(&push_constant_module(4, [0, 16])).unwrap();
+        let compute = ShaderReflection::from_words(&push_constant_module(5, [32, 48])).unwrap();
+
+        let layout = ReflectedPipelineLayout::new(&[&vertex, &fragment, &compute]).unwrap();
+
+        assert_eq!(layout.push_constant_ranges, vec![
+            PushConstantRange {
+                offset: 0,
+                size: 32,
+                stages: ShaderStages { vertex: true, fragment: true, ..ShaderStages::none() },
+            },
+            PushConstantRange {
+                offset: 32,
+                size: 32,
+                stages: ShaderStages { compute: true, ..ShaderStages::none() },
+            },
+        ]);
+    }
+
+    /// Hand assembled equivalent of:
+    ///
+    ///     layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInputMS depth;
+    ///     layout(set = 0, binding = 1, rgba8) uniform writeonly image2DArray layers;
+    #[test]
+    fn reflects_input_attachments_and_storage_images() {
+        let words = module(4, &[
+            instruction(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[12, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[22, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[22, DECORATION_BINDING, 1]),
+
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+
+            // Dim SubpassData, multisampled, sampled 2
+            instruction(OP_TYPE_IMAGE, &[10, 2, 6, 0, 0, 1, 2, 0]),
+            instruction(OP_TYPE_POINTER, &[11, STORAGE_UNIFORM_CONSTANT, 10]),
+            instruction(OP_VARIABLE, &[11, 12, STORAGE_UNIFORM_CONSTANT]),
+
+            // Dim 2D, arrayed, sampled 2, format Rgba8
+            instruction(OP_TYPE_IMAGE, &[20, 2, 1, 0, 1, 0, 2, 4]),
+            instruction(OP_TYPE_POINTER, &[21, STORAGE_UNIFORM_CONSTANT, 20]),
+            instruction(OP_VARIABLE, &[21, 22, STORAGE_UNIFORM_CONSTANT]),
+        ]);
+
+        let reflection = ShaderReflection::from_words(&words).unwrap();
+
+        let depth = reflection.binding(0, 0).unwrap();
+        assert_eq!(depth.kind, DescriptorKind::InputAttachment);
+        assert!(depth.image_multisampled);
+
+        let layers = reflection.binding(0, 1).unwrap();
+        assert_eq!(layers.kind, DescriptorKind::StorageImage);
+        assert_eq!(layers.image_dimensions, Some(ImageDimensions::TwoDimensional));
+        assert!(layers.image_arrayed);
+        assert!(!layers.image_multisampled);
+    }
+}
@@ -0,0 +1,634 @@
+use crate::{
+    math::{
+        self,
+        Matrix4,
+        Vector3,
+    },
+    upload::UploadManager,
+    vulkan_app::AcquiredImage,
+};
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::Path,
+    sync::Arc,
+};
+
+use winit::window::Window;
+
+use rusttype::{
+    point,
+    Font,
+    Scale,
+};
+
+use vulkano::{
+    buffer::{
+        BufferUsage,
+        CpuBufferPool,
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        DynamicState,
+    },
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+    },
+    format::{
+        ClearValue,
+        Format,
+    },
+    framebuffer::{
+        Framebuffer,
+        FramebufferAbstract,
+        RenderPassAbstract,
+        Subpass,
+    },
+    image::{
+        Dimensions,
+        SwapchainImage,
+    },
+    pipeline::{
+        viewport::Viewport,
+        GraphicsPipeline,
+        GraphicsPipelineAbstract,
+    },
+    sampler::{
+        Filter,
+        MipmapMode,
+        Sampler,
+        SamplerAddressMode,
+    },
+};
+
+pub type Color = [f32; 4];
+
+/// Printable ASCII, enough for stats and labels
+const DEFAULT_CHARACTERS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+const ATLAS_WIDTH: u32 = 512;
+
+/// Empty texels between glyphs so linear filtering never bleeds into a neighbour
+const GLYPH_PADDING: u32 = 1;
+
+mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(push_constant) uniform PushConstants {
+                vec2 scale;
+                vec2 translate;
+            } pc;
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 uv;
+            layout(location = 2) in vec4 color;
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) out vec4 v_color;
+
+            void main() {
+                v_uv = uv;
+                v_color = color;
+                gl_Position = vec4(position * pc.scale + pc.translate, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(v_color.rgb, v_color.a * texture(atlas, v_uv).r);
+            }
+        "
+    }
+}
+
+/// Placement of a single rasterised glyph, in pixels relative to the pen position on the baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub advance: f32,
+    /// Top left corner of the bitmap (+Y down)
+    pub offset: [f32; 2],
+    /// Zero for glyphs without a bitmap (e.g. space)
+    pub size: [f32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+/// A positioned, textured quad produced by `FontAtlas::layout`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+/// Glyph metrics, kerning and a single channel coverage bitmap for one font at one pixel size
+#[derive(Debug, Clone, Default)]
+pub struct FontAtlas {
+    pub pixel_height: f32,
+    /// Distance from the top of a line to its baseline
+    pub ascent: f32,
+    /// Distance between consecutive baselines
+    pub line_height: f32,
+    pub glyphs: HashMap<char, Glyph>,
+    /// Only pairs with a non zero adjustment are stored
+    pub kerning: HashMap<(char, char), f32>,
+    pub width: u32,
+    pub height: u32,
+    /// R8 coverage, `width * height` bytes
+    pub pixels: Vec<u8>,
+}
+
+impl FontAtlas {
+
+    /// Rasterises printable ASCII from a TTF/OTF file
+    pub fn from_file<P: AsRef<Path>>(path: P, pixel_height: f32) -> Result<FontAtlas, Box<dyn Error>> {
+        let bytes = std::fs::read(path.as_ref())
+            .map_err(|error| format!("Unable to read font {}: {}", path.as_ref().display(), error))?;
+        FontAtlas::rasterize(&bytes, pixel_height, DEFAULT_CHARACTERS)
+    }
+
+    /// Rasterises `characters` into a shelf packed atlas
+    ///
+    /// Characters the font has no glyph for are skipped.
+    pub fn rasterize(font_data: &[u8], pixel_height: f32, characters: &str) -> Result<FontAtlas, Box<dyn Error>> {
+
+        let font = Font::try_from_bytes(font_data).ok_or("Unable to parse font data")?;
+        let scale = Scale::uniform(pixel_height);
+        let v_metrics = font.v_metrics(scale);
+
+        let mut characters = characters.chars().collect::<Vec<_>>();
+        characters.sort_unstable();
+        characters.dedup();
+        characters.retain(|&character| font.glyph(character).id().0 != 0);
+
+        // Pack tallest first so shelves waste as little space as possible
+        let mut rasterized = characters
+            .iter()
+            .map(|&character| (character, font.glyph(character).scaled(scale).positioned(point(0.0, 0.0))))
+            .collect::<Vec<_>>();
+        rasterized.sort_by_key(|(_, glyph)| std::cmp::Reverse(glyph.pixel_bounding_box().map_or(0, |bounds| bounds.height())));
+
+        let mut placements = Vec::with_capacity(rasterized.len());
+        let (mut x, mut y, mut shelf_height) = (GLYPH_PADDING, GLYPH_PADDING, 0);
+
+        for (character, glyph) in rasterized.iter() {
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                None => {
+                    placements.push((*character, None));
+                    continue;
+                }
+            };
+
+            let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+            if width + 2 * GLYPH_PADDING > ATLAS_WIDTH {
+                return Err(format!("Glyph '{}' is too wide for the font atlas", character).into());
+            }
+            if x + width + GLYPH_PADDING > ATLAS_WIDTH {
+                x = GLYPH_PADDING;
+                y += shelf_height + GLYPH_PADDING;
+                shelf_height = 0;
+            }
+
+            placements.push((*character, Some([x, y])));
+            x += width + GLYPH_PADDING;
+            shelf_height = shelf_height.max(height);
+        }
+
+        let height = (y + shelf_height + GLYPH_PADDING).next_power_of_two();
+        let mut pixels = vec![0; (ATLAS_WIDTH * height) as usize];
+        let mut glyphs = HashMap::with_capacity(characters.len());
+
+        for ((character, glyph), (_, placement)) in rasterized.iter().zip(placements.iter()) {
+            let advance = font.glyph(*character).scaled(scale).h_metrics().advance_width;
+
+            let (bounds, [x, y]) = match (glyph.pixel_bounding_box(), placement) {
+                (Some(bounds), Some(placement)) => (bounds, *placement),
+                _ => {
+                    glyphs.insert(*character, Glyph {
+                        advance,
+                        offset: [0.0, 0.0],
+                        size: [0.0, 0.0],
+                        uv_min: [0.0, 0.0],
+                        uv_max: [0.0, 0.0],
+                    });
+                    continue;
+                }
+            };
+
+            glyph.draw(|glyph_x, glyph_y, coverage| {
+                let index = (y + glyph_y) * ATLAS_WIDTH + x + glyph_x;
+                pixels[index as usize] = (coverage * 255.0).round() as u8;
+            });
+
+            let (width, glyph_height) = (bounds.width() as f32, bounds.height() as f32);
+            glyphs.insert(*character, Glyph {
+                advance,
+                offset: [bounds.min.x as f32, bounds.min.y as f32],
+                size: [width, glyph_height],
+                uv_min: [x as f32 / ATLAS_WIDTH as f32, y as f32 / height as f32],
+                uv_max: [(x as f32 + width) / ATLAS_WIDTH as f32, (y as f32 + glyph_height) / height as f32],
+            });
+        }
+
+        let mut kerning = HashMap::new();
+        for &first in characters.iter() {
+            for &second in characters.iter() {
+                let adjustment = font.pair_kerning(scale, first, second);
+                if adjustment != 0.0 {
+                    kerning.insert((first, second), adjustment);
+                }
+            }
+        }
+
+        Ok(FontAtlas {
+            pixel_height,
+            ascent: v_metrics.ascent,
+            line_height: v_metrics.ascent - v_metrics.descent + v_metrics.line_gap,
+            glyphs,
+            kerning,
+            width: ATLAS_WIDTH,
+            height,
+            pixels,
+        })
+    }
+
+    /// Missing characters fall back to '?' and are dropped if that is missing too
+    fn glyph(&self, character: char) -> Option<(char, &Glyph)> {
+        self.glyphs
+            .get(&character)
+            .map(|glyph| (character, glyph))
+            .or_else(|| self.glyphs.get(&'?').map(|glyph| ('?', glyph)))
+    }
+
+    fn kerning(&self, previous: Option<char>, character: char) -> f32 {
+        previous.and_then(|previous| self.kerning.get(&(previous, character))).cloned().unwrap_or(0.0)
+    }
+
+    /// Width of a single line without wrapping
+    pub fn measure(&self, text: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for character in text.chars() {
+            if let Some((character, glyph)) = self.glyph(character) {
+                width += self.kerning(previous, character) + glyph.advance;
+                previous = Some(character);
+            }
+        }
+        width
+    }
+
+    /// Lays out `text` with its top left corner at the origin (+Y down)
+    ///
+    /// Lines break at '\n' and, when `max_width` is given, between words that would overflow it.
+    /// Words longer than `max_width` are left to overflow rather than being split.
+    pub fn layout(&self, text: &str, max_width: Option<f32>) -> Vec<GlyphQuad> {
+        let mut quads = Vec::with_capacity(text.len());
+        let space_advance = self.glyphs.get(&' ').map_or(self.pixel_height * 0.25, |glyph| glyph.advance);
+        let mut baseline = self.ascent;
+
+        for line in text.split('\n') {
+            let mut x = 0.0;
+            let mut line_empty = true;
+
+            for word in line.split(' ') {
+                let word_width = self.measure(word);
+
+                if !line_empty {
+                    if max_width.map_or(false, |max_width| x + space_advance + word_width > max_width) {
+                        x = 0.0;
+                        baseline += self.line_height;
+                    } else {
+                        x += space_advance;
+                    }
+                }
+                line_empty = false;
+
+                let mut previous = None;
+                for character in word.chars() {
+                    if let Some((character, glyph)) = self.glyph(character) {
+                        x += self.kerning(previous, character);
+                        previous = Some(character);
+
+                        if glyph.size[0] > 0.0 && glyph.size[1] > 0.0 {
+                            let min = [x + glyph.offset[0], baseline + glyph.offset[1]];
+                            quads.push(GlyphQuad {
+                                min,
+                                max: [min[0] + glyph.size[0], min[1] + glyph.size[1]],
+                                uv_min: glyph.uv_min,
+                                uv_max: glyph.uv_max,
+                            });
+                        }
+                        x += glyph.advance;
+                    }
+                }
+            }
+
+            baseline += self.line_height;
+        }
+
+        quads
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct TextVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(TextVertex, position, uv, color);
+
+type CachedFramebuffer = (Arc<SwapchainImage<Window>>, Arc<dyn FramebufferAbstract + Send + Sync>);
+
+/// Batched text for FPS counters and labels without a UI library
+///
+/// Strings are queued during the frame, either at a pixel position or anchored to a world
+/// position (labels stay a constant size on screen), and recorded in a pass of their own after
+/// the main pass, like `DebugDraw`.
+pub struct TextRenderer {
+    atlas: FontAtlas,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertex_pool: CpuBufferPool<TextVertex>,
+    framebuffers: HashMap<usize, CachedFramebuffer>,
+    vertices: Vec<TextVertex>,
+    viewport_size: [f32; 2],
+}
+
+impl TextRenderer {
+
+    pub fn new(
+        upload_manager: &mut UploadManager,
+        atlas: FontAtlas,
+        color_format: Format,
+    ) -> Result<TextRenderer, Box<dyn Error>> {
+
+        let device = upload_manager.device().clone();
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Load,
+                    store: Store,
+                    format: color_format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )?);
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        let pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync> = Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<TextVertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .blend_alpha_blending()
+            .render_pass(Subpass::from(render_pass.clone(), 0).ok_or("Unable to build subpass")?)
+            .build(device.clone())?);
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )?;
+
+        let image = upload_manager.upload_image(
+            &atlas.pixels,
+            Dimensions::Dim2d { width: atlas.width, height: atlas.height },
+            Format::R8Unorm,
+        )?;
+
+        let layout = pipeline.descriptor_set_layout(0).ok_or("Text pipeline has no descriptor set 0")?;
+        let descriptor_set = Arc::new(PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(image, sampler)?
+            .build()?);
+
+        Ok(TextRenderer {
+            atlas,
+            render_pass,
+            pipeline,
+            descriptor_set,
+            vertex_pool: CpuBufferPool::new(device, BufferUsage::vertex_buffer()),
+            framebuffers: HashMap::new(),
+            vertices: Vec::new(),
+            viewport_size: [1.0, 1.0],
+        })
+    }
+
+    pub fn atlas(&self) -> &FontAtlas {
+        &self.atlas
+    }
+
+    /// Should be called from `AppEventHandler::on_window_resize`, used to place world space labels
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.viewport_size = [width as f32, height as f32];
+        }
+    }
+
+    /// Queues `text` with its top left corner at `position` in pixels
+    pub fn text(&mut self, text: &str, position: [f32; 2], color: Color) {
+        self.wrapped_text(text, position, None, color);
+    }
+
+    /// Like `text` but wraps between words at `max_width` pixels
+    pub fn wrapped_text(&mut self, text: &str, position: [f32; 2], max_width: Option<f32>, color: Color) {
+        for quad in self.atlas.layout(text, max_width) {
+            let min = [quad.min[0] + position[0], quad.min[1] + position[1]];
+            let max = [quad.max[0] + position[0], quad.max[1] + position[1]];
+
+            let corners = [
+                ([min[0], min[1]], [quad.uv_min[0], quad.uv_min[1]]),
+                ([max[0], min[1]], [quad.uv_max[0], quad.uv_min[1]]),
+                ([max[0], max[1]], [quad.uv_max[0], quad.uv_max[1]]),
+                ([min[0], max[1]], [quad.uv_min[0], quad.uv_max[1]]),
+            ];
+
+            for &index in [0, 1, 2, 0, 2, 3].iter() {
+                let (position, uv) = corners[index];
+                self.vertices.push(TextVertex { position, uv, color });
+            }
+        }
+    }
+
+    /// Queues `text` centered above `world_position`, skipped when it is behind the camera
+    ///
+    /// The label is drawn on top of the scene at a constant pixel size.
+    pub fn label(&mut self, text: &str, world_position: Vector3, view_projection: &Matrix4, color: Color) {
+        let clip = math::transform_vector4(view_projection, [world_position[0], world_position[1], world_position[2], 1.0]);
+        if clip[3] <= 0.0 {
+            return;
+        }
+
+        // Vulkan NDC is already +Y down, like pixel coordinates
+        let screen = [
+            (clip[0] / clip[3] * 0.5 + 0.5) * self.viewport_size[0],
+            (clip[1] / clip[3] * 0.5 + 0.5) * self.viewport_size[1],
+        ];
+        let width = self.atlas.measure(text);
+        self.text(text, [screen[0] - width * 0.5, screen[1] - self.atlas.line_height], color);
+    }
+
+    fn framebuffer(&mut self, acquired_image: &AcquiredImage) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, Box<dyn Error>> {
+        if let Some((image, framebuffer)) = self.framebuffers.get(&acquired_image.image_num) {
+            if Arc::ptr_eq(image, &acquired_image.image) {
+                return Ok(framebuffer.clone());
+            }
+        }
+
+        let framebuffer: Arc<dyn FramebufferAbstract + Send + Sync> = Arc::new(
+            Framebuffer::start(self.render_pass.clone())
+                .add(acquired_image.image.clone())?
+                .build()?,
+        );
+        self.framebuffers.insert(acquired_image.image_num, (acquired_image.image.clone(), framebuffer.clone()));
+
+        Ok(framebuffer)
+    }
+
+    /// Records and clears everything queued this frame, must be recorded outside of any render pass
+    pub fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        acquired_image: &AcquiredImage,
+    ) -> Result<(), Box<dyn Error>> {
+
+        if self.vertices.is_empty() {
+            return Ok(());
+        }
+
+        let framebuffer = self.framebuffer(acquired_image)?;
+        let dimensions = acquired_image.image.dimensions();
+
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            .. DynamicState::none()
+        };
+
+        let push_constants = vs::ty::PushConstants {
+            scale: [2.0 / dimensions[0] as f32, 2.0 / dimensions[1] as f32],
+            translate: [-1.0, -1.0],
+        };
+
+        let vertices = Arc::new(self.vertex_pool.chunk(self.vertices.drain(..).collect::<Vec<_>>().into_iter())?);
+
+        builder.begin_render_pass(framebuffer, false, vec![ClearValue::None])?;
+        builder.draw(self.pipeline.clone(), &dynamic_state, vec![vertices], self.descriptor_set.clone(), push_constants)?;
+        builder.end_render_pass()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monospaced 10 pixel glyphs with a 2 pixel tighter "AV" pair
+    fn monospaced_atlas() -> FontAtlas {
+        let mut atlas = FontAtlas {
+            pixel_height: 10.0,
+            ascent: 8.0,
+            line_height: 12.0,
+            ..FontAtlas::default()
+        };
+
+        for character in "AVab?".chars() {
+            atlas.glyphs.insert(character, Glyph {
+                advance: 10.0,
+                offset: [1.0, -8.0],
+                size: [8.0, 8.0],
+                uv_min: [0.0, 0.0],
+                uv_max: [1.0, 1.0],
+            });
+        }
+        atlas.glyphs.insert(' ', Glyph {
+            advance: 5.0,
+            offset: [0.0, 0.0],
+            size: [0.0, 0.0],
+            uv_min: [0.0, 0.0],
+            uv_max: [0.0, 0.0],
+        });
+        atlas.kerning.insert(('A', 'V'), -2.0);
+        atlas
+    }
+
+    #[test]
+    fn kerning_pairs_tighten_advance() {
+        let atlas = monospaced_atlas();
+        assert_eq!(atlas.measure("AV"), 18.0);
+        assert_eq!(atlas.measure("VA"), 20.0);
+
+        let quads = atlas.layout("AV", None);
+        assert_eq!(quads.len(), 2);
+        assert_eq!(quads[0].min, [1.0, 0.0]);
+        assert_eq!(quads[1].min, [9.0, 0.0]);
+    }
+
+    #[test]
+    fn layout_wraps_at_spaces_and_newlines() {
+        let atlas = monospaced_atlas();
+
+        // "ab ab" is 45 pixels wide so it only fits on one line without a limit
+        let unwrapped = atlas.layout("ab ab", None);
+        assert!(unwrapped.iter().all(|quad| quad.min[1] == 0.0));
+        assert_eq!(unwrapped[2].min[0], 26.0);
+
+        let wrapped = atlas.layout("ab ab", Some(30.0));
+        assert_eq!(wrapped.len(), 4);
+        assert_eq!(wrapped[2].min, [1.0, 12.0]);
+
+        let explicit = atlas.layout("a\nb", None);
+        assert_eq!(explicit[1].min, [1.0, 12.0]);
+    }
+
+    #[test]
+    fn missing_characters_fall_back() {
+        let atlas = monospaced_atlas();
+        assert_eq!(atlas.layout("a€", None).len(), 2);
+        assert_eq!(atlas.measure("€"), 10.0);
+    }
+}
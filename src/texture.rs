@@ -0,0 +1,384 @@
+use crate::upload::UploadManager;
+
+use std::{
+    convert::TryInto,
+    error::Error,
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use vulkano::{
+    device::Device,
+    format::Format,
+    image::{
+        Dimensions,
+        ImmutableImage,
+    },
+    sampler::{
+        Filter,
+        MipmapMode,
+        Sampler,
+        SamplerAddressMode,
+    },
+};
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureColorSpace {
+    /// Color data (albedo, emissive)
+    Srgb,
+    /// Non-color data (normal maps, metallic/roughness)
+    Linear,
+}
+
+/// A sampled device local image
+///
+/// Images double as their own views in vulkano so `image` can be bound directly
+pub struct Texture {
+    pub image: Arc<ImmutableImage<Format>>,
+    pub sampler: Arc<Sampler>,
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+}
+
+impl Texture {
+
+    /// Loads a PNG, JPEG or KTX2 file (detected from its contents)
+    pub fn from_file<P: AsRef<Path>>(
+        upload_manager: &mut UploadManager,
+        path: P,
+        color_space: TextureColorSpace,
+    ) -> Result<Texture, Box<dyn Error>> {
+
+        let bytes = fs::read(path.as_ref())
+            .map_err(|e| format!("Unable to read texture {}: {}", path.as_ref().display(), e))?;
+
+        Texture::from_memory(upload_manager, &bytes, color_space)
+    }
+
+    pub fn from_memory(
+        upload_manager: &mut UploadManager,
+        bytes: &[u8],
+        color_space: TextureColorSpace,
+    ) -> Result<Texture, Box<dyn Error>> {
+
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Texture::from_ktx2(upload_manager, bytes);
+        }
+
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        let (width, height) = image.dimensions();
+
+        Texture::from_rgba8(upload_manager, width, height, &image.into_raw(), color_space)
+    }
+
+    /// Creates a texture from tightly packed RGBA8 pixels, generating mipmaps when the format allows it
+    pub fn from_rgba8(
+        upload_manager: &mut UploadManager,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        color_space: TextureColorSpace,
+    ) -> Result<Texture, Box<dyn Error>> {
+
+        let expected_len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|texels| texels.checked_mul(4))
+            .ok_or_else(|| format!("{}x{} RGBA8 image is too large", width, height))?;
+
+        if pixels.len() != expected_len {
+            return Err(format!("Expected {} bytes of RGBA8 pixel data but got {}", expected_len, pixels.len()).into());
+        }
+
+        let format = match color_space {
+            TextureColorSpace::Srgb => Format::R8G8B8A8Srgb,
+            TextureColorSpace::Linear => Format::R8G8B8A8Unorm,
+        };
+
+        let dimensions = Dimensions::Dim2d { width, height };
+        let device = upload_manager.device().clone();
+
+        let (image, mip_levels) = if supports_linear_filtering(&device, format) {
+            let image = upload_manager.upload_image_with_mipmaps(pixels, dimensions, format)?;
+            let mip_levels = image.mipmap_levels();
+            (image, mip_levels)
+        } else {
+            warn!("{:?} does not support linear filtering, mipmaps will not be generated", format);
+            (upload_manager.upload_image(pixels, dimensions, format)?, 1)
+        };
+
+        Ok(Texture {
+            image,
+            sampler: create_sampler(device, mip_levels)?,
+            format,
+            width,
+            height,
+            mip_levels,
+        })
+    }
+
+    /// Loads an uncompressed or BCn KTX2 container, using its pre-baked mip levels
+    ///
+    /// Supercompressed, array, cubemap and 3D textures are not supported
+    pub fn from_ktx2(upload_manager: &mut UploadManager, bytes: &[u8]) -> Result<Texture, Box<dyn Error>> {
+
+        let ktx2 = Ktx2::parse(bytes)?;
+        let device = upload_manager.device().clone();
+
+        let format = ktx2_format(ktx2.vk_format)
+            .ok_or_else(|| format!("Unsupported KTX2 vkFormat {}", ktx2.vk_format))?;
+
+        if is_block_compressed(format) && !device.enabled_features().texture_compression_bc {
+            return Err(format!("{:?} requires the texture_compression_bc device feature", format).into());
+        }
+
+        let dimensions = Dimensions::Dim2d { width: ktx2.width, height: ktx2.height };
+
+        let (image, mip_levels) = if ktx2.generate_mipmaps {
+            if is_block_compressed(format) || !supports_linear_filtering(&device, format) {
+                return Err(format!("Cannot generate mipmaps for {:?}", format).into());
+            }
+
+            let base_level = ktx2.level_data(bytes, 0)?;
+            let image = upload_manager.upload_image_with_mipmaps(base_level, dimensions, format)?;
+            let mip_levels = image.mipmap_levels();
+            (image, mip_levels)
+        } else {
+            let levels = (0..ktx2.levels.len())
+                .map(|level| ktx2.level_data(bytes, level))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            (upload_manager.upload_image_levels(&levels, dimensions, format)?, levels.len() as u32)
+        };
+
+        Ok(Texture {
+            image,
+            sampler: create_sampler(device, mip_levels)?,
+            format,
+            width: ktx2.width,
+            height: ktx2.height,
+            mip_levels,
+        })
+    }
+}
+
+fn supports_linear_filtering(device: &Arc<Device>, format: Format) -> bool {
+    format
+        .properties(device.physical_device())
+        .optimal_tiling_features
+        .sampled_image_filter_linear
+}
+
+fn create_sampler(device: Arc<Device>, mip_levels: u32) -> Result<Arc<Sampler>, Box<dyn Error>> {
+    Ok(Sampler::new(
+        device,
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Linear,
+        SamplerAddressMode::Repeat,
+        SamplerAddressMode::Repeat,
+        SamplerAddressMode::Repeat,
+        0.0,
+        1.0,
+        0.0,
+        mip_levels as f32,
+    )?)
+}
+
+fn is_block_compressed(format: Format) -> bool {
+    match format {
+        Format::BC1_RGBUnormBlock | Format::BC1_RGBSrgbBlock
+        | Format::BC1_RGBAUnormBlock | Format::BC1_RGBASrgbBlock
+        | Format::BC2UnormBlock | Format::BC2SrgbBlock
+        | Format::BC3UnormBlock | Format::BC3SrgbBlock
+        | Format::BC4UnormBlock | Format::BC4SnormBlock
+        | Format::BC5UnormBlock | Format::BC5SnormBlock
+        | Format::BC6HUfloatBlock | Format::BC6HSfloatBlock
+        | Format::BC7UnormBlock | Format::BC7SrgbBlock => true,
+        _ => false,
+    }
+}
+
+/// Maps the raw VkFormat values KTX2 stores to the formats supported by the loader
+fn ktx2_format(vk_format: u32) -> Option<Format> {
+    match vk_format {
+        37 => Some(Format::R8G8B8A8Unorm),
+        43 => Some(Format::R8G8B8A8Srgb),
+        131 => Some(Format::BC1_RGBUnormBlock),
+        132 => Some(Format::BC1_RGBSrgbBlock),
+        133 => Some(Format::BC1_RGBAUnormBlock),
+        134 => Some(Format::BC1_RGBASrgbBlock),
+        135 => Some(Format::BC2UnormBlock),
+        136 => Some(Format::BC2SrgbBlock),
+        137 => Some(Format::BC3UnormBlock),
+        138 => Some(Format::BC3SrgbBlock),
+        139 => Some(Format::BC4UnormBlock),
+        140 => Some(Format::BC4SnormBlock),
+        141 => Some(Format::BC5UnormBlock),
+        142 => Some(Format::BC5SnormBlock),
+        143 => Some(Format::BC6HUfloatBlock),
+        144 => Some(Format::BC6HSfloatBlock),
+        145 => Some(Format::BC7UnormBlock),
+        146 => Some(Format::BC7SrgbBlock),
+        _ => None,
+    }
+}
+
+struct Ktx2Level {
+    offset: usize,
+    length: usize,
+}
+
+struct Ktx2 {
+    vk_format: u32,
+    width: u32,
+    height: u32,
+    /// A level count of zero requests mip generation at load time
+    generate_mipmaps: bool,
+    /// Always contains at least the base level
+    levels: Vec<Ktx2Level>,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    let word = bytes.get(offset..offset + 4).ok_or("Truncated KTX2 file")?;
+    Ok(u32::from_le_bytes(word.try_into()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Box<dyn Error>> {
+    let word = bytes.get(offset..offset + 8).ok_or("Truncated KTX2 file")?;
+    Ok(u64::from_le_bytes(word.try_into()?))
+}
+
+impl Ktx2 {
+
+    fn parse(bytes: &[u8]) -> Result<Ktx2, Box<dyn Error>> {
+
+        if !bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Err("Not a KTX2 file".into());
+        }
+
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?;
+        let face_count = read_u32(bytes, 36)?;
+        let level_count = read_u32(bytes, 40)?;
+        let supercompression_scheme = read_u32(bytes, 44)?;
+
+        if supercompression_scheme != 0 {
+            return Err("Supercompressed KTX2 files are not supported".into());
+        }
+
+        if height == 0 || depth > 1 || layer_count > 1 || face_count != 1 {
+            return Err("Only single layer 2D KTX2 textures are supported".into());
+        }
+
+        // The level index always has at least one entry, even when level_count is 0
+        let levels = (0..level_count.max(1) as usize)
+            .map(|level| -> Result<Ktx2Level, Box<dyn Error>> {
+                let entry = KTX2_HEADER_SIZE + level * KTX2_LEVEL_INDEX_ENTRY_SIZE;
+                Ok(Ktx2Level {
+                    offset: read_u64(bytes, entry)? as usize,
+                    length: read_u64(bytes, entry + 8)? as usize,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Ktx2 {
+            vk_format,
+            width,
+            height,
+            generate_mipmaps: level_count == 0,
+            levels,
+        })
+    }
+
+    fn level_data<'a>(&self, bytes: &'a [u8], level: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let level = self.levels.get(level).ok_or("Invalid KTX2 mip level")?;
+
+        let end = level.offset.checked_add(level.length).ok_or("KTX2 mip level lies outside of the file")?;
+
+        Ok(bytes
+            .get(level.offset..end)
+            .ok_or("KTX2 mip level lies outside of the file")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single layer 2D KTX2 file with `levels` as (offset, length) level index entries
+    fn ktx2_file(vk_format: u32, level_count: u32, supercompression_scheme: u32, levels: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for word in &[vk_format, 1, 8, 4, 0, 0, 1, level_count, supercompression_scheme] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.resize(KTX2_HEADER_SIZE, 0);
+
+        for (offset, length) in levels {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn ktx2_header_and_level_index_are_parsed() {
+        let level_start = (KTX2_HEADER_SIZE + 2 * KTX2_LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut bytes = ktx2_file(43, 2, 0, &[(level_start, 4), (level_start + 4, 2)]);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let ktx2 = Ktx2::parse(&bytes).unwrap();
+        assert_eq!(ktx2.vk_format, 43);
+        assert_eq!((ktx2.width, ktx2.height), (8, 4));
+        assert!(!ktx2.generate_mipmaps);
+        assert_eq!(ktx2.levels.len(), 2);
+        assert_eq!(ktx2.level_data(&bytes, 0).unwrap(), &[1, 2, 3, 4]);
+        assert_eq!(ktx2.level_data(&bytes, 1).unwrap(), &[5, 6]);
+        assert!(ktx2.level_data(&bytes, 2).is_err());
+        assert_eq!(ktx2_format(ktx2.vk_format), Some(Format::R8G8B8A8Srgb));
+    }
+
+    #[test]
+    fn ktx2_without_levels_requests_mip_generation() {
+        let level_start = (KTX2_HEADER_SIZE + KTX2_LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut bytes = ktx2_file(37, 0, 0, &[(level_start, 4)]);
+        bytes.extend_from_slice(&[0; 4]);
+
+        let ktx2 = Ktx2::parse(&bytes).unwrap();
+        assert!(ktx2.generate_mipmaps);
+        assert_eq!(ktx2.levels.len(), 1);
+        assert_eq!(ktx2.level_data(&bytes, 0).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn invalid_ktx2_files_are_rejected() {
+        let level_start = (KTX2_HEADER_SIZE + KTX2_LEVEL_INDEX_ENTRY_SIZE) as u64;
+
+        assert!(Ktx2::parse(b"not a ktx2 file").is_err());
+        assert!(Ktx2::parse(&KTX2_IDENTIFIER).is_err());
+        assert!(Ktx2::parse(&ktx2_file(37, 1, 2, &[(level_start, 0)])).is_err());
+
+        // Level index cut short
+        assert!(Ktx2::parse(&ktx2_file(37, 2, 0, &[(level_start, 0)])).is_err());
+
+        let mut cubemap = ktx2_file(37, 1, 0, &[(level_start, 0)]);
+        cubemap[36..40].copy_from_slice(&6u32.to_le_bytes());
+        assert!(Ktx2::parse(&cubemap).is_err());
+
+        // Levels past the end of the file, including offsets that would overflow
+        let bytes = ktx2_file(37, 1, 0, &[(level_start, 16)]);
+        assert!(Ktx2::parse(&bytes).unwrap().level_data(&bytes, 0).is_err());
+        let bytes = ktx2_file(37, 1, 0, &[(u64::MAX, 16)]);
+        assert!(Ktx2::parse(&bytes).unwrap().level_data(&bytes, 0).is_err());
+    }
+}
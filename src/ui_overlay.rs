@@ -0,0 +1,565 @@
+use crate::{
+    texture::Texture,
+    upload::UploadManager,
+    vulkan_app::{
+        AcquiredImage,
+        RenderState,
+    },
+};
+
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    error::Error,
+    sync::Arc,
+    time::Instant,
+};
+
+use imgui::{
+    im_str,
+    Condition,
+    Context,
+    DrawCmd,
+    DrawCmdParams,
+    FontSource,
+    Key,
+    TextureId,
+    Ui,
+};
+
+use winit::{
+    event::{
+        ElementState,
+        MouseButton,
+        MouseScrollDelta,
+        VirtualKeyCode,
+        WindowEvent,
+    },
+    window::Window,
+};
+
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferSlice,
+        BufferUsage,
+        CpuBufferPool,
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        DynamicState,
+    },
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+    },
+    format::{
+        ClearValue,
+        Format,
+    },
+    framebuffer::{
+        Framebuffer,
+        FramebufferAbstract,
+        RenderPassAbstract,
+        Subpass,
+    },
+    image::{
+        Dimensions,
+        ImageViewAccess,
+        SwapchainImage,
+    },
+    pipeline::{
+        viewport::{
+            Scissor,
+            Viewport,
+        },
+        GraphicsPipeline,
+        GraphicsPipelineAbstract,
+    },
+    sampler::{
+        Filter,
+        MipmapMode,
+        Sampler,
+        SamplerAddressMode,
+    },
+};
+
+/// Number of frame times kept for the stats graph
+const FRAME_HISTORY: usize = 120;
+
+mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(push_constant) uniform PushConstants {
+                vec2 scale;
+                vec2 translate;
+            } pc;
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 uv;
+            layout(location = 2) in vec4 color;
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) out vec4 v_color;
+
+            void main() {
+                v_uv = uv;
+                v_color = color;
+                gl_Position = vec4(position * pc.scale + pc.translate, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color * texture(tex, v_uv);
+            }
+        "
+    }
+}
+
+/// imgui's `DrawVert` packs colors as bytes, which vulkano cannot bind to a float shader input
+#[derive(Default, Debug, Clone, Copy)]
+struct UiVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(UiVertex, position, uv, color);
+
+fn is_srgb(format: Format) -> bool {
+    matches!(format, Format::R8G8B8A8Srgb | Format::B8G8R8A8Srgb | Format::A8B8G8R8SrgbPack32)
+}
+
+/// Dear ImGui overlay drawn in its own render pass on top of the swapchain image
+///
+/// The overlay loads the existing contents of the image, so it is recorded after the
+/// application's own render pass has ended.  Forward window events to `on_window_event` and
+/// check `wants_mouse`/`wants_keyboard` before using input elsewhere.
+pub struct UiOverlay {
+    context: Context,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    vertex_pool: CpuBufferPool<UiVertex>,
+    index_pool: CpuBufferPool<u16>,
+    sampler: Arc<Sampler>,
+    textures: HashMap<TextureId, Arc<dyn DescriptorSet + Send + Sync>>,
+    next_texture_id: usize,
+    /// Cached per swapchain image index, rebuilt when the swapchain is recreated
+    framebuffers: HashMap<usize, (Arc<SwapchainImage<Window>>, Arc<dyn FramebufferAbstract + Send + Sync>)>,
+    /// imgui colors are authored in sRGB, so they are linearized when rendering to an sRGB target
+    color_lut: [f32; 256],
+    device_name: String,
+    frame_times: VecDeque<f32>,
+    last_frame: Instant,
+    pub show_stats: bool,
+}
+
+impl UiOverlay {
+
+    /// `format` must match the swapchain images the overlay is drawn onto
+    pub fn new(
+        upload_manager: &mut UploadManager,
+        window: &Window,
+        format: Format,
+    ) -> Result<UiOverlay, Box<dyn Error>> {
+
+        let device = upload_manager.device().clone();
+
+        let mut context = Context::create();
+        context.set_ini_filename(None);
+        context.io_mut().font_global_scale = window.scale_factor() as f32;
+        UiOverlay::init_key_map(&mut context);
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Load,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )?);
+
+        let vs = vs::Shader::load(device.clone())?;
+        let fs = fs::Shader::load(device.clone())?;
+
+        let pipeline = Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<UiVertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .viewports_scissors_dynamic(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .blend_alpha_blending()
+            .render_pass(Subpass::from(render_pass.clone(), 0).ok_or("Unable to build subpass")?)
+            .build(device.clone())?);
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )?;
+
+        let font_image = {
+            let mut fonts = context.fonts();
+            fonts.add_font(&[FontSource::DefaultFontData { config: None }]);
+            let atlas = fonts.build_rgba32_texture();
+
+            upload_manager.upload_image(
+                atlas.data,
+                Dimensions::Dim2d { width: atlas.width, height: atlas.height },
+                Format::R8G8B8A8Unorm,
+            )?
+        };
+
+        let mut color_lut = [0.0; 256];
+        for (index, value) in color_lut.iter_mut().enumerate() {
+            let srgb = index as f32 / 255.0;
+            *value = if is_srgb(format) {
+                if srgb <= 0.04045 { srgb / 12.92 } else { ((srgb + 0.055) / 1.055).powf(2.4) }
+            } else {
+                srgb
+            };
+        }
+
+        let mut overlay = UiOverlay {
+            context,
+            render_pass,
+            pipeline,
+            vertex_pool: CpuBufferPool::new(device.clone(), BufferUsage::vertex_buffer()),
+            index_pool: CpuBufferPool::new(device.clone(), BufferUsage::index_buffer()),
+            sampler,
+            textures: HashMap::new(),
+            next_texture_id: 0,
+            framebuffers: HashMap::new(),
+            color_lut,
+            device_name: device.physical_device().name().to_string(),
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            last_frame: Instant::now(),
+            show_stats: true,
+        };
+
+        let font_texture = overlay.register_image(font_image)?;
+        overlay.context.fonts().tex_id = font_texture;
+
+        Ok(overlay)
+    }
+
+    fn init_key_map(context: &mut Context) {
+        let io = context.io_mut();
+        io[Key::Tab] = VirtualKeyCode::Tab as _;
+        io[Key::LeftArrow] = VirtualKeyCode::Left as _;
+        io[Key::RightArrow] = VirtualKeyCode::Right as _;
+        io[Key::UpArrow] = VirtualKeyCode::Up as _;
+        io[Key::DownArrow] = VirtualKeyCode::Down as _;
+        io[Key::PageUp] = VirtualKeyCode::PageUp as _;
+        io[Key::PageDown] = VirtualKeyCode::PageDown as _;
+        io[Key::Home] = VirtualKeyCode::Home as _;
+        io[Key::End] = VirtualKeyCode::End as _;
+        io[Key::Insert] = VirtualKeyCode::Insert as _;
+        io[Key::Delete] = VirtualKeyCode::Delete as _;
+        io[Key::Backspace] = VirtualKeyCode::Back as _;
+        io[Key::Space] = VirtualKeyCode::Space as _;
+        io[Key::Enter] = VirtualKeyCode::Return as _;
+        io[Key::Escape] = VirtualKeyCode::Escape as _;
+        io[Key::KeyPadEnter] = VirtualKeyCode::NumpadEnter as _;
+        io[Key::A] = VirtualKeyCode::A as _;
+        io[Key::C] = VirtualKeyCode::C as _;
+        io[Key::V] = VirtualKeyCode::V as _;
+        io[Key::X] = VirtualKeyCode::X as _;
+        io[Key::Y] = VirtualKeyCode::Y as _;
+        io[Key::Z] = VirtualKeyCode::Z as _;
+    }
+
+    /// Makes a texture usable with `imgui::Image`
+    pub fn register_texture(&mut self, texture: &Texture) -> Result<TextureId, Box<dyn Error>> {
+        self.register_image(texture.image.clone())
+    }
+
+    fn register_image<I>(&mut self, image: I) -> Result<TextureId, Box<dyn Error>>
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        let layout = self.pipeline.descriptor_set_layout(0).ok_or("UI pipeline has no descriptor set 0")?;
+
+        let set = PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(image, self.sampler.clone())?
+            .build()?;
+
+        let id = TextureId::from(self.next_texture_id);
+        self.next_texture_id += 1;
+        self.textures.insert(id, Arc::new(set));
+
+        Ok(id)
+    }
+
+    /// True while the UI is using the mouse (e.g. hovering a window), so cameras should ignore it
+    pub fn wants_mouse(&self) -> bool {
+        self.context.io().want_capture_mouse
+    }
+
+    pub fn wants_keyboard(&self) -> bool {
+        self.context.io().want_capture_keyboard
+    }
+
+    pub fn on_window_event(&mut self, event: &WindowEvent) {
+
+        let io = self.context.io_mut();
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                io.mouse_pos = [position.x as f32, position.y as f32];
+            }
+            WindowEvent::CursorLeft { .. } => {
+                io.mouse_pos = [f32::MAX, f32::MAX];
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let index = match button {
+                    MouseButton::Left => 0,
+                    MouseButton::Right => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Other(_) => return,
+                };
+                io.mouse_down[index] = *state == ElementState::Pressed;
+            }
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => {
+                    io.mouse_wheel_h += x;
+                    io.mouse_wheel += y;
+                }
+                MouseScrollDelta::PixelDelta(position) => {
+                    io.mouse_wheel_h += position.x as f32 / 100.0;
+                    io.mouse_wheel += position.y as f32 / 100.0;
+                }
+            },
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    io.keys_down[key as usize] = input.state == ElementState::Pressed;
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                io.key_shift = modifiers.shift();
+                io.key_ctrl = modifiers.ctrl();
+                io.key_alt = modifiers.alt();
+                io.key_super = modifiers.logo();
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                if !character.is_control() {
+                    io.add_input_character(*character);
+                }
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                io.font_global_scale = *scale_factor as f32;
+            }
+            WindowEvent::Focused(false) => {
+                io.keys_down.iter_mut().for_each(|key| *key = false);
+                io.mouse_down.iter_mut().for_each(|button| *button = false);
+            }
+            _ => (),
+        }
+    }
+
+    fn framebuffer(
+        &mut self,
+        acquired_image: &AcquiredImage,
+    ) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, Box<dyn Error>> {
+
+        if let Some((image, framebuffer)) = self.framebuffers.get(&acquired_image.image_num) {
+            if Arc::ptr_eq(image, &acquired_image.image) {
+                return Ok(framebuffer.clone());
+            }
+        }
+
+        let framebuffer: Arc<dyn FramebufferAbstract + Send + Sync> = Arc::new(
+            Framebuffer::start(self.render_pass.clone())
+                .add(acquired_image.image.clone())?
+                .build()?,
+        );
+
+        self.framebuffers.insert(acquired_image.image_num, (acquired_image.image.clone(), framebuffer.clone()));
+
+        Ok(framebuffer)
+    }
+
+    fn stats_window(ui: &Ui, frame_times: &[f32], device_name: &str, present_mode: &str) {
+
+        let average = frame_times.iter().sum::<f32>() / frame_times.len().max(1) as f32;
+
+        imgui::Window::new(im_str!("Stats"))
+            .position([10.0, 10.0], Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(ui, || {
+                ui.text(format!("FPS: {:.1}", if average > 0.0 { 1000.0 / average } else { 0.0 }));
+                ui.text(format!("Frame time: {:.2} ms", average));
+                ui.plot_lines(im_str!("##frame_times"), frame_times)
+                    .graph_size([240.0, 60.0])
+                    .scale_min(0.0)
+                    .build();
+                ui.separator();
+                ui.text(format!("Device: {}", device_name));
+                ui.text(format!("Present mode: {}", present_mode));
+            });
+    }
+
+    /// Builds the UI with `build_ui` (after the stats panel) and records it onto the acquired image
+    ///
+    /// Must be recorded outside of any render pass
+    pub fn record<F>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        render_state: &RenderState,
+        acquired_image: &AcquiredImage,
+        build_ui: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&Ui),
+    {
+        let now = Instant::now();
+        let delta_time = now - self.last_frame;
+        self.last_frame = now;
+
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(delta_time.as_secs_f32() * 1000.0);
+
+        let framebuffer = self.framebuffer(acquired_image)?;
+        let dimensions = acquired_image.image.dimensions();
+
+        let io = self.context.io_mut();
+        io.display_size = [dimensions[0] as f32, dimensions[1] as f32];
+        io.display_framebuffer_scale = [1.0, 1.0];
+        io.delta_time = delta_time.as_secs_f32().max(std::f32::EPSILON);
+
+        let frame_times = self.frame_times.iter().cloned().collect::<Vec<_>>();
+        let present_mode = format!("{:?}", render_state.swapchain.present_mode());
+        let show_stats = self.show_stats;
+
+        let ui = self.context.frame();
+
+        if show_stats {
+            UiOverlay::stats_window(&ui, &frame_times, &self.device_name, &present_mode);
+        }
+        build_ui(&ui);
+
+        let draw_data = ui.render();
+
+        if draw_data.total_idx_count == 0 {
+            return Ok(());
+        }
+
+        let scale = [2.0 / draw_data.display_size[0], 2.0 / draw_data.display_size[1]];
+        let push_constants = vs::ty::PushConstants {
+            scale,
+            translate: [
+                -1.0 - draw_data.display_pos[0] * scale[0],
+                -1.0 - draw_data.display_pos[1] * scale[1],
+            ],
+        };
+
+        let mut dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            .. DynamicState::none()
+        };
+
+        builder.begin_render_pass(framebuffer, false, vec![ClearValue::None])?;
+
+        for draw_list in draw_data.draw_lists() {
+            if draw_list.idx_buffer().is_empty() {
+                continue;
+            }
+
+            let color_lut = &self.color_lut;
+            let vertices = self.vertex_pool.chunk(draw_list.vtx_buffer().iter().map(|vertex| UiVertex {
+                position: vertex.pos,
+                uv: vertex.uv,
+                color: [
+                    color_lut[vertex.col[0] as usize],
+                    color_lut[vertex.col[1] as usize],
+                    color_lut[vertex.col[2] as usize],
+                    vertex.col[3] as f32 / 255.0,
+                ],
+            }))?;
+            let vertices: Arc<dyn BufferAccess + Send + Sync> = Arc::new(vertices);
+            let indices = Arc::new(self.index_pool.chunk(draw_list.idx_buffer().iter().cloned())?);
+
+            for command in draw_list.commands() {
+                // State is set per draw and user callbacks are not supported, so only elements matter
+                if let DrawCmd::Elements { count, cmd_params: DrawCmdParams { clip_rect, texture_id, idx_offset, .. } } = command {
+
+                    let x = ((clip_rect[0] - draw_data.display_pos[0]).max(0.0)) as u32;
+                    let y = ((clip_rect[1] - draw_data.display_pos[1]).max(0.0)) as u32;
+                    let right = ((clip_rect[2] - draw_data.display_pos[0]) as u32).min(dimensions[0]);
+                    let bottom = ((clip_rect[3] - draw_data.display_pos[1]) as u32).min(dimensions[1]);
+
+                    if right <= x || bottom <= y {
+                        continue;
+                    }
+
+                    dynamic_state.scissors = Some(vec![Scissor {
+                        origin: [x as i32, y as i32],
+                        dimensions: [right - x, bottom - y],
+                    }]);
+
+                    let set = self.textures
+                        .get(&texture_id)
+                        .ok_or_else(|| format!("Unknown UI texture {:?}", texture_id))?
+                        .clone();
+
+                    let index_slice = BufferSlice::from_typed_buffer_access(indices.clone())
+                        .slice(idx_offset..idx_offset + count)
+                        .ok_or("UI draw command lies outside of its index buffer")?;
+
+                    builder.draw_indexed(
+                        self.pipeline.clone(),
+                        &dynamic_state,
+                        vec![vertices.clone()],
+                        index_slice,
+                        set,
+                        push_constants,
+                    )?;
+                }
+            }
+        }
+
+        builder.end_render_pass()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    error::Error,
+    mem,
+    ptr,
+    sync::Arc,
+};
+
+use vulkano::{
+    buffer::{
+        BufferSlice,
+        BufferUsage,
+        CpuAccessibleBuffer,
+        TypedBufferAccess,
+    },
+    device::Device,
+};
+
+type RingBuffer = Arc<CpuAccessibleBuffer<[u8]>>;
+
+/// A chunk of per-frame uniform data written into the ring
+pub struct UniformAllocation {
+    pub buffer: RingBuffer,
+    /// Byte offset into `buffer` (use as the dynamic offset for dynamic uniform buffer descriptors)
+    pub offset: u32,
+    pub size: usize,
+}
+
+impl UniformAllocation {
+
+    /// Slice covering only this allocation (for non-dynamic descriptors)
+    pub fn slice(&self) -> BufferSlice<[u8], RingBuffer> {
+        let start = self.offset as usize;
+
+        self.buffer
+            .clone()
+            .into_buffer_slice()
+            .slice(start..start + self.size)
+            .expect("Allocation is always within the ring buffer")
+    }
+}
+
+/// Per-frame uniform buffer ring
+///
+/// Holds one host visible buffer per frame in flight and hands out chunks aligned to
+/// `min_uniform_buffer_offset_alignment`.  Chunks are only valid until the same frame slot comes
+/// around again, so the number of frames must be at least the number of frames the app lets
+/// the GPU run behind the CPU.
+pub struct UniformBufferRing {
+    frames: Vec<RingBuffer>,
+    alignment: usize,
+    capacity: usize,
+    current_frame: usize,
+    cursor: usize,
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+impl UniformBufferRing {
+
+    pub fn new(
+        device: Arc<Device>,
+        frames_in_flight: usize,
+        bytes_per_frame: usize,
+    ) -> Result<UniformBufferRing, Box<dyn Error>> {
+
+        if frames_in_flight == 0 {
+            return Err("Uniform buffer ring requires at least one frame".into());
+        }
+
+        let alignment = (device.physical_device().limits().min_uniform_buffer_offset_alignment() as usize).max(1);
+        let capacity = align_up(bytes_per_frame, alignment);
+
+        let frames = (0..frames_in_flight)
+            .map(|_| CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::uniform_buffer(),
+                false,
+                (0..capacity).map(|_| 0u8),
+            ))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(UniformBufferRing {
+            frames,
+            alignment,
+            capacity,
+            current_frame: 0,
+            cursor: 0,
+        })
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Moves to the next frame slot, recycling everything allocated from it previously
+    ///
+    /// Should be called once per frame before any allocations
+    pub fn begin_frame(&mut self) {
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
+        self.cursor = 0;
+    }
+
+    pub fn current_buffer(&self) -> RingBuffer {
+        self.frames[self.current_frame].clone()
+    }
+
+    /// Slice of the current frame's buffer sized for one `T`, for binding as a dynamic uniform buffer
+    pub fn dynamic_binding<T>(&self) -> Result<BufferSlice<[u8], RingBuffer>, Box<dyn Error>> {
+        Ok(self.current_buffer()
+            .into_buffer_slice()
+            .slice(0..mem::size_of::<T>())
+            .ok_or("Uniform type does not fit in the ring buffer")?)
+    }
+
+    pub fn allocate<T: Copy>(&mut self, data: &T) -> Result<UniformAllocation, Box<dyn Error>> {
+        self.allocate_with(mem::size_of::<T>(), |contents| unsafe {
+            // Written as a `T` rather than viewed as bytes, since reading padding bytes (e.g.
+            // after a shader struct's vec3 members) is undefined behaviour
+            ptr::write_unaligned(contents.as_mut_ptr() as *mut T, *data);
+        })
+    }
+
+    pub fn allocate_bytes(&mut self, bytes: &[u8]) -> Result<UniformAllocation, Box<dyn Error>> {
+        self.allocate_with(bytes.len(), |contents| contents.copy_from_slice(bytes))
+    }
+
+    /// Reserves `size` bytes in the current frame and fills them with `write`
+    fn allocate_with<F>(&mut self, size: usize, write: F) -> Result<UniformAllocation, Box<dyn Error>>
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let offset = align_up(self.cursor, self.alignment);
+        let end = offset + size;
+
+        if end > self.capacity {
+            return Err(format!(
+                "Uniform buffer ring exhausted ({} of {} bytes used this frame)",
+                self.cursor,
+                self.capacity,
+            ).into());
+        }
+
+        let buffer = self.current_buffer();
+
+        {
+            // Fails if the GPU is still using this frame slot (not enough frames in the ring)
+            let mut contents = buffer.write()?;
+            write(&mut contents[offset..end]);
+        }
+
+        self.cursor = end;
+
+        Ok(UniformAllocation {
+            buffer,
+            offset: offset as u32,
+            size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::align_up;
+
+    #[test]
+    fn align_up_rounds_to_alignment() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 64), 320);
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    error::Error,
+    sync::Arc,
+};
+
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferUsage,
+        CpuAccessibleBuffer,
+        ImmutableBuffer,
+    },
+    command_buffer::{
+        pool::standard::StandardCommandPoolAlloc,
+        sys::{
+            Flags,
+            Kind,
+            UnsafeCommandBuffer,
+            UnsafeCommandBufferBuilder,
+            UnsafeCommandBufferBuilderPipelineBarrier,
+        },
+        AutoCommandBufferBuilder,
+        CommandBuffer,
+        CommandBufferExecError,
+    },
+    device::{
+        Device,
+        DeviceOwned,
+        Queue,
+    },
+    format::Format,
+    image::{
+        Dimensions,
+        ImageAccess,
+        ImageLayout,
+        ImageUsage,
+        ImmutableImage,
+        MipmapsCount,
+    },
+    instance::QueueFamily,
+    sampler::Filter,
+    sync,
+    sync::{
+        AccessCheckError,
+        AccessFlagBits,
+        GpuFuture,
+        PipelineStages,
+    },
+};
+
+/// A resource written on the transfer queue that the graphics family has to acquire
+#[derive(Clone)]
+enum Transferred {
+    Buffer(Arc<dyn BufferAccess + Send + Sync>),
+    Image(Arc<dyn ImageAccess + Send + Sync>),
+}
+
+/// Which half of a queue family ownership transfer a `BarrierCommands` records
+#[derive(Clone, Copy)]
+enum TransferHalf {
+    Release,
+    Acquire,
+}
+
+/// Primary command buffer holding only queue family ownership transfer barriers, which
+/// `AutoCommandBufferBuilder` has no methods for
+///
+/// Submitted after the copies on the transfer queue (release) and before any use on the
+/// graphics queue (acquire).  The barriers don't change layouts or contents, so there is
+/// nothing for vulkano to lock or check.
+struct BarrierCommands {
+    device: Arc<Device>,
+    inner: UnsafeCommandBuffer<StandardCommandPoolAlloc>,
+    _resources: Vec<Transferred>,
+}
+
+unsafe impl DeviceOwned for BarrierCommands {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+unsafe impl CommandBuffer for BarrierCommands {
+    type PoolAlloc = StandardCommandPoolAlloc;
+
+    fn inner(&self) -> &UnsafeCommandBuffer<StandardCommandPoolAlloc> {
+        &self.inner
+    }
+
+    fn lock_submit(&self, _future: &dyn GpuFuture, _queue: &Queue) -> Result<(), CommandBufferExecError> {
+        Ok(())
+    }
+
+    unsafe fn unlock(&self) {
+    }
+
+    fn check_buffer_access(
+        &self,
+        _buffer: &dyn BufferAccess,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+
+    fn check_image_access(
+        &self,
+        _image: &dyn ImageAccess,
+        _layout: ImageLayout,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+}
+
+/// Stages data through host visible memory into device local buffers and images
+///
+/// Uploads are recorded into a single command buffer per queue and only submitted by `flush`,
+/// so many uploads share one submission.  When a transfer queue from another family is
+/// available the copies run on it into exclusively owned resources, and `flush` releases them
+/// from the transfer family and acquires them on the graphics family.
+///
+/// Mipmap generation requires blits, which are only available on graphics queues, so those
+/// uploads are recorded into a separate graphics queue command buffer that runs after the
+/// transfer submission.
+///
+/// Resources returned are uninitialized until the future returned by `flush` has been joined
+/// into the frame that first uses them.
+pub struct UploadManager {
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    transfer_queue: Option<Arc<Queue>>,
+    transfer_commands: Option<AutoCommandBufferBuilder>,
+    graphics_commands: Option<AutoCommandBufferBuilder>,
+    /// Written by `transfer_commands` and still owned by the transfer family
+    transfers: Vec<Transferred>,
+}
+
+impl UploadManager {
+
+    pub fn new(
+        device: Arc<Device>,
+        graphics_queue: Arc<Queue>,
+        transfer_queue: Option<Arc<Queue>>,
+    ) -> UploadManager {
+
+        // A transfer queue in the graphics family gains nothing over the graphics queue itself
+        let transfer_queue = transfer_queue.filter(|queue| queue.family().id() != graphics_queue.family().id());
+
+        UploadManager {
+            device,
+            graphics_queue,
+            transfer_queue,
+            transfer_commands: None,
+            graphics_commands: None,
+            transfers: Vec::new(),
+        }
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    fn upload_queue(&self) -> &Arc<Queue> {
+        self.transfer_queue.as_ref().unwrap_or(&self.graphics_queue)
+    }
+
+    /// Resources are exclusive to the family that writes them first, ownership of those written
+    /// on the transfer queue moves to the graphics family in `flush`
+    fn upload_families(&self) -> Vec<QueueFamily> {
+        vec![self.upload_queue().family()]
+    }
+
+    fn transfer_commands(&mut self) -> Result<&mut AutoCommandBufferBuilder, Box<dyn Error>> {
+
+        if self.transfer_commands.is_none() {
+            self.transfer_commands = Some(AutoCommandBufferBuilder::primary_one_time_submit(
+                self.device.clone(),
+                self.upload_queue().family(),
+            )?);
+        }
+
+        Ok(self.transfer_commands.as_mut().expect("Transfer commands were just created"))
+    }
+
+    fn graphics_commands(&mut self) -> Result<&mut AutoCommandBufferBuilder, Box<dyn Error>> {
+
+        if self.graphics_commands.is_none() {
+            self.graphics_commands = Some(AutoCommandBufferBuilder::primary_one_time_submit(
+                self.device.clone(),
+                self.graphics_queue.family(),
+            )?);
+        }
+
+        Ok(self.graphics_commands.as_mut().expect("Graphics commands were just created"))
+    }
+
+    fn create_staging_buffer(&self, data: &[u8]) -> Result<Arc<CpuAccessibleBuffer<[u8]>>, Box<dyn Error>> {
+        Ok(CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            data.iter().cloned(),
+        )?)
+    }
+
+    pub fn upload_buffer<T, I>(
+        &mut self,
+        data: I,
+        usage: BufferUsage,
+    ) -> Result<Arc<ImmutableBuffer<[T]>>, Box<dyn Error>>
+    where
+        T: Send + Sync + 'static,
+        I: ExactSizeIterator<Item = T>,
+    {
+        let len = data.len();
+
+        if len == 0 {
+            return Err("Cannot upload an empty buffer".into());
+        }
+
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            data,
+        )?;
+
+        let usage = BufferUsage {
+            transfer_destination: true,
+            .. usage
+        };
+
+        // Safe since the buffer is filled by the copy below before the returned future completes
+        let (buffer, initialization) = unsafe {
+            ImmutableBuffer::<[T]>::raw(
+                self.device.clone(),
+                len * std::mem::size_of::<T>(),
+                usage,
+                self.upload_families(),
+            )?
+        };
+
+        self.transfer_commands()?.copy_buffer(staging, initialization)?;
+
+        if self.transfer_queue.is_some() {
+            self.transfers.push(Transferred::Buffer(buffer.clone()));
+        }
+
+        Ok(buffer)
+    }
+
+    pub fn upload_vertex_buffer<T, I>(&mut self, data: I) -> Result<Arc<ImmutableBuffer<[T]>>, Box<dyn Error>>
+    where
+        T: Send + Sync + 'static,
+        I: ExactSizeIterator<Item = T>,
+    {
+        self.upload_buffer(data, BufferUsage::vertex_buffer())
+    }
+
+    pub fn upload_index_buffer<T, I>(&mut self, data: I) -> Result<Arc<ImmutableBuffer<[T]>>, Box<dyn Error>>
+    where
+        T: Send + Sync + 'static,
+        I: ExactSizeIterator<Item = T>,
+    {
+        self.upload_buffer(data, BufferUsage::index_buffer())
+    }
+
+    /// Uploads tightly packed texel data for a single mip level image
+    pub fn upload_image(
+        &mut self,
+        data: &[u8],
+        dimensions: Dimensions,
+        format: Format,
+    ) -> Result<Arc<ImmutableImage<Format>>, Box<dyn Error>> {
+        self.upload_image_levels(&[data], dimensions, format)
+    }
+
+    /// Uploads an image whose mip levels are already available (level 0 first)
+    pub fn upload_image_levels(
+        &mut self,
+        levels: &[&[u8]],
+        dimensions: Dimensions,
+        format: Format,
+    ) -> Result<Arc<ImmutableImage<Format>>, Box<dyn Error>> {
+
+        if levels.is_empty() {
+            return Err("Cannot upload an image without any mip levels".into());
+        }
+
+        let usage = ImageUsage {
+            transfer_destination: true,
+            sampled: true,
+            .. ImageUsage::none()
+        };
+
+        let (image, initialization) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            dimensions,
+            format,
+            MipmapsCount::Specific(levels.len() as u32),
+            usage,
+            ImageLayout::ShaderReadOnlyOptimal,
+            self.upload_families(),
+        )?;
+
+        let initialization = Arc::new(initialization);
+
+        for (level, data) in levels.iter().enumerate() {
+            let staging = self.create_staging_buffer(data)?;
+            let level_dimensions = dimensions
+                .to_image_dimensions()
+                .mipmap_dimensions(level as u32)
+                .ok_or("Image has more mip levels than its dimensions allow")?;
+
+            self.transfer_commands()?.copy_buffer_to_image_dimensions(
+                staging,
+                initialization.clone(),
+                [0, 0, 0],
+                level_dimensions.width_height_depth(),
+                0,
+                dimensions.array_layers_with_cube(),
+                level as u32,
+            )?;
+        }
+
+        if self.transfer_queue.is_some() {
+            self.transfers.push(Transferred::Image(image.clone()));
+        }
+
+        Ok(image)
+    }
+
+    /// Uploads the base level of a 2D image and fills the full mip chain with linear blits
+    ///
+    /// The caller is responsible for checking the format supports linear filtering
+    pub fn upload_image_with_mipmaps(
+        &mut self,
+        data: &[u8],
+        dimensions: Dimensions,
+        format: Format,
+    ) -> Result<Arc<ImmutableImage<Format>>, Box<dyn Error>> {
+
+        let usage = ImageUsage {
+            transfer_source: true,
+            transfer_destination: true,
+            sampled: true,
+            .. ImageUsage::none()
+        };
+
+        let (image, initialization) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            dimensions,
+            format,
+            MipmapsCount::Log2,
+            usage,
+            ImageLayout::ShaderReadOnlyOptimal,
+            vec![self.graphics_queue.family()],
+        )?;
+
+        let initialization = Arc::new(initialization);
+        let staging = self.create_staging_buffer(data)?;
+        let layers = dimensions.array_layers_with_cube();
+        let mip_levels = image.mipmap_levels();
+
+        let commands = self.graphics_commands()?;
+
+        commands.copy_buffer_to_image_dimensions(
+            staging,
+            initialization.clone(),
+            [0, 0, 0],
+            dimensions.width_height_depth(),
+            0,
+            layers,
+            0,
+        )?;
+
+        let image_dimensions = dimensions.to_image_dimensions();
+
+        for level in 1..mip_levels {
+            let source = image_dimensions.mipmap_dimensions(level - 1).ok_or("Invalid mip level")?.width_height_depth();
+            let destination = image_dimensions.mipmap_dimensions(level).ok_or("Invalid mip level")?.width_height_depth();
+
+            commands.blit_image(
+                initialization.clone(),
+                [0, 0, 0],
+                [source[0] as i32, source[1] as i32, source[2] as i32],
+                0,
+                level - 1,
+                initialization.clone(),
+                [0, 0, 0],
+                [destination[0] as i32, destination[1] as i32, destination[2] as i32],
+                0,
+                level,
+                layers,
+                Filter::Linear,
+            )?;
+        }
+
+        Ok(image)
+    }
+
+    /// True if uploads have been recorded since the last flush
+    pub fn has_pending(&self) -> bool {
+        self.transfer_commands.is_some() || self.graphics_commands.is_some()
+    }
+
+    /// Records one half of the ownership transfer of every resource in `transfers`
+    ///
+    /// Both halves use identical barriers.  Images are already in their final layout once the
+    /// copies end, so the layout is left unchanged.
+    fn ownership_transfer(&self, transfers: Vec<Transferred>, half: TransferHalf) -> Result<BarrierCommands, Box<dyn Error>> {
+
+        let transfer_family = self.upload_queue().family();
+        let graphics_family = self.graphics_queue.family();
+        let queue_transfer = Some((transfer_family.id(), graphics_family.id()));
+
+        let (family, source_stage, source_access, destination_stage, destination_access) = match half {
+            TransferHalf::Release => (
+                transfer_family,
+                PipelineStages { transfer: true, ..PipelineStages::none() },
+                AccessFlagBits { transfer_write: true, ..AccessFlagBits::none() },
+                PipelineStages { bottom_of_pipe: true, ..PipelineStages::none() },
+                AccessFlagBits::none(),
+            ),
+            TransferHalf::Acquire => (
+                graphics_family,
+                PipelineStages { top_of_pipe: true, ..PipelineStages::none() },
+                AccessFlagBits::none(),
+                PipelineStages { all_commands: true, ..PipelineStages::none() },
+                AccessFlagBits { memory_read: true, ..AccessFlagBits::none() },
+            ),
+        };
+
+        let command_pool = Device::standard_command_pool(&self.device, family);
+
+        let inner = unsafe {
+            let mut barrier = UnsafeCommandBufferBuilderPipelineBarrier::new();
+
+            for transferred in &transfers {
+                match transferred {
+                    Transferred::Buffer(buffer) => barrier.add_buffer_memory_barrier(
+                        buffer.as_ref(),
+                        source_stage,
+                        source_access,
+                        destination_stage,
+                        destination_access,
+                        false,
+                        queue_transfer,
+                        0,
+                        buffer.size(),
+                    ),
+                    Transferred::Image(image) => {
+                        let inner = image.inner();
+                        barrier.add_image_memory_barrier(
+                            image.as_ref(),
+                            0 .. inner.num_mipmap_levels as u32,
+                            0 .. inner.num_layers as u32,
+                            source_stage,
+                            source_access,
+                            destination_stage,
+                            destination_access,
+                            false,
+                            queue_transfer,
+                            ImageLayout::ShaderReadOnlyOptimal,
+                            ImageLayout::ShaderReadOnlyOptimal,
+                        );
+                    }
+                }
+            }
+
+            let mut commands = UnsafeCommandBufferBuilder::new(&command_pool, Kind::primary(), Flags::OneTimeSubmit)?;
+            commands.pipeline_barrier(&barrier);
+            commands.build()?
+        };
+
+        Ok(BarrierCommands { device: self.device.clone(), inner, _resources: transfers })
+    }
+
+    /// Submits all recorded uploads, at most one submission per queue
+    ///
+    /// The returned future must be joined before any of the uploaded resources are used
+    pub fn flush(&mut self) -> Result<Box<dyn GpuFuture>, Box<dyn Error>> {
+
+        let mut future = sync::now(self.device.clone()).boxed();
+
+        if let Some(builder) = self.transfer_commands.take() {
+            future = future
+                .then_execute(self.upload_queue().clone(), builder.build()?)?
+                .boxed();
+
+            let transfers = std::mem::take(&mut self.transfers);
+
+            if !transfers.is_empty() {
+                let release = self.ownership_transfer(transfers.clone(), TransferHalf::Release)?;
+                future = future.then_execute(self.upload_queue().clone(), release)?.boxed();
+            }
+
+            future = future.then_signal_semaphore_and_flush()?.boxed();
+
+            if !transfers.is_empty() {
+                let acquire = self.ownership_transfer(transfers, TransferHalf::Acquire)?;
+                future = future.then_execute(self.graphics_queue.clone(), acquire)?.boxed();
+            }
+        }
+
+        if let Some(builder) = self.graphics_commands.take() {
+            future = future
+                .then_execute(self.graphics_queue.clone(), builder.build()?)?
+                .boxed();
+        }
+
+        Ok(future.then_signal_fence_and_flush()?.boxed())
+    }
+}
@@ -9,19 +9,30 @@ use vulkano::{
     command_buffer::DynamicState,
     device::{
         Device,
+        DeviceOwned,
         Queue,
     },
+    format::{
+        ClearValue,
+        Format,
+    },
     framebuffer::{
         Framebuffer,
         FramebufferAbstract,
-        FramebufferCreationError,
         RenderPassAbstract,
+        RenderPassCreationError,
+        RenderPassDesc,
     },
     image::{
+        AttachmentImage,
         ImageUsage,
         SwapchainImage,
     },
-    instance::Instance,
+    instance::{
+        Instance,
+        PhysicalDevice,
+        QueueFamily,
+    },
     pipeline::viewport::Viewport,
     swapchain,
     swapchain::{
@@ -30,6 +41,7 @@ use vulkano::{
         FullscreenExclusive,
         PresentMode,
         Surface,
+        SupportedPresentModes,
         SurfaceTransform,
         Swapchain,
         SwapchainAcquireFuture,
@@ -78,10 +90,37 @@ impl VulkanApp {
     }
 }
 
-pub struct DefaultInstanceFactory{}
+/// Surface for an additional window, on the instance `device` was created from
+///
+/// Fails if `present_queue` can't present to it (e.g. the window is on another GPU's display).
+pub fn create_surface(
+    device: &Arc<Device>,
+    present_queue: &Arc<Queue>,
+    window: Window,
+) -> Result<Arc<Surface<Window>>, Box<dyn Error>> {
+    let surface = vulkano_win::create_vk_surface(window, device.instance().clone())?;
+
+    if !surface.is_supported(present_queue.family())? {
+        return Err("Queue family is unable to present to the window's surface".into());
+    }
+
+    Ok(surface)
+}
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+pub struct DefaultInstanceFactory{
+    validation: bool,
+}
 
 impl DefaultInstanceFactory { pub fn new() -> Box<dyn InstanceFactory> {
-        Box::new(DefaultInstanceFactory{})
+        DefaultInstanceFactory::with_validation(false)
+    }
+
+    /// Enables the Khronos validation layer when it is installed, messages go to the layer's
+    /// default output (stdout)
+    pub fn with_validation(validation: bool) -> Box<dyn InstanceFactory> {
+        Box::new(DefaultInstanceFactory{ validation })
     }
 }
 
@@ -93,23 +132,129 @@ impl InstanceFactory for DefaultInstanceFactory {
         debug!("Available Count: {}", available.count());
 
         let available = vulkano::instance::layers_list().unwrap();
-        available.for_each(|layer| info!("available_extensions: {}", layer.name()));
+        let mut validation_available = false;
+        available.for_each(|layer| {
+            info!("available_extensions: {}", layer.name());
+            validation_available |= layer.name() == VALIDATION_LAYER;
+        });
+
+        let layers = match (self.validation, validation_available) {
+            (true, true) => vec![VALIDATION_LAYER],
+            (true, false) => {
+                warn!("Validation requested but {} is not installed", VALIDATION_LAYER);
+                vec![]
+            },
+            (false, _) => vec![],
+        };
 
         let extensions = vulkano_win::required_extensions();
 
         debug!("required_extensions: {:?}", extensions);
 
-        match Instance::new(None, &extensions, None) {
+        match Instance::new(None, &extensions, layers) {
             Ok(instance) => instance,
             Err(err) => panic!("failed to create Vulkan Instance {:?}", err),
         }
     }
 }
 
-pub struct DefaultSwapchainFactory{}
+/// Which physical device `--gpu` asked for
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuSelection {
+    /// Index in `PhysicalDevice::enumerate` order
+    Index(usize),
+    /// Case insensitive substring of the device name
+    Name(String),
+}
+
+impl GpuSelection {
+
+    pub fn parse(value: &str) -> GpuSelection {
+        match value.parse() {
+            Ok(index) => GpuSelection::Index(index),
+            Err(_) => GpuSelection::Name(value.to_string()),
+        }
+    }
+
+    pub(crate) fn matches(&self, physical_device: &PhysicalDevice) -> bool {
+        match self {
+            GpuSelection::Index(index) => physical_device.index() == *index,
+            GpuSelection::Name(name) => physical_device.name().to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+/// First device (matching `gpu`, if given) with a graphics queue family that can present to
+/// `surface`, shared by the device factories
+pub fn select_physical_device<'a>(
+    instance: &'a Arc<Instance>,
+    surface: &Arc<Surface<Window>>,
+    gpu: Option<&GpuSelection>,
+) -> Result<(PhysicalDevice<'a>, QueueFamily<'a>), Box<dyn Error>> {
+
+    for physical_device in PhysicalDevice::enumerate(instance) {
+        info!("GPU {}: {} ({:?})", physical_device.index(), physical_device.name(), physical_device.ty());
+    }
+
+    find_physical_device(instance, gpu, |queue_family| {
+        queue_family.supports_graphics()
+        && surface.is_supported(*queue_family).unwrap_or(false)
+    })
+}
+
+/// First device (matching `gpu`, if given) with a queue family accepted by `suitable`
+pub fn find_physical_device<'a, F>(
+    instance: &'a Arc<Instance>,
+    gpu: Option<&GpuSelection>,
+    suitable: F,
+) -> Result<(PhysicalDevice<'a>, QueueFamily<'a>), Box<dyn Error>>
+where
+    F: Fn(&QueueFamily) -> bool,
+{
+    PhysicalDevice::enumerate(instance)
+        .filter(|physical_device| gpu.map_or(true, |gpu| gpu.matches(physical_device)))
+        .find_map(|physical_device| {
+            physical_device
+                .queue_families()
+                .find(|queue_family| suitable(queue_family))
+                .map(|queue_family| (physical_device, queue_family))
+        })
+        .ok_or_else(|| match gpu {
+            Some(gpu) => format!("Unable to find compatible device matching {:?}", gpu).into(),
+            None => "Unable to find compatible device".into(),
+        })
+}
+
+pub struct DefaultSwapchainFactory{
+    present_mode: PresentMode,
+}
 
 impl DefaultSwapchainFactory { pub fn new() -> Box<dyn SwapchainFactory> {
-        Box::new(DefaultSwapchainFactory{})
+        DefaultSwapchainFactory::with_present_mode(PresentMode::Fifo)
+    }
+
+    /// Falls back to the other non-blocking mode and then to Fifo (always supported) when
+    /// `present_mode` is unavailable
+    pub fn with_present_mode(present_mode: PresentMode) -> Box<dyn SwapchainFactory> {
+        Box::new(DefaultSwapchainFactory{ present_mode })
+    }
+
+    fn choose_present_mode(&self, supported: &SupportedPresentModes) -> PresentMode {
+        let fallback = match self.present_mode {
+            PresentMode::Immediate => Some(PresentMode::Mailbox),
+            PresentMode::Mailbox => Some(PresentMode::Immediate),
+            _ => None,
+        };
+
+        let present_mode = std::iter::once(self.present_mode)
+            .chain(fallback)
+            .find(|&mode| supported.supports(mode))
+            .unwrap_or(PresentMode::Fifo);
+
+        if present_mode != self.present_mode {
+            warn!("Present mode {:?} not supported, using {:?}", self.present_mode, present_mode);
+        }
+        present_mode
     }
 }
 
@@ -144,11 +289,16 @@ impl SwapchainFactory for DefaultSwapchainFactory {
             format,
             dimensions,
             1,
-            ImageUsage::color_attachment(),
+            // Transfer source allows screenshots
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: caps.supported_usage_flags.transfer_source,
+                .. ImageUsage::none()
+            },
             queue,
             SurfaceTransform::Identity,
             alpha,
-            PresentMode::Fifo,
+            self.choose_present_mode(&caps.present_modes),
             FullscreenExclusive::Default,
             true,
             ColorSpace::SrgbNonLinear,
@@ -156,19 +306,92 @@ impl SwapchainFactory for DefaultSwapchainFactory {
     }
 }
 
+/// Single pass render pass drawing to the swapchain image, laid out the way `RenderState`
+/// builds framebuffers: with MSAA the multisampled first attachment is resolved into the
+/// swapchain image
+pub fn create_color_render_pass(
+    device: &Arc<Device>,
+    format: Format,
+    samples: u32,
+) -> Result<Arc<dyn RenderPassAbstract + Send + Sync>, RenderPassCreationError> {
+
+    if samples > 1 {
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+
+                multisampled: {
+                    load: Clear,
+                    store: DontCare,
+                    format: format,
+                    samples: samples,
+                },
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+
+            pass: {
+                color: [multisampled],
+                depth_stencil: {},
+                resolve: [color],
+            }
+        )?;
+
+        return Ok(Arc::new(render_pass));
+    }
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+
+            color: {
+                load: Clear,
+                store: Store,
+                format: format,
+                samples: 1,
+            }
+        },
+
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        }
+    )?;
+
+    Ok(Arc::new(render_pass))
+}
+
 pub struct AcquiredImage {
     pub image_num: usize,
     pub acquire_future: SwapchainAcquireFuture<Window>,
-    pub framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    /// `None` when the render state has no render pass
+    framebuffer: Option<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    /// For passes that render to the swapchain with their own framebuffers (e.g. overlays)
+    pub image: Arc<SwapchainImage<Window>>,
     pub suboptimal: bool
 }
 
+impl AcquiredImage {
+
+    /// The render state's framebuffer around this image
+    pub fn framebuffer(&self) -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, Box<dyn Error>> {
+        Ok(self.framebuffer.clone().ok_or("Render state was built without a render pass")?)
+    }
+}
+
 pub struct RenderState {
     pub swapchain: Arc<Swapchain<Window>>,
+    /// Empty without a render pass
     framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    images: Vec<Arc<SwapchainImage<Window>>>,
     surface: Arc<Surface<Window>>,
     pub dynamic_state: DynamicState,
-    pub render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    /// `None` when something else (e.g. a `RenderGraph`) builds the framebuffers
+    pub render_pass: Option<Arc<dyn RenderPassAbstract + Send + Sync>>,
 }
 
 impl RenderState {
@@ -183,6 +406,51 @@ impl RenderState {
 
         let (swapchain, swapchain_images) = swapchain_factory.create_swapchain(device, &swapchain_queue, surface.clone())?;
 
+        RenderState::from_swapchain(swapchain, swapchain_images, surface, render_pass)
+    }
+
+    /// Surface, swapchain and framebuffers for a secondary window (see `create_surface`)
+    pub fn for_window(
+        device: Arc<Device>,
+        swapchain_factory: Box<dyn SwapchainFactory>,
+        present_queue: &Arc<Queue>,
+        window: Window,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<RenderState, Box<dyn Error>> {
+
+        let surface = create_surface(&device, present_queue, window)?;
+
+        RenderState::new(device, swapchain_factory, present_queue, surface, render_pass)
+    }
+
+    /// Builds framebuffers for `render_pass` around an existing swapchain, e.g. one handed over
+    /// by a previous sample
+    pub fn from_swapchain(
+        swapchain: Arc<Swapchain<Window>>,
+        swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+        surface: Arc<Surface<Window>>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<RenderState, Box<dyn Error>> {
+        RenderState::from_parts(swapchain, swapchain_images, surface, Some(render_pass))
+    }
+
+    /// Like `from_swapchain` for renderers that build their own framebuffers around the
+    /// swapchain images, such as `RenderGraph`
+    pub fn without_render_pass(
+        swapchain: Arc<Swapchain<Window>>,
+        swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+        surface: Arc<Surface<Window>>,
+    ) -> Result<RenderState, Box<dyn Error>> {
+        RenderState::from_parts(swapchain, swapchain_images, surface, None)
+    }
+
+    fn from_parts(
+        swapchain: Arc<Swapchain<Window>>,
+        swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+        surface: Arc<Surface<Window>>,
+        render_pass: Option<Arc<dyn RenderPassAbstract + Send + Sync>>,
+    ) -> Result<RenderState, Box<dyn Error>> {
+
         let mut dynamic_state = DynamicState {
             line_width: None,
             viewports: None,
@@ -193,7 +461,7 @@ impl RenderState {
         };
 
         let framebuffers = RenderState::create_frame_buffers(
-            render_pass.clone(),
+            render_pass.as_ref(),
             &mut dynamic_state,
             &swapchain_images,
         )?;
@@ -201,12 +469,35 @@ impl RenderState {
         Ok(RenderState {
             swapchain,
             framebuffers,
+            images: swapchain_images,
             surface,
             dynamic_state,
             render_pass,
         })
     }
 
+    pub fn images(&self) -> &[Arc<SwapchainImage<Window>>] {
+        &self.images
+    }
+
+    pub fn surface(&self) -> &Arc<Surface<Window>> {
+        &self.surface
+    }
+
+    /// Clear values for a render pass from `create_color_render_pass`
+    pub fn clear_values(&self, color: [f32; 4]) -> Vec<ClearValue> {
+        let samples = self.render_pass
+            .as_ref()
+            .and_then(|render_pass| render_pass.attachment_desc(0))
+            .map_or(1, |attachment| attachment.samples);
+
+        if samples > 1 {
+            vec![color.into(), ClearValue::None]
+        } else {
+            vec![color.into()]
+        }
+    }
+
     pub fn recreate(
         &mut self,
     ) -> Result<(), Box<dyn Error>>{
@@ -214,13 +505,14 @@ impl RenderState {
             RenderState::recreate_swapchain(self.swapchain.clone(), self.surface.clone())?;
 
         let framebufers = RenderState::create_frame_buffers(
-            self.render_pass.clone(),
+            self.render_pass.as_ref(),
             &mut self.dynamic_state,
             &swapchain_images,
         )?;
 
         self.swapchain = swapchain;
         self.framebuffers = framebufers;
+        self.images = swapchain_images;
 
         Ok(())
     }
@@ -235,11 +527,13 @@ impl RenderState {
         swapchain.recreate_with_dimensions(dimensions)
     }
 
+    /// When the render pass' first attachment is multisampled a transient image is created for
+    /// it and the swapchain image becomes the second (resolve) attachment
     fn create_frame_buffers(
-        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        render_pass: Option<&Arc<dyn RenderPassAbstract + Send + Sync>>,
         dynamic_state: &mut DynamicState,
-        swapchain_images: &Vec<Arc<SwapchainImage<Window>>>,
-    ) -> Result<Vec<Arc<dyn FramebufferAbstract + Send + Sync>>, FramebufferCreationError> {
+        swapchain_images: &[Arc<SwapchainImage<Window>>],
+    ) -> Result<Vec<Arc<dyn FramebufferAbstract + Send + Sync>>, Box<dyn Error>> {
 
         let dimensions = swapchain_images[0].dimensions();
 
@@ -251,28 +545,37 @@ impl RenderState {
 
         dynamic_state.viewports = Some(vec![viewport]);
 
+        let render_pass = match render_pass {
+            Some(render_pass) => render_pass,
+            None => return Ok(Vec::new()),
+        };
+
+        let samples = render_pass.attachment_desc(0).map_or(1, |attachment| attachment.samples);
+
         type ArcFramebuffer = Arc<dyn FramebufferAbstract + Send + Sync>;
-        type FramebufferResult = Result<ArcFramebuffer, FramebufferCreationError>;
 
-        let framebuffer_results = swapchain_images
+        swapchain_images
             .iter()
-            .map(|image| -> FramebufferResult {
-                    let framebuffer = Framebuffer::start(render_pass.clone())
-                    .add(image.clone())?
-                    .build()?;
-
-                    Ok(Arc::new(framebuffer) as ArcFramebuffer)
-            });
-
-        // @TODO - this should be some kind of utility function since it is likely
-        // to have genric use case (ResultFlatMap??)
-        let first_error = framebuffer_results.clone().find(|result| result.is_err());
-
-        match first_error {
-            Some(Err(first_error)) => Err(first_error),
-            None => Ok(framebuffer_results.map(|result| result.expect("This should never happen")).collect::<Vec<_>>()),
-            _ => unreachable!("Unexpected error occured"),
-        }
+            .map(|image| -> Result<ArcFramebuffer, Box<dyn Error>> {
+                if samples > 1 {
+                    let multisampled = AttachmentImage::transient_multisampled(
+                        render_pass.device().clone(),
+                        dimensions,
+                        samples,
+                        image.swapchain().format(),
+                    )?;
+
+                    Ok(Arc::new(Framebuffer::start(render_pass.clone())
+                        .add(multisampled)?
+                        .add(image.clone())?
+                        .build()?))
+                } else {
+                    Ok(Arc::new(Framebuffer::start(render_pass.clone())
+                        .add(image.clone())?
+                        .build()?))
+                }
+            })
+            .collect()
     }
 
     pub fn acquire_next_image(&mut self)
@@ -281,9 +584,10 @@ impl RenderState {
         let (image_num, suboptimal, acquire_future) =
             swapchain::acquire_next_image(self.swapchain.clone(), None)?;
 
-        let framebuffer = self.framebuffers[image_num].clone();
+        let framebuffer = self.framebuffers.get(image_num).cloned();
+        let image = self.images[image_num].clone();
 
-        Ok(AcquiredImage{image_num, acquire_future, framebuffer, suboptimal})
+        Ok(AcquiredImage{image_num, acquire_future, framebuffer, image, suboptimal})
     }
 }
 
@@ -0,0 +1,92 @@
+use crate::vulkan_app::{
+    select_physical_device,
+    DeviceFactory,
+    GpuSelection,
+};
+
+use std::{
+    sync::Arc,
+    error::Error,
+};
+
+use winit::window::Window;
+
+use vulkano::{
+    device::{
+        Device,
+        Features,
+        Queue,
+    },
+    instance::Instance,
+    swapchain::Surface,
+};
+
+/// Creates a graphics queue (index 0) and, when the device exposes a dedicated transfer
+/// family, a transfer queue (index 1) for uploads
+pub struct GraphicsAndTransferQueuesDeviceFactory{
+    gpu: Option<GpuSelection>,
+    /// Enabled where the selected device supports them
+    optional_features: Features,
+}
+
+impl GraphicsAndTransferQueuesDeviceFactory {
+
+    pub fn new() -> Box<dyn DeviceFactory> {
+        GraphicsAndTransferQueuesDeviceFactory::with_gpu(None)
+    }
+
+    /// `None` picks the first compatible device
+    pub fn with_gpu(gpu: Option<GpuSelection>) -> Box<dyn DeviceFactory> {
+        GraphicsAndTransferQueuesDeviceFactory::with_optional_features(gpu, Features::none())
+    }
+
+    /// Enables whichever of `optional_features` the device supports, check
+    /// `Device::enabled_features` for what was actually enabled
+    pub fn with_optional_features(gpu: Option<GpuSelection>, optional_features: Features) -> Box<dyn DeviceFactory> {
+        Box::new(GraphicsAndTransferQueuesDeviceFactory{ gpu, optional_features })
+    }
+}
+
+impl DeviceFactory for GraphicsAndTransferQueuesDeviceFactory {
+
+    fn create_device(
+        &self,
+        instance: Arc<Instance>,
+        surface: Arc<Surface<Window>>,
+    ) -> Result<(Arc<Device>, Vec<Arc<Queue>>), Box<dyn Error>> {
+
+        let (physical_device, compatible_graphics_queue_family) =
+            select_physical_device(&instance, &surface, self.gpu.as_ref())?;
+
+        // Transfer-only families usually map to the DMA engines on discrete GPUs
+        let transfer_queue_family = physical_device.queue_families().find(
+            |queue_family| -> bool {
+                queue_family.explicitly_supports_transfers()
+                && !queue_family.supports_graphics()
+                && !queue_family.supports_compute()
+        });
+
+        let device_extensions = vulkano::device::DeviceExtensions {
+            khr_swapchain: true,
+            .. vulkano::device::DeviceExtensions::none()
+        };
+
+        let queue_families = match transfer_queue_family {
+            Some(transfer_queue_family) => vec![(compatible_graphics_queue_family, 0.5), (transfer_queue_family, 0.5)],
+            None => vec![(compatible_graphics_queue_family, 0.5)],
+        };
+
+        let features = physical_device.supported_features().intersection(&self.optional_features);
+
+        let (device, queues) = Device::new(
+            physical_device,
+            &features,
+            &device_extensions,
+            queue_families.into_iter(),
+        )?;
+
+        let queues = queues.collect::<Vec<_>>();
+
+        Ok((device, queues))
+    }
+}
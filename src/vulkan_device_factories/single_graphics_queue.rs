@@ -1,4 +1,8 @@
-use crate::vulkan_app::DeviceFactory;
+use crate::vulkan_app::{
+    select_physical_device,
+    DeviceFactory,
+    GpuSelection,
+};
 
 use std::{
     sync::Arc,
@@ -13,20 +17,31 @@ use vulkano::{
         Features,
         Queue,
     },
-    instance::{
-        Instance,
-        PhysicalDevice,
-        QueueFamily
-    },
+    instance::Instance,
     swapchain::Surface,
 };
 
-pub struct SingleGraphicsQueueDeviceFactory{}
+pub struct SingleGraphicsQueueDeviceFactory{
+    gpu: Option<GpuSelection>,
+    /// Enabled where the selected device supports them
+    optional_features: Features,
+}
 
 impl SingleGraphicsQueueDeviceFactory {
 
     pub fn new() -> Box<dyn DeviceFactory> {
-        Box::new(SingleGraphicsQueueDeviceFactory{})
+        SingleGraphicsQueueDeviceFactory::with_gpu(None)
+    }
+
+    /// `None` picks the first compatible device
+    pub fn with_gpu(gpu: Option<GpuSelection>) -> Box<dyn DeviceFactory> {
+        SingleGraphicsQueueDeviceFactory::with_optional_features(gpu, Features::none())
+    }
+
+    /// Enables whichever of `optional_features` the device supports, check
+    /// `Device::enabled_features` for what was actually enabled
+    pub fn with_optional_features(gpu: Option<GpuSelection>, optional_features: Features) -> Box<dyn DeviceFactory> {
+        Box::new(SingleGraphicsQueueDeviceFactory{ gpu, optional_features })
     }
 }
 
@@ -38,31 +53,19 @@ impl DeviceFactory for SingleGraphicsQueueDeviceFactory {
         surface: Arc<Surface<Window>>,
     ) -> Result<(Arc<Device>, Vec<Arc<Queue>>), Box<dyn Error>> {
 
-        let (physical_device, compatible_graphics_queue_family) = {
-            PhysicalDevice::enumerate(&instance).find_map(
-                |physical_device| -> Option<(PhysicalDevice, QueueFamily)> {
-
-                    let compatible_graphics_queue_family = physical_device.queue_families().find(
-                        |queue_family| -> bool {
-                            queue_family.supports_graphics()
-                            && surface.is_supported(*queue_family).unwrap_or(false)
-                    });
-
-                    match compatible_graphics_queue_family {
-                        Some(queue_family) => Some((physical_device, queue_family)),
-                        None => None,
-                    }
-            }).ok_or("Unable to find compatible device")
-        }?;
+        let (physical_device, compatible_graphics_queue_family) =
+            select_physical_device(&instance, &surface, self.gpu.as_ref())?;
 
         let device_extensions = vulkano::device::DeviceExtensions {
             khr_swapchain: true,
             .. vulkano::device::DeviceExtensions::none()
         };
 
+        let features = physical_device.supported_features().intersection(&self.optional_features);
+
         let (device, queues) = Device::new(
             physical_device,
-            &Features::none(),
+            &features,
             &device_extensions,
             [(compatible_graphics_queue_family, 0.5)].iter().cloned()
         )?;